@@ -4,7 +4,7 @@ use std::fmt::{self, Display};
 
 use warp::filters::body::BodyDeserializeError;
 use warp::filters::cors::CorsForbidden;
-use warp::http::StatusCode;
+use warp::http::{HeaderValue, StatusCode};
 use warp::reject::Reject;
 use warp::{Rejection, Reply};
 
@@ -19,6 +19,8 @@ use argon2::Error as ArgonError;
 pub enum Error {
     ParseError(std::num::ParseIntError),
     MissingParameters,
+    InvalidParameter(String),
+    InvalidContent(String),
     DatabaseQueryError(sqlx::Error),
 
     ReqwestAPIError(ReqwestError),
@@ -28,10 +30,64 @@ pub enum Error {
     ServerError(APILayerError),
 
     WrongPassword,
+    /// Too many logins failed for the email or IP, holds the seconds
+    /// until the next attempt is allowed
+    LoginLocked(i64),
     ArgonLibraryError(ArgonError),
+    AccountNotFound,
+    AccountAlreadyExists,
+    /// An account looked up by id rather than by the email logging in
+    UnknownAccount,
 
+    /// The request carries neither an access token nor an API key
+    MissingCredentials,
     CannotDecryptToken,
+    /// The access token is on the deny-list, the account logged out
+    TokenRevoked,
+    /// The refresh token is unknown, expired, revoked or was used before
+    InvalidRefreshToken,
+    /// The password reset token is unknown, expired or was used before
+    InvalidResetToken,
+    /// The email verification token is unknown, expired or was used before
+    InvalidVerificationToken,
+    /// The login challenge is unknown, expired or was used before
+    InvalidLoginChallenge,
+    /// Neither a current TOTP code nor an unused recovery code
+    InvalidTwoFactorCode,
+    TwoFactorAlreadyEnabled,
+    TwoFactorNotEnabled,
     Unauthorized,
+    AccountSuspended,
+    EmailNotVerified,
+    /// The route needs a token from a login that passed a second factor
+    TwoFactorRequired,
+    /// The API key is unknown or was revoked
+    InvalidApiKey,
+    /// The session is limited to scopes that don't cover the route,
+    /// holds the scope the route needs
+    InsufficientScope(&'static str),
+    /// The state of a login through the identity provider is unknown,
+    /// expired, was used before or belongs to another browser
+    InvalidOidcState,
+    /// The identity provider turned the login down or its ID token did
+    /// not check out, holds the reason for the log
+    OidcLoginFailed(String),
+    /// The identity provider could not be reached or gave an answer that
+    /// makes no sense, holds the reason for the log
+    IdentityProviderError(String),
+
+    QuestionNotFound,
+    AnswerNotFound,
+    CommentNotFound,
+    ApiKeyNotFound,
+    RevisionNotFound,
+    TagNotFound,
+    PreconditionFailed,
+    QuestionLocked,
+
+    MailerError(String),
+    /// A token key in the config cannot be read
+    InvalidTokenKey(String),
 
     MigrationError(sqlx::migrate::MigrateError),
 }
@@ -55,6 +111,8 @@ impl Display for Error {
         match self {
             Error::ParseError(err) => write!(f, "Cannot parse parameter: {}", err),
             Error::MissingParameters => write!(f, "Missing parameter"),
+            Error::InvalidParameter(reason) => write!(f, "Invalid parameter: {}", reason),
+            Error::InvalidContent(reason) => write!(f, "Invalid content: {}", reason),
 
             Error::DatabaseQueryError(_) => write!(f, "Cannot update, invalid data."),
 
@@ -64,10 +122,49 @@ impl Display for Error {
             Error::ServerError(err) => write!(f, "External Server error: {}", err),
 
             Error::WrongPassword => write!(f, "Wrong password"),
+            Error::LoginLocked(seconds) => write!(
+                f,
+                "Too many failed logins, try again in {} seconds",
+                seconds
+            ),
             Error::ArgonLibraryError(_) => write!(f, "Cannot verifiy password"),
+            Error::AccountNotFound => write!(f, "Account not found"),
+            Error::AccountAlreadyExists => write!(f, "Account already exists"),
+            Error::UnknownAccount => write!(f, "Account not found"),
 
+            Error::MissingCredentials => write!(f, "Missing credentials"),
             Error::CannotDecryptToken => write!(f, "Cannot decrypt error"),
+            Error::TokenRevoked => write!(f, "Token has been revoked"),
+            Error::InvalidRefreshToken => write!(f, "Invalid refresh token"),
+            Error::InvalidResetToken => write!(f, "Invalid password reset token"),
+            Error::InvalidVerificationToken => write!(f, "Invalid email verification token"),
+            Error::InvalidLoginChallenge => write!(f, "Invalid login challenge"),
+            Error::InvalidTwoFactorCode => write!(f, "Invalid two-factor code"),
+            Error::TwoFactorAlreadyEnabled => {
+                write!(f, "Two-factor authentication is already enabled")
+            }
+            Error::TwoFactorNotEnabled => write!(f, "Two-factor authentication is not enabled"),
             Error::Unauthorized => write!(f, "No permission to change the underlying resource"),
+            Error::AccountSuspended => write!(f, "Account is suspended"),
+            Error::EmailNotVerified => write!(f, "Email address is not verified"),
+            Error::TwoFactorRequired => write!(f, "Two-factor authentication is required"),
+            Error::InvalidApiKey => write!(f, "Invalid API key"),
+            Error::InsufficientScope(scope) => write!(f, "Insufficient scope, needs {}", scope),
+            Error::InvalidOidcState => write!(f, "Invalid sign-in state"),
+            Error::OidcLoginFailed(reason) => write!(f, "Sign-in failed: {}", reason),
+            Error::IdentityProviderError(err) => write!(f, "Identity provider error: {}", err),
+
+            Error::QuestionNotFound => write!(f, "Question not found"),
+            Error::AnswerNotFound => write!(f, "Answer not found"),
+            Error::CommentNotFound => write!(f, "Comment not found"),
+            Error::ApiKeyNotFound => write!(f, "API key not found"),
+            Error::RevisionNotFound => write!(f, "Revision not found"),
+            Error::TagNotFound => write!(f, "Tag not found"),
+            Error::PreconditionFailed => write!(f, "Precondition failed"),
+            Error::QuestionLocked => write!(f, "Question is locked"),
+
+            Error::MailerError(err) => write!(f, "Cannot send email: {}", err),
+            Error::InvalidTokenKey(err) => write!(f, "Invalid token key: {}", err),
 
             Error::MigrationError(_) => write!(f, "Cannot migrate data"),
         }
@@ -78,8 +175,50 @@ impl Reject for Error {}
 
 const DUPLICATE_KEY: u32 = 23505;
 
+/// Protection space named in `WWW-Authenticate` challenges
+const REALM: &str = "rust-web-dev";
+
 #[instrument]
 pub async fn return_error(r: Rejection) -> Result<impl Reply, Rejection> {
+    let retry_after = match r.find() {
+        Some(crate::Error::LoginLocked(seconds)) => Some(*seconds),
+        _ => None,
+    };
+    let challenge = bearer_challenge(&r);
+
+    let mut response = error_reply(r).await?.into_response();
+    let headers = response.headers_mut();
+    if let Some(seconds) = retry_after {
+        headers.insert("retry-after", HeaderValue::from(seconds));
+    }
+    if let Some(challenge) = challenge.and_then(|c| HeaderValue::from_str(&c).ok()) {
+        headers.insert("www-authenticate", challenge);
+    }
+    Ok(response)
+}
+
+/// The `WWW-Authenticate` challenge of RFC 6750 telling the client how
+/// to authenticate, for rejections of the credentials a request carried
+fn bearer_challenge(r: &Rejection) -> Option<String> {
+    match r.find() {
+        Some(crate::Error::MissingCredentials) => Some(format!("Bearer realm=\"{}\"", REALM)),
+        Some(
+            crate::Error::CannotDecryptToken
+            | crate::Error::TokenRevoked
+            | crate::Error::InvalidApiKey,
+        ) => Some(format!(
+            "Bearer realm=\"{}\", error=\"invalid_token\"",
+            REALM
+        )),
+        Some(crate::Error::InsufficientScope(scope)) => Some(format!(
+            "Bearer realm=\"{}\", error=\"insufficient_scope\", scope=\"{}\"",
+            REALM, scope
+        )),
+        _ => None,
+    }
+}
+
+async fn error_reply(r: Rejection) -> Result<warp::reply::WithStatus<String>, Rejection> {
     if let Some(crate::Error::DatabaseQueryError(e)) = r.find() {
         event!(Level::ERROR, "Database query error");
 
@@ -132,12 +271,232 @@ pub async fn return_error(r: Rejection) -> Result<impl Reply, Rejection> {
             "Wrong E-Mail/Password combination".to_string(),
             StatusCode::UNAUTHORIZED,
         ))
+    } else if let Some(crate::Error::LoginLocked(seconds)) = r.find() {
+        event!(Level::WARN, "Login attempted while locked out");
+        Ok(warp::reply::with_status(
+            format!("Too many failed logins, try again in {} seconds", seconds),
+            StatusCode::TOO_MANY_REQUESTS,
+        ))
+    } else if let Some(crate::Error::AccountNotFound) = r.find() {
+        event!(Level::ERROR, "Entered unknown email");
+        Ok(warp::reply::with_status(
+            "Wrong E-Mail/Password combination".to_string(),
+            StatusCode::UNAUTHORIZED,
+        ))
+    } else if let Some(crate::Error::UnknownAccount) = r.find() {
+        event!(Level::WARN, "Requested account does not exist");
+        Ok(warp::reply::with_status(
+            "Account not found".to_string(),
+            StatusCode::NOT_FOUND,
+        ))
+    } else if let Some(crate::Error::AccountAlreadyExists) = r.find() {
+        event!(Level::ERROR, "Email already registered");
+        Ok(warp::reply::with_status(
+            "Account already exsists".to_string(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ))
+    } else if let Some(crate::Error::MissingCredentials) = r.find() {
+        event!(
+            Level::WARN,
+            "Request without credentials to a protected route"
+        );
+        Ok(warp::reply::with_status(
+            "Missing credentials".to_string(),
+            StatusCode::UNAUTHORIZED,
+        ))
+    } else if let Some(crate::Error::CannotDecryptToken) = r.find() {
+        event!(Level::WARN, "Invalid or expired access token was presented");
+        Ok(warp::reply::with_status(
+            "Invalid token".to_string(),
+            StatusCode::UNAUTHORIZED,
+        ))
+    } else if let Some(crate::Error::TokenRevoked) = r.find() {
+        event!(Level::WARN, "Revoked access token was used");
+        Ok(warp::reply::with_status(
+            "Token has been revoked".to_string(),
+            StatusCode::UNAUTHORIZED,
+        ))
+    } else if let Some(crate::Error::InvalidRefreshToken) = r.find() {
+        event!(Level::WARN, "Invalid refresh token was presented");
+        Ok(warp::reply::with_status(
+            "Invalid refresh token".to_string(),
+            StatusCode::UNAUTHORIZED,
+        ))
+    } else if let Some(crate::Error::InvalidResetToken) = r.find() {
+        event!(Level::WARN, "Invalid password reset token was presented");
+        Ok(warp::reply::with_status(
+            "Invalid password reset token".to_string(),
+            StatusCode::UNAUTHORIZED,
+        ))
+    } else if let Some(crate::Error::InvalidVerificationToken) = r.find() {
+        event!(
+            Level::WARN,
+            "Invalid email verification token was presented"
+        );
+        Ok(warp::reply::with_status(
+            "Invalid email verification token".to_string(),
+            StatusCode::UNAUTHORIZED,
+        ))
+    } else if let Some(crate::Error::InvalidApiKey) = r.find() {
+        event!(Level::WARN, "Invalid API key was presented");
+        Ok(warp::reply::with_status(
+            "Invalid API key".to_string(),
+            StatusCode::UNAUTHORIZED,
+        ))
+    } else if let Some(crate::Error::InvalidOidcState) = r.find() {
+        event!(Level::WARN, "Invalid sign-in state was presented");
+        Ok(warp::reply::with_status(
+            "Invalid sign-in state".to_string(),
+            StatusCode::UNAUTHORIZED,
+        ))
+    } else if let Some(crate::Error::OidcLoginFailed(reason)) = r.find() {
+        event!(
+            Level::WARN,
+            "Sign-in through the identity provider failed: {}",
+            reason
+        );
+        Ok(warp::reply::with_status(
+            "Sign-in failed".to_string(),
+            StatusCode::UNAUTHORIZED,
+        ))
+    } else if let Some(crate::Error::IdentityProviderError(e)) = r.find() {
+        event!(Level::ERROR, "Identity provider error: {}", e);
+        Ok(warp::reply::with_status(
+            "Identity provider error".to_string(),
+            StatusCode::BAD_GATEWAY,
+        ))
+    } else if let Some(crate::Error::InvalidLoginChallenge) = r.find() {
+        event!(Level::WARN, "Invalid login challenge was presented");
+        Ok(warp::reply::with_status(
+            "Invalid login challenge".to_string(),
+            StatusCode::UNAUTHORIZED,
+        ))
+    } else if let Some(crate::Error::InvalidTwoFactorCode) = r.find() {
+        event!(Level::WARN, "Entered wrong two-factor code");
+        Ok(warp::reply::with_status(
+            "Invalid two-factor code".to_string(),
+            StatusCode::UNAUTHORIZED,
+        ))
+    } else if let Some(crate::Error::TwoFactorAlreadyEnabled) = r.find() {
+        event!(Level::WARN, "Two-factor authentication is already enabled");
+        Ok(warp::reply::with_status(
+            "Two-factor authentication is already enabled".to_string(),
+            StatusCode::CONFLICT,
+        ))
+    } else if let Some(crate::Error::TwoFactorNotEnabled) = r.find() {
+        event!(Level::WARN, "Two-factor authentication is not enabled");
+        Ok(warp::reply::with_status(
+            "Two-factor authentication is not enabled".to_string(),
+            StatusCode::CONFLICT,
+        ))
+    } else if let Some(crate::Error::MailerError(e)) = r.find() {
+        event!(Level::ERROR, "Cannot send email: {}", e);
+        Ok(warp::reply::with_status(
+            "Internal Server Error".to_string(),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ))
+    } else if let Some(crate::Error::InvalidTokenKey(e)) = r.find() {
+        event!(Level::ERROR, "Invalid token key: {}", e);
+        Ok(warp::reply::with_status(
+            "Internal Server Error".to_string(),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ))
     } else if let Some(crate::Error::Unauthorized) = r.find() {
         event!(Level::ERROR, "Not matching account id");
         Ok(warp::reply::with_status(
             "No permission to change underlying resource".to_string(),
             StatusCode::UNAUTHORIZED,
         ))
+    } else if let Some(crate::Error::AccountSuspended) = r.find() {
+        event!(Level::WARN, "Suspended account tried to post");
+        Ok(warp::reply::with_status(
+            "Account is suspended".to_string(),
+            StatusCode::FORBIDDEN,
+        ))
+    } else if let Some(crate::Error::EmailNotVerified) = r.find() {
+        event!(
+            Level::WARN,
+            "Account with an unverified email tried to post"
+        );
+        Ok(warp::reply::with_status(
+            "Email address is not verified".to_string(),
+            StatusCode::FORBIDDEN,
+        ))
+    } else if let Some(crate::Error::TwoFactorRequired) = r.find() {
+        event!(
+            Level::WARN,
+            "Token without a second factor was used on a privileged route"
+        );
+        Ok(warp::reply::with_status(
+            "Two-factor authentication is required".to_string(),
+            StatusCode::FORBIDDEN,
+        ))
+    } else if let Some(crate::Error::InsufficientScope(_)) = r.find() {
+        event!(
+            Level::WARN,
+            "Session without the scope of the route was used"
+        );
+        Ok(warp::reply::with_status(
+            "Insufficient scope".to_string(),
+            StatusCode::FORBIDDEN,
+        ))
+    } else if let Some(crate::Error::QuestionNotFound) = r.find() {
+        event!(Level::WARN, "Requested question does not exist");
+        Ok(warp::reply::with_status(
+            "Question not found".to_string(),
+            StatusCode::NOT_FOUND,
+        ))
+    } else if let Some(crate::Error::AnswerNotFound) = r.find() {
+        event!(Level::WARN, "Requested answer does not exist");
+        Ok(warp::reply::with_status(
+            "Answer not found".to_string(),
+            StatusCode::NOT_FOUND,
+        ))
+    } else if let Some(crate::Error::CommentNotFound) = r.find() {
+        event!(Level::WARN, "Requested comment does not exist");
+        Ok(warp::reply::with_status(
+            "Comment not found".to_string(),
+            StatusCode::NOT_FOUND,
+        ))
+    } else if let Some(crate::Error::ApiKeyNotFound) = r.find() {
+        event!(Level::WARN, "Requested API key does not exist");
+        Ok(warp::reply::with_status(
+            "API key not found".to_string(),
+            StatusCode::NOT_FOUND,
+        ))
+    } else if let Some(crate::Error::RevisionNotFound) = r.find() {
+        event!(Level::WARN, "Requested revision does not exist");
+        Ok(warp::reply::with_status(
+            "Revision not found".to_string(),
+            StatusCode::NOT_FOUND,
+        ))
+    } else if let Some(crate::Error::TagNotFound) = r.find() {
+        event!(Level::WARN, "Requested tag does not exist");
+        Ok(warp::reply::with_status(
+            "Tag not found".to_string(),
+            StatusCode::NOT_FOUND,
+        ))
+    } else if let Some(crate::Error::PreconditionFailed) = r.find() {
+        event!(
+            Level::WARN,
+            "Resource changed since the client last read it"
+        );
+        Ok(warp::reply::with_status(
+            "Precondition failed".to_string(),
+            StatusCode::PRECONDITION_FAILED,
+        ))
+    } else if let Some(crate::Error::QuestionLocked) = r.find() {
+        event!(Level::WARN, "Attempt to change a locked question");
+        Ok(warp::reply::with_status(
+            "Question is locked".to_string(),
+            StatusCode::LOCKED,
+        ))
+    } else if let Some(crate::Error::InvalidContent(reason)) = r.find() {
+        event!(Level::WARN, "Rejected content: {}", reason);
+        Ok(warp::reply::with_status(
+            format!("Invalid content: {}", reason),
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ))
     } else if let Some(error) = r.find::<CorsForbidden>() {
         event!(Level::ERROR, "CORS forbidden error: {}", error);
 
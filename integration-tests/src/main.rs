@@ -1,6 +1,6 @@
 use std::net::SocketAddr;
 
-use rust_web_dev::{oneshot, setup_store, Config, Error};
+use rust_web_dev::{oneshot, setup_memory_store, setup_store, Config, Error, StoreBackend};
 
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -20,7 +20,10 @@ struct User {
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
-struct Token(String);
+struct Token {
+    access_token: String,
+    refresh_token: String,
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct Question {
@@ -30,13 +33,20 @@ struct Question {
     tags: Option<Vec<String>>,
 }
 
-// #[derive(Debug, Serialize, Deserialize, Clone)]
-// struct QuestionAnswer {
-//     id: i32,
-//     title: String,
-//     content: String,
-//     tags: Option<Vec<String>>,
-// }
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Answer {
+    id: Option<i32>,
+    content: String,
+    question_id: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct QuestionAnswers {
+    id: i32,
+    title: String,
+    content: String,
+    answers: Vec<Answer>,
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
@@ -44,33 +54,17 @@ async fn main() -> Result<(), Error> {
 
     let config = Config::new().expect("Config can't be set");
 
-    let s = Command::new("sqlx")
-        .arg("database")
-        .arg("drop")
-        .arg("--database-url")
-        .arg(format!(
-            "postgres://{}:{}@{}:{}/{}",
-            config.db_user, config.db_password, config.db_host, config.db_port, config.db_name
-        ))
-        .arg("-y")
-        .output()
-        .expect("sqlx command failed to start");
-    io::stderr().write_all(&s.stderr).unwrap();
-
-    let s = Command::new("sqlx")
-        .arg("database")
-        .arg("create")
-        .arg("--database-url")
-        .arg(format!(
-            "postgres://{}:{}@{}:{}/{}",
-            config.db_user, config.db_password, config.db_host, config.db_port, config.db_name
-        ))
-        .output()
-        .expect("sqlx command failed to start");
-    io::stderr().write_all(&s.stderr).unwrap();
-
-    let store = setup_store(&config).await?;
-    let handler = oneshot(store).await;
+    let handler = match config.store {
+        StoreBackend::Postgres => {
+            reset_database(&config);
+            let store = setup_store(&config).await?;
+            oneshot(store).await
+        }
+        StoreBackend::Memory => {
+            let store = setup_memory_store(&config);
+            oneshot(store).await
+        }
+    };
 
     let u = User {
         email: "test@email.com".to_string(),
@@ -106,7 +100,19 @@ async fn main() -> Result<(), Error> {
     }
 
     print!("Running post_question...");
-    match AssertUnwindSafe(post_question(token, handler.bind_addr))
+    match AssertUnwindSafe(post_question(token.clone(), handler.bind_addr))
+        .catch_unwind()
+        .await
+    {
+        Ok(_) => println!("✓"),
+        Err(_) => {
+            let _ = handler.sender.send(());
+            std::process::exit(1);
+        }
+    }
+
+    print!("Running post_answer...");
+    match AssertUnwindSafe(post_answer(token, handler.bind_addr))
         .catch_unwind()
         .await
     {
@@ -122,6 +128,33 @@ async fn main() -> Result<(), Error> {
     Ok(())
 }
 
+fn reset_database(config: &Config) {
+    let s = Command::new("sqlx")
+        .arg("database")
+        .arg("drop")
+        .arg("--database-url")
+        .arg(format!(
+            "postgres://{}:{}@{}:{}/{}",
+            config.db_user, config.db_password, config.db_host, config.db_port, config.db_name
+        ))
+        .arg("-y")
+        .output()
+        .expect("sqlx command failed to start");
+    io::stderr().write_all(&s.stderr).unwrap();
+
+    let s = Command::new("sqlx")
+        .arg("database")
+        .arg("create")
+        .arg("--database-url")
+        .arg(format!(
+            "postgres://{}:{}@{}:{}/{}",
+            config.db_user, config.db_password, config.db_host, config.db_port, config.db_name
+        ))
+        .output()
+        .expect("sqlx command failed to start");
+    io::stderr().write_all(&s.stderr).unwrap();
+}
+
 async fn register_new_user(user: &User, bind_addr: SocketAddr) {
     let client = Client::new();
     let res = client
@@ -158,7 +191,7 @@ async fn post_question(token: Token, bind_addr: SocketAddr) {
     let client = Client::new();
     let res = client
         .post(format!("http://{}/questions", bind_addr))
-        .header("Authorization", token.0)
+        .bearer_auth(&token.access_token)
         .json(&q)
         .send()
         .await
@@ -171,3 +204,39 @@ async fn post_question(token: Token, bind_addr: SocketAddr) {
     assert_eq!(q.title, res.title);
     assert_eq!(q.content, res.content);
 }
+
+async fn post_answer(token: Token, bind_addr: SocketAddr) {
+    let a = Answer {
+        id: None,
+        content: "Write an integration test.".to_string(),
+        question_id: None,
+    };
+    let client = Client::new();
+    let res = client
+        .post(format!("http://{}/questions/1/answers", bind_addr))
+        .bearer_auth(&token.access_token)
+        .json(&a)
+        .send()
+        .await
+        .unwrap()
+        .json::<Answer>()
+        .await
+        .unwrap();
+
+    assert_eq!(Some(1), res.id);
+    assert_eq!(Some(1), res.question_id);
+    assert_eq!(a.content, res.content);
+
+    let res = client
+        .get(format!("http://{}/questions/1", bind_addr))
+        .send()
+        .await
+        .unwrap()
+        .json::<QuestionAnswers>()
+        .await
+        .unwrap();
+
+    assert_eq!(1, res.id);
+    assert_eq!(1, res.answers.len());
+    assert_eq!(a.content, res.answers[0].content);
+}
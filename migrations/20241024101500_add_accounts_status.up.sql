@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE accounts
+ADD COLUMN status VARCHAR(32) NOT NULL DEFAULT 'active';
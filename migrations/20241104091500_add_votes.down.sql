@@ -0,0 +1,8 @@
+-- Add down migration script here
+ALTER TABLE answers
+DROP COLUMN IF EXISTS score;
+
+ALTER TABLE questions
+DROP COLUMN IF EXISTS score;
+
+DROP TABLE IF EXISTS votes;
@@ -0,0 +1,21 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS votes (
+    id serial PRIMARY KEY,
+    account_id integer NOT NULL,
+    question_id integer REFERENCES questions (id) ON DELETE CASCADE,
+    answer_id integer REFERENCES answers (id) ON DELETE CASCADE,
+    value smallint NOT NULL CHECK (value IN (-1, 1)),
+    created_on TIMESTAMP NOT NULL DEFAULT NOW(),
+    CHECK (num_nonnulls(question_id, answer_id) = 1),
+    UNIQUE (account_id, question_id),
+    UNIQUE (account_id, answer_id)
+);
+
+CREATE INDEX IF NOT EXISTS votes_question_id_idx ON votes (question_id);
+CREATE INDEX IF NOT EXISTS votes_answer_id_idx ON votes (answer_id);
+
+ALTER TABLE questions
+ADD COLUMN score integer NOT NULL DEFAULT 0;
+
+ALTER TABLE answers
+ADD COLUMN score integer NOT NULL DEFAULT 0;
@@ -0,0 +1,13 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS comments (
+    id serial PRIMARY KEY,
+    content TEXT NOT NULL CHECK (char_length(content) <= 600),
+    question_id integer REFERENCES questions (id) ON DELETE CASCADE,
+    answer_id integer REFERENCES answers (id) ON DELETE CASCADE,
+    account_id integer NOT NULL,
+    created_on TIMESTAMP NOT NULL DEFAULT NOW(),
+    CHECK (num_nonnulls(question_id, answer_id) = 1)
+);
+
+CREATE INDEX IF NOT EXISTS comments_question_id_idx ON comments (question_id);
+CREATE INDEX IF NOT EXISTS comments_answer_id_idx ON comments (answer_id);
@@ -0,0 +1,12 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS question_revisions (
+    id serial PRIMARY KEY,
+    question_id integer NOT NULL REFERENCES questions (id) ON DELETE CASCADE,
+    revision integer NOT NULL,
+    title VARCHAR (255) NOT NULL,
+    content TEXT NOT NULL,
+    tags TEXT [],
+    account_id integer NOT NULL,
+    created_on TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (question_id, revision)
+);
@@ -0,0 +1,47 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS tags (
+    id serial PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    description TEXT CHECK (char_length(description) <= 500),
+    synonym_of integer REFERENCES tags (id) ON DELETE SET NULL,
+    created_on TIMESTAMP NOT NULL DEFAULT NOW(),
+    CHECK (synonym_of <> id)
+);
+
+CREATE INDEX IF NOT EXISTS tags_synonym_of_idx ON tags (synonym_of);
+
+CREATE TABLE IF NOT EXISTS question_tags (
+    question_id integer NOT NULL REFERENCES questions (id) ON DELETE CASCADE,
+    tag_id integer NOT NULL REFERENCES tags (id) ON DELETE CASCADE,
+    PRIMARY KEY (question_id, tag_id)
+);
+
+CREATE INDEX IF NOT EXISTS question_tags_tag_id_idx ON question_tags (tag_id);
+
+-- Normalize the existing arrays the way the API does from now on:
+-- lowercased, words joined by dashes, without duplicates
+UPDATE questions
+SET tags = (
+    SELECT array_agg(name ORDER BY position)
+    FROM (
+        SELECT DISTINCT ON (name) name, position
+        FROM unnest(questions.tags) WITH ORDINALITY AS raw (tag, position),
+            LATERAL (
+                SELECT lower(regexp_replace(trim(raw.tag), '\s+', '-', 'g')) AS name
+            ) normalized
+        WHERE name <> ''
+        ORDER BY name, position
+    ) deduplicated
+)
+WHERE tags IS NOT NULL;
+
+INSERT INTO tags (name)
+SELECT DISTINCT unnest(tags) FROM questions
+ON CONFLICT (name) DO NOTHING;
+
+INSERT INTO question_tags (question_id, tag_id)
+SELECT questions.id, tags.id
+FROM questions
+CROSS JOIN LATERAL unnest(questions.tags) AS question_tag (name)
+JOIN tags ON tags.name = question_tag.name
+ON CONFLICT DO NOTHING;
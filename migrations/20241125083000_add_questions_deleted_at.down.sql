@@ -0,0 +1,8 @@
+-- Add down migration script here
+-- Without the column deleted questions would come back, so purge them
+DELETE FROM questions WHERE deleted_at IS NOT NULL;
+
+DROP INDEX IF EXISTS questions_deleted_at_idx;
+
+ALTER TABLE questions
+DROP COLUMN deleted_at;
@@ -0,0 +1,7 @@
+-- Add down migration script here
+ALTER TABLE questions
+DROP COLUMN locked;
+
+ALTER TABLE accounts
+DROP CONSTRAINT IF EXISTS accounts_status_check,
+DROP COLUMN role;
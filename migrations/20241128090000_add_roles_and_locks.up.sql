@@ -0,0 +1,8 @@
+-- Add up migration script here
+ALTER TABLE accounts
+ADD COLUMN role VARCHAR(32) NOT NULL DEFAULT 'user',
+ADD CONSTRAINT accounts_role_check CHECK (role IN ('user', 'moderator', 'admin')),
+ADD CONSTRAINT accounts_status_check CHECK (status IN ('active', 'suspended'));
+
+ALTER TABLE questions
+ADD COLUMN locked BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,18 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS refresh_tokens (
+    id serial PRIMARY KEY,
+    token_hash TEXT NOT NULL UNIQUE,
+    family TEXT NOT NULL,
+    account_id integer NOT NULL,
+    expires_on TIMESTAMP NOT NULL,
+    used_on TIMESTAMP,
+    revoked_on TIMESTAMP,
+    created_on TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS refresh_tokens_family_idx ON refresh_tokens (family);
+
+CREATE TABLE IF NOT EXISTS revoked_tokens (
+    jti TEXT PRIMARY KEY,
+    expires_on TIMESTAMP NOT NULL
+);
@@ -0,0 +1,5 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS email_verifications;
+
+ALTER TABLE accounts
+DROP COLUMN IF EXISTS email_verified_at;
@@ -0,0 +1,44 @@
+-- Add up migration script here
+ALTER TABLE accounts
+ADD COLUMN email_verified_at TIMESTAMP;
+
+-- Accounts from before verification existed count as verified,
+-- so enforcing verification does not lock them out
+UPDATE accounts SET email_verified_at = NOW();
+
+-- Emails are now stored trimmed and lowercased, and logins only look
+-- up ones like `local@example.com`. Accounts whose emails only differ
+-- in case, or are no such address, could never log in again, so the
+-- migration stops until they were given an address of their own.
+DO $$
+DECLARE
+    stranded TEXT;
+BEGIN
+    SELECT string_agg(format('%s (%s)', id, email), ', ' ORDER BY id)
+    INTO stranded
+    FROM accounts
+    WHERE lower(btrim(email)) IN (
+        SELECT lower(btrim(email)) FROM accounts
+        GROUP BY lower(btrim(email)) HAVING count(*) > 1
+    )
+    OR char_length(btrim(email)) > 254
+    OR lower(btrim(email)) !~ '^[^@<>[:space:][:cntrl:]]+@([[:alnum:]]([[:alnum:]-]*[[:alnum:]])?\.)+[[:alnum:]]([[:alnum:]-]*[[:alnum:]])?$';
+
+    IF stranded IS NOT NULL THEN
+        RAISE EXCEPTION 'accounts % could not log in with their emails normalized', stranded
+            USING HINT = 'Give each of them a unique, valid email address and run the migration again.';
+    END IF;
+END
+$$;
+
+UPDATE accounts SET email = lower(btrim(email))
+WHERE email <> lower(btrim(email));
+
+CREATE TABLE IF NOT EXISTS email_verifications (
+    id serial PRIMARY KEY,
+    token_hash TEXT NOT NULL UNIQUE,
+    account_id integer NOT NULL,
+    expires_on TIMESTAMP NOT NULL,
+    used_on TIMESTAMP,
+    created_on TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,6 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS login_failures (
+    key TEXT PRIMARY KEY,
+    failures integer NOT NULL,
+    last_failure TIMESTAMP NOT NULL
+);
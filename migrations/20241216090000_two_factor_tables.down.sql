@@ -0,0 +1,7 @@
+-- Add down migration script here
+ALTER TABLE refresh_tokens
+DROP COLUMN IF EXISTS two_factor;
+
+DROP TABLE IF EXISTS login_challenges;
+DROP TABLE IF EXISTS recovery_codes;
+DROP TABLE IF EXISTS two_factor;
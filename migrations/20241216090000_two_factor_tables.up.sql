@@ -0,0 +1,26 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS two_factor (
+    account_id integer PRIMARY KEY,
+    secret TEXT NOT NULL,
+    confirmed_on TIMESTAMP,
+    last_used_step BIGINT NOT NULL DEFAULT 0,
+    created_on TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS recovery_codes (
+    account_id integer NOT NULL,
+    code_hash TEXT NOT NULL,
+    PRIMARY KEY (account_id, code_hash)
+);
+
+CREATE TABLE IF NOT EXISTS login_challenges (
+    id serial PRIMARY KEY,
+    token_hash TEXT NOT NULL UNIQUE,
+    account_id integer NOT NULL,
+    expires_on TIMESTAMP NOT NULL,
+    used_on TIMESTAMP,
+    created_on TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+ALTER TABLE refresh_tokens
+ADD COLUMN two_factor BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,14 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS api_keys (
+    id serial PRIMARY KEY,
+    account_id integer NOT NULL,
+    name TEXT NOT NULL,
+    prefix TEXT NOT NULL,
+    key_hash TEXT NOT NULL UNIQUE,
+    scopes TEXT[] NOT NULL,
+    created_on TIMESTAMP NOT NULL DEFAULT NOW(),
+    last_used_on TIMESTAMP,
+    revoked_on TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS api_keys_account_id_idx ON api_keys (account_id);
@@ -0,0 +1,10 @@
+-- Add down migration script here
+ALTER TABLE accounts
+DROP COLUMN IF EXISTS display_name,
+DROP COLUMN IF EXISTS bio,
+DROP COLUMN IF EXISTS avatar_url,
+DROP COLUMN IF EXISTS created_on;
+
+ALTER TABLE accounts DROP CONSTRAINT IF EXISTS accounts_email_key;
+ALTER TABLE accounts DROP CONSTRAINT IF EXISTS accounts_pkey;
+ALTER TABLE accounts ADD PRIMARY KEY (email);
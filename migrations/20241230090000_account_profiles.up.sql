@@ -0,0 +1,17 @@
+-- Add up migration script here
+ALTER TABLE accounts DROP CONSTRAINT accounts_pkey;
+ALTER TABLE accounts ADD PRIMARY KEY (id);
+ALTER TABLE accounts ADD CONSTRAINT accounts_email_key UNIQUE (email);
+
+ALTER TABLE accounts
+ADD COLUMN display_name VARCHAR(50),
+ADD COLUMN bio TEXT,
+ADD COLUMN avatar_url TEXT,
+ADD COLUMN created_on TIMESTAMP NOT NULL DEFAULT NOW();
+
+-- Accounts from before are dated back to their first post, if they made one
+UPDATE accounts SET created_on = LEAST(
+    created_on,
+    (SELECT MIN(created_on) FROM questions WHERE questions.account_id = accounts.id),
+    (SELECT MIN(created_on) FROM answers WHERE answers.account_id = accounts.id)
+);
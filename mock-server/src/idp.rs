@@ -0,0 +1,237 @@
+use serde_json::json;
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use sha2::{Digest, Sha256};
+
+use warp::http::{StatusCode, Uri};
+use warp::reply::Response;
+use warp::{Filter, Rejection, Reply};
+
+/// Who logs in when an authorization request has no `login_hint`
+pub const IDP_DEFAULT_USER: &str = "user@example.com";
+/// The `login_hint` of a user who turns every login down
+pub const IDP_DENYING_USER: &str = "deny@example.com";
+/// Users whose `login_hint` starts with this have not verified their email
+pub const IDP_UNVERIFIED_PREFIX: &str = "unverified";
+
+/// Id of the key ID tokens are signed with
+const KEY_ID: &str = "mock-key";
+/// Public parts of the signing key in `idp_key.pem`, as the JWKS lists them
+const KEY_MODULUS: &str = "sey8P8ON8OV4A5SBjGKcxy3gM5rBo9udzYElg9LVS6z34ucWHorJl3g6WrkYixjf3JsV89OZ0ca8OUrqxOdYhgeS7pJhnYKfDbz04Koi0k7cUK7SClwjGVG7gzmYan3x9KX2f8IqEtM6WCQjdHih00tqVq1mzDNigsH1v4wMYnfLTT-4S9TnsY6zc8tEvY9QqOiZnh1NUgHkdKyTE2puay2Ft7G-hb83HOeBhNPdr8rbFQSttgafYx41F8Nn6XW-V2qONgRjy8AfTiQ6NWO-8dJ15TKvgDHQdXDvOqeijoTBhCdxChJliSRP9q46_72l8EQk8zxz61zQYukl2y7Miw";
+const KEY_EXPONENT: &str = "AQAB";
+/// Seconds the ID tokens are valid for
+const ID_TOKEN_TTL: u64 = 300;
+
+/// What an authorization code was handed out for
+#[derive(Debug, Clone)]
+struct Grant {
+    client_id: String,
+    redirect_uri: String,
+    nonce: Option<String>,
+    code_challenge: String,
+    email: String,
+}
+
+/// A stand-in OpenID Connect provider. Users are never asked anything:
+/// every authorization request logs in its `login_hint` right away, and
+/// only the PKCE verifier is checked when the code is traded in.
+#[derive(Debug, Clone)]
+pub(crate) struct Idp {
+    issuer: String,
+    grants: Arc<Mutex<HashMap<String, Grant>>>,
+    last_code: Arc<AtomicU64>,
+}
+
+impl Idp {
+    pub(crate) fn new(issuer: String) -> Self {
+        Self {
+            issuer,
+            grants: Arc::default(),
+            last_code: Arc::default(),
+        }
+    }
+
+    fn discovery(&self) -> Response {
+        warp::reply::json(&json!({
+            "issuer": self.issuer,
+            "authorization_endpoint": format!("{}/authorize", self.issuer),
+            "token_endpoint": format!("{}/token", self.issuer),
+            "jwks_uri": format!("{}/jwks", self.issuer),
+            "response_types_supported": ["code"],
+            "subject_types_supported": ["public"],
+            "id_token_signing_alg_values_supported": ["RS256"],
+            "code_challenge_methods_supported": ["S256"],
+        }))
+        .into_response()
+    }
+
+    fn jwks(&self) -> Response {
+        warp::reply::json(&json!({
+            "keys": [{
+                "kty": "RSA",
+                "use": "sig",
+                "alg": "RS256",
+                "kid": KEY_ID,
+                "n": KEY_MODULUS,
+                "e": KEY_EXPONENT,
+            }]
+        }))
+        .into_response()
+    }
+
+    fn authorize(&self, query: HashMap<String, String>) -> Response {
+        let param = |name: &str| query.get(name).cloned().unwrap_or_default();
+        if param("response_type") != "code" || param("code_challenge_method") != "S256" {
+            return bad_request("unsupported_response_type");
+        }
+        let (client_id, redirect_uri, state) =
+            (param("client_id"), param("redirect_uri"), param("state"));
+        let code_challenge = param("code_challenge");
+        if client_id.is_empty() || redirect_uri.is_empty() || code_challenge.is_empty() {
+            return bad_request("invalid_request");
+        }
+
+        let email = query
+            .get("login_hint")
+            .cloned()
+            .unwrap_or_else(|| IDP_DEFAULT_USER.to_string());
+        let separator = if redirect_uri.contains('?') { '&' } else { '?' };
+        if email == IDP_DENYING_USER {
+            return redirect(format!(
+                "{}{}error=access_denied&state={}",
+                redirect_uri, separator, state
+            ));
+        }
+
+        let code = format!(
+            "code-{}",
+            self.last_code.fetch_add(1, Ordering::Relaxed) + 1
+        );
+        self.grants.lock().unwrap().insert(
+            code.clone(),
+            Grant {
+                client_id,
+                redirect_uri: redirect_uri.clone(),
+                nonce: query.get("nonce").cloned(),
+                code_challenge,
+                email,
+            },
+        );
+
+        redirect(format!(
+            "{}{}code={}&state={}",
+            redirect_uri, separator, code, state
+        ))
+    }
+
+    fn token(&self, form: HashMap<String, String>) -> Response {
+        let param = |name: &str| form.get(name).cloned().unwrap_or_default();
+        if param("grant_type") != "authorization_code" {
+            return bad_request("unsupported_grant_type");
+        }
+        // Codes are single use, even when the request is turned down
+        let grant = match self.grants.lock().unwrap().remove(&param("code")) {
+            Some(grant) => grant,
+            None => return bad_request("invalid_grant"),
+        };
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(param("code_verifier")));
+        if grant.client_id != param("client_id")
+            || grant.redirect_uri != param("redirect_uri")
+            || grant.code_challenge != challenge
+        {
+            return bad_request("invalid_grant");
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let mut claims = json!({
+            "iss": self.issuer,
+            "sub": format!("mock|{}", grant.email),
+            "aud": grant.client_id,
+            "iat": now,
+            "exp": now + ID_TOKEN_TTL,
+            "email": grant.email,
+            "email_verified": !grant.email.starts_with(IDP_UNVERIFIED_PREFIX),
+        });
+        if let Some(nonce) = grant.nonce {
+            claims["nonce"] = json!(nonce);
+        }
+
+        let header = Header {
+            kid: Some(KEY_ID.to_string()),
+            ..Header::new(Algorithm::RS256)
+        };
+        let key = EncodingKey::from_rsa_pem(include_bytes!("idp_key.pem")).expect("Invalid key");
+        let id_token = jsonwebtoken::encode(&header, &claims, &key).expect("Cannot sign token");
+
+        warp::reply::json(&json!({
+            "access_token": format!("access-{}", param("code")),
+            "token_type": "Bearer",
+            "expires_in": ID_TOKEN_TTL,
+            "id_token": id_token,
+        }))
+        .into_response()
+    }
+
+    pub(crate) fn routes(&self) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
+        let idp = warp::any().map({
+            let idp = self.clone();
+            move || idp.clone()
+        });
+
+        let discovery = warp::get()
+            .and(warp::path!(".well-known" / "openid-configuration"))
+            .and(idp.clone())
+            .map(|idp: Idp| idp.discovery());
+
+        let jwks = warp::get()
+            .and(warp::path!("jwks"))
+            .and(idp.clone())
+            .map(|idp: Idp| idp.jwks());
+
+        let authorize = warp::get()
+            .and(warp::path!("authorize"))
+            .and(idp.clone())
+            .and(warp::query())
+            .map(|idp: Idp, query| idp.authorize(query));
+
+        let token = warp::post()
+            .and(warp::path!("token"))
+            .and(idp)
+            .and(warp::body::form())
+            .map(|idp: Idp, form| idp.token(form));
+
+        discovery
+            .or(jwks)
+            .unify()
+            .or(authorize)
+            .unify()
+            .or(token)
+            .unify()
+    }
+}
+
+fn redirect(location: String) -> Response {
+    match location.parse::<Uri>() {
+        Ok(uri) => warp::redirect::found(uri).into_response(),
+        Err(_) => bad_request("invalid_request"),
+    }
+}
+
+/// Error answer in the shape OAuth 2.0 gives them
+fn bad_request(error: &str) -> Response {
+    warp::reply::with_status(
+        warp::reply::json(&json!({ "error": error })),
+        StatusCode::BAD_REQUEST,
+    )
+    .into_response()
+}
@@ -11,6 +11,10 @@ use bytes::Bytes;
 
 use std::collections::HashMap;
 
+mod idp;
+
+pub use idp::{IDP_DEFAULT_USER, IDP_DENYING_USER, IDP_UNVERIFIED_PREFIX};
+
 #[derive(Debug, Clone)]
 pub struct MockServer {
     pub bind_addr: SocketAddr,
@@ -30,6 +34,12 @@ impl MockServer {
         Self { bind_addr }
     }
 
+    /// Issuer URL of the stand-in OpenID Connect provider the server
+    /// also plays, its discovery document is found under it
+    pub fn issuer(&self) -> String {
+        format!("http://{}", self.bind_addr)
+    }
+
     async fn check_profanity(_: (), content: Bytes) -> Result<impl Reply, Rejection> {
         let content = String::from_utf8(content.to_vec()).expect("Invalid UTF-8");
 
@@ -74,6 +84,7 @@ impl MockServer {
             .and(warp::path::end())
             .and(warp::body::bytes())
             .and_then(Self::check_profanity)
+            .or(idp::Idp::new(self.issuer()).routes())
     }
 
     pub fn oneshot(&self) -> OneshotHandler {
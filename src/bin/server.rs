@@ -1,15 +1,23 @@
-use rust_web_dev::{run, setup_store, Config};
+use rust_web_dev::{run, setup_memory_store, setup_store, Config, StoreBackend};
 
 #[tokio::main]
 async fn main() -> Result<(), handle_errors::Error> {
     dotenvy::dotenv().ok();
 
     let config = Config::new().expect("Config can't not be set");
-    let store = setup_store(&config).await?;
 
-    tracing::info!("Q&A service build ID {}", env!("RUST_WEB_DEV_VERSION"));
-
-    run(config, store).await;
+    match config.store {
+        StoreBackend::Postgres => {
+            let store = setup_store(&config).await?;
+            tracing::info!("Q&A service build ID {}", env!("RUST_WEB_DEV_VERSION"));
+            run(config, store).await;
+        }
+        StoreBackend::Memory => {
+            let store = setup_memory_store(&config);
+            tracing::info!("Q&A service build ID {}", env!("RUST_WEB_DEV_VERSION"));
+            run(config, store).await;
+        }
+    }
 
     Ok(())
 }
@@ -1,8 +1,10 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use std::env;
 
 use handle_errors::Error;
 
+pub const DEFAULT_RETENTION_DAYS: i64 = 30;
+
 /// Q&A web service API
 #[derive(Debug, Parser, PartialEq)]
 #[command(author, version, about, long_about = None)]
@@ -34,6 +36,103 @@ pub struct Config {
     /// Database name
     #[arg(long, default_value = "rustwebdev")]
     pub db_name: String,
+
+    /// Where questions and accounts are stored
+    #[arg(long, value_enum, default_value_t = StoreBackend::Postgres)]
+    pub store: StoreBackend,
+
+    /// How many days deleted questions can be restored before they are purged
+    #[arg(long, default_value_t = DEFAULT_RETENTION_DAYS)]
+    pub retention_days: i64,
+
+    /// How emails such as password resets are delivered
+    #[arg(long, value_enum, default_value_t = MailerBackend::Log)]
+    pub mailer: MailerBackend,
+
+    /// Directory the file mailer writes emails to
+    #[arg(long, default_value = "mail")]
+    pub mail_dir: String,
+
+    /// Address emails are sent from
+    #[arg(long, default_value = "Q&A <noreply@localhost>")]
+    pub mail_from: String,
+
+    /// SMTP relay the SMTP mailer sends through
+    #[arg(long, default_value = "localhost")]
+    pub smtp_host: String,
+
+    /// PORT of the SMTP relay
+    #[arg(long, default_value = "587")]
+    pub smtp_port: u16,
+
+    /// User to log in to the SMTP relay as, if it needs a login
+    #[arg(long)]
+    pub smtp_username: Option<String>,
+
+    /// Password for `smtp_username`
+    #[arg(long)]
+    pub smtp_password: Option<String>,
+
+    /// Talk to the SMTP relay without TLS, only for local relays
+    #[arg(long)]
+    pub smtp_insecure: bool,
+
+    /// Only let accounts that verified their email post
+    #[arg(long)]
+    pub require_verified_email: bool,
+
+    /// Issuer URL of the OpenID Connect provider to offer logins through,
+    /// none are offered unless it is set
+    #[arg(long)]
+    pub oidc_issuer: Option<String>,
+
+    /// Client id this service is registered with at the provider
+    #[arg(long)]
+    pub oidc_client_id: Option<String>,
+
+    /// Client secret for `oidc_client_id`, if the provider gave one
+    #[arg(long)]
+    pub oidc_client_secret: Option<String>,
+
+    /// Where the provider sends browsers back to, `/oidc/callback` on
+    /// the address the service is reached at
+    #[arg(long)]
+    pub oidc_redirect_uri: Option<String>,
+
+    /// Key access tokens are issued with: a `k4.secret` PASERK for
+    /// `v4.public` tokens other services can check, or a `k2.local`
+    /// PASERK or 32 characters for `v2.local` tokens only we can read
+    #[arg(long, default_value = "")]
+    pub paseto_key: String,
+
+    /// Id of `paseto_key`, named in the footer of the tokens
+    #[arg(long, default_value = "1")]
+    pub paseto_key_id: String,
+
+    /// Keys tokens are still accepted from as `<kid>=<key>`, such as the
+    /// one `paseto_key` replaced, until its tokens expired
+    #[arg(long, value_delimiter = ',')]
+    pub paseto_verification_keys: Vec<String>,
+}
+
+/// Storage backends the service can run on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StoreBackend {
+    /// PostgreSQL, configured through the `db_*` options
+    Postgres,
+    /// Process memory; everything is lost on restart
+    Memory,
+}
+
+/// Ways of delivering emails
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MailerBackend {
+    /// Write emails to the log
+    Log,
+    /// Write each email to a file in `mail_dir`
+    File,
+    /// Send emails through the `smtp_*` relay
+    Smtp,
 }
 
 impl Config {
@@ -42,7 +141,10 @@ impl Config {
 
         let _ =
             env::var("BAD_WORDS_API_KEY").unwrap_or_else(|_| panic!("BadWords API key not set"));
-        let _ = env::var("PASETO_KEY").unwrap_or_else(|_| panic!("PASETO key not set"));
+        let paseto_key = env::var("PASETO_KEY").unwrap_or(config.paseto_key);
+        if paseto_key.is_empty() {
+            panic!("PASETO key not set");
+        }
 
         let port = env::var("PORT")
             .ok()
@@ -52,10 +154,63 @@ impl Config {
             .unwrap_or(config.port);
 
         let db_user = env::var("POSTGRES_USER").unwrap_or(config.db_user);
-        let db_password = env::var("POSTGRES_PASSWORD").unwrap();
+        let store = env::var("STORE_BACKEND")
+            .ok()
+            .map(|val| parse_enum::<StoreBackend>("STORE_BACKEND", &val))
+            .transpose()?
+            .unwrap_or(config.store);
+
+        // The password is only needed when we actually talk to Postgres
+        let db_password = match store {
+            StoreBackend::Postgres => env::var("POSTGRES_PASSWORD").unwrap(),
+            StoreBackend::Memory => env::var("POSTGRES_PASSWORD").unwrap_or(config.db_password),
+        };
         let db_host = env::var("POSTGRES_HOST").unwrap_or(config.db_host);
         let db_port = env::var("POSTGRES_PORT").unwrap_or_else(|_| config.db_port.to_string());
         let db_name = env::var("POSTGRES_DB").unwrap_or(config.db_name);
+        let retention_days = env::var("RETENTION_DAYS")
+            .ok()
+            .map(|val| val.parse::<i64>())
+            .transpose()
+            .map_err(Error::ParseError)?
+            .unwrap_or(config.retention_days);
+        let mailer = env::var("MAILER")
+            .ok()
+            .map(|val| parse_enum::<MailerBackend>("MAILER", &val))
+            .transpose()?
+            .unwrap_or(config.mailer);
+        let mail_dir = env::var("MAIL_DIR").unwrap_or(config.mail_dir);
+        let mail_from = env::var("MAIL_FROM").unwrap_or(config.mail_from);
+        let smtp_host = env::var("SMTP_HOST").unwrap_or(config.smtp_host);
+        let smtp_port = env::var("SMTP_PORT")
+            .ok()
+            .map(|val| val.parse::<u16>())
+            .transpose()
+            .map_err(Error::ParseError)?
+            .unwrap_or(config.smtp_port);
+        let smtp_username = env::var("SMTP_USERNAME").ok().or(config.smtp_username);
+        let smtp_password = env::var("SMTP_PASSWORD").ok().or(config.smtp_password);
+        let smtp_insecure = env_flag("SMTP_INSECURE").unwrap_or(config.smtp_insecure);
+        let require_verified_email =
+            env_flag("REQUIRE_VERIFIED_EMAIL").unwrap_or(config.require_verified_email);
+        let oidc_issuer = env::var("OIDC_ISSUER").ok().or(config.oidc_issuer);
+        let oidc_client_id = env::var("OIDC_CLIENT_ID").ok().or(config.oidc_client_id);
+        let oidc_client_secret = env::var("OIDC_CLIENT_SECRET")
+            .ok()
+            .or(config.oidc_client_secret);
+        let oidc_redirect_uri = env::var("OIDC_REDIRECT_URI")
+            .ok()
+            .or(config.oidc_redirect_uri);
+        let paseto_key_id = env::var("PASETO_KEY_ID").unwrap_or(config.paseto_key_id);
+        let paseto_verification_keys = env::var("PASETO_VERIFICATION_KEYS")
+            .map(|val| {
+                val.split(',')
+                    .map(str::trim)
+                    .filter(|key| !key.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or(config.paseto_verification_keys);
 
         Ok(Self {
             log_level: config.log_level,
@@ -65,10 +220,41 @@ impl Config {
             db_host,
             db_port: db_port.parse::<u16>().map_err(Error::ParseError)?,
             db_name,
+            store,
+            retention_days,
+            mailer,
+            mail_dir,
+            mail_from,
+            smtp_host,
+            smtp_port,
+            smtp_username,
+            smtp_password,
+            smtp_insecure,
+            require_verified_email,
+            oidc_issuer,
+            oidc_client_id,
+            oidc_client_secret,
+            oidc_redirect_uri,
+            paseto_key,
+            paseto_key_id,
+            paseto_verification_keys,
         })
     }
 }
 
+/// Read one of the values of an option from an environment variable,
+/// failing with `Error::InvalidParameter` on anything else
+fn parse_enum<T: ValueEnum>(name: &str, val: &str) -> Result<T, Error> {
+    T::from_str(val, true).map_err(|err| Error::InvalidParameter(format!("{}: {}", name, err)))
+}
+
+/// Read a boolean environment variable, `true` or `1` turn it on
+fn env_flag(name: &str) -> Option<bool> {
+    env::var(name)
+        .ok()
+        .map(|val| matches!(val.trim().to_lowercase().as_str(), "true" | "1"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -88,13 +274,39 @@ mod tests {
             db_host: "localhost".to_string(),
             db_port: 5432,
             db_name: "rustwebdev".to_string(),
+            store: StoreBackend::Postgres,
+            retention_days: DEFAULT_RETENTION_DAYS,
+            mailer: MailerBackend::Log,
+            mail_dir: "mail".to_string(),
+            mail_from: "Q&A <noreply@localhost>".to_string(),
+            smtp_host: "localhost".to_string(),
+            smtp_port: 587,
+            smtp_username: None,
+            smtp_password: None,
+            smtp_insecure: false,
+            require_verified_email: false,
+            oidc_issuer: None,
+            oidc_client_id: None,
+            oidc_client_secret: None,
+            oidc_redirect_uri: None,
+            paseto_key: "RANDOM WORDS WINTER MACINTOSH PC".to_string(),
+            paseto_key_id: "1".to_string(),
+            paseto_verification_keys: vec![],
         };
 
         assert_eq!(expected, config);
+
+        for name in ["STORE_BACKEND", "MAILER"] {
+            env::set_var(name, "unknown");
+            let err = Config::new().unwrap_err();
+            assert!(matches!(err, Error::InvalidParameter(_)), "{}", name);
+            env::remove_var(name);
+        }
     }
 
     fn set_env() {
         env::set_var("BAD_WORDS_API_KEY", "yes");
+        env::set_var("PASETO_KEY", "RANDOM WORDS WINTER MACINTOSH PC");
         env::set_var("POSTGRES_USER", "postgres");
         env::set_var("POSTGRES_PASSWORD", "pass");
         env::set_var("POSTGRES_HOST", "localhost");
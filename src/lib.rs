@@ -4,33 +4,56 @@ mod routes;
 mod store;
 mod types;
 
+mod policy;
 mod profanity;
+mod purge;
 
 mod config;
+mod mailer;
+mod oidc;
+mod token_keys;
 
 use warp::http::Method;
 use warp::reply::Reply;
 use warp::Filter;
 
-use store::Store;
+use types::{CommentTarget, Role, Scope};
+
+pub use store::{
+    AccountRepository, ApiKeyRepository, CommentRepository, IdentityRepository,
+    LoginAttemptRepository, MemoryStore, PgStore, QuestionRepository, Store, TagRepository,
+    TokenRepository, TwoFactorRepository,
+};
 
 use tracing_subscriber::fmt::format::FmtSpan;
 
-pub use config::Config;
+pub use config::{Config, MailerBackend, StoreBackend, DEFAULT_RETENTION_DAYS};
+pub use mailer::{Email, FileMailer, LogMailer, Mailer, SharedMailer};
+pub use oidc::{OidcClient, OidcSettings, SharedOidcClient};
+pub use token_keys::{PublicKey, SharedTokenKeys, TokenKeys};
 
 pub use handle_errors::Error;
 
 use tokio::sync::oneshot::{self, Sender};
 
+use std::future;
 use std::net::SocketAddr;
+use std::sync::Arc;
 
 pub struct OneshotHandler {
     pub sender: Sender<()>,
     pub bind_addr: SocketAddr,
 }
 
-pub async fn oneshot(store: Store) -> OneshotHandler {
-    let routes = build_routes(store);
+pub async fn oneshot<S: Store>(store: S) -> OneshotHandler {
+    let routes = build_routes(
+        store,
+        chrono::Duration::days(DEFAULT_RETENTION_DAYS),
+        Arc::new(LogMailer),
+        Arc::new(TokenKeys::generate()),
+        false,
+        None,
+    );
     let (tx, rx) = oneshot::channel();
 
     let bind_addr: SocketAddr = tokio::net::TcpListener::bind("127.0.0.1:0")
@@ -50,87 +73,661 @@ pub async fn oneshot(store: Store) -> OneshotHandler {
     }
 }
 
-pub async fn run(config: Config, store: Store) {
-    let routes = build_routes(store);
+pub async fn run<S: Store>(config: Config, store: S) {
+    let retention = chrono::Duration::days(config.retention_days);
+    purge::spawn_purge_job(store.clone(), retention);
+
+    let mailer = mailer::from_config(&config).expect("Mailer can't be set up");
+    let token_keys = token_keys::from_config(&config).expect("Token keys can't be loaded");
+    let oidc = oidc::from_config(&config)
+        .await
+        .expect("OpenID Connect can't be set up");
+
+    let routes = build_routes(
+        store,
+        retention,
+        mailer,
+        token_keys,
+        config.require_verified_email,
+        oidc,
+    );
     warp::serve(routes).run(([0, 0, 0, 0], config.port)).await;
 }
 
-fn build_routes(store: Store) -> impl Filter<Extract = impl Reply> + Clone {
+fn build_routes<S: Store>(
+    store: S,
+    retention: chrono::Duration,
+    mailer: SharedMailer,
+    token_keys: SharedTokenKeys,
+    require_verified_email: bool,
+    oidc: Option<SharedOidcClient>,
+) -> impl Filter<Extract = impl Reply> + Clone {
+    let auth = routes::auth(store.clone(), token_keys.clone());
+    // Asking, answering and commenting may need a verified email
+    let poster = if require_verified_email {
+        routes::require_verified_email(store.clone(), token_keys.clone()).boxed()
+    } else {
+        auth.clone().boxed()
+    };
+    let moderator = routes::require_role(store.clone(), token_keys.clone(), Role::Moderator);
+    let admin = routes::require_role(store.clone(), token_keys.clone(), Role::Admin);
+    // API keys only get to the routes their scopes cover
+    let question_poster = routes::require_scope(poster.clone(), Scope::QuestionsWrite).boxed();
+    let question_writer = routes::require_scope(auth.clone(), Scope::QuestionsWrite).boxed();
+    let answer_poster = routes::require_scope(poster.clone(), Scope::AnswersWrite).boxed();
+    let answer_writer = routes::require_scope(auth.clone(), Scope::AnswersWrite).boxed();
+    let comment_poster = routes::require_scope(poster.clone(), Scope::CommentsWrite).boxed();
+    let comment_writer = routes::require_scope(auth.clone(), Scope::CommentsWrite).boxed();
+    let voter = routes::require_scope(auth.clone(), Scope::VotesWrite).boxed();
+    let tag_writer = routes::require_scope(auth.clone(), Scope::TagsWrite).boxed();
+    let account = routes::require_scope(auth.clone(), Scope::Account).boxed();
     let store_filter = warp::any().map(move || store.clone());
+    let retention_filter = warp::any().map(move || retention);
+    let mailer_filter = warp::any().map(move || mailer.clone());
+    let token_keys_filter = warp::any().map(move || token_keys.clone());
+    // Logins through an identity provider are only there if one is set up
+    let oidc_filter = warp::any()
+        .and_then(move || future::ready(oidc.clone().ok_or_else(warp::reject::not_found)));
 
     let cors = warp::cors()
         .allow_any_origin()
-        .allow_header("content-type")
-        .allow_methods(&[Method::PUT, Method::DELETE, Method::GET, Method::POST]);
+        .allow_headers(vec![
+            "authorization",
+            "x-api-key",
+            "content-type",
+            "if-match",
+            "if-none-match",
+        ])
+        .expose_headers(vec!["link", "x-total-count", "etag", "www-authenticate"])
+        .allow_methods(&[
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+            Method::GET,
+            Method::POST,
+        ]);
 
     let get_questions = warp::get()
         .and(warp::path("questions"))
         .and(warp::path::end())
         .and(warp::query())
         .and(store_filter.clone())
-        .and_then(routes::get_questions);
+        .and_then(routes::get_questions::<S>);
+
+    let get_question = warp::get()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path::end())
+        .and(warp::header::optional::<String>("if-none-match"))
+        .and(store_filter.clone())
+        .and_then(routes::get_question::<S>);
 
     let add_question = warp::post()
         .and(warp::path("questions"))
         .and(warp::path::end())
-        .and(routes::auth())
+        .and(question_poster.clone())
         .and(store_filter.clone())
         .and(warp::body::json())
-        .and_then(routes::add_question);
+        .and_then(routes::add_question::<S>);
 
     let update_question = warp::put()
         .and(warp::path("questions"))
         .and(warp::path::param::<i32>())
         .and(warp::path::end())
-        .and(routes::auth())
+        .and(question_writer.clone())
+        .and(warp::header::optional::<String>("if-match"))
         .and(store_filter.clone())
         .and(warp::body::json())
-        .and_then(routes::update_question);
+        .and_then(routes::update_question::<S>);
 
     let delete_question = warp::delete()
         .and(warp::path("questions"))
         .and(warp::path::param::<i32>())
         .and(warp::path::end())
-        .and(routes::auth())
+        .and(question_writer.clone())
+        .and(warp::header::optional::<String>("if-match"))
+        .and(store_filter.clone())
+        .and_then(routes::delete_question::<S>);
+
+    let restore_question = warp::post()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("restore"))
+        .and(warp::path::end())
+        .and(question_writer.clone())
+        .and(retention_filter)
         .and(store_filter.clone())
-        .and_then(routes::delete_question);
+        .and_then(routes::restore_question::<S>);
+
+    let get_answers = warp::get()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("answers"))
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and_then(routes::get_answers::<S>);
+
+    let get_answer = warp::get()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("answers"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and_then(routes::get_answer::<S>);
 
     let add_answer = warp::post()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("answers"))
+        .and(warp::path::end())
+        .and(answer_poster.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::add_answer::<S>);
+
+    let update_answer = warp::put()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("answers"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path::end())
+        .and(answer_writer.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::update_answer::<S>);
+
+    let delete_answer = warp::delete()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
         .and(warp::path("answers"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path::end())
+        .and(answer_writer.clone())
+        .and(store_filter.clone())
+        .and_then(routes::delete_answer::<S>);
+
+    let get_revisions = warp::get()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("revisions"))
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and_then(routes::get_revisions::<S>);
+
+    let get_revision_diff = warp::get()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("revisions"))
+        .and(warp::path("diff"))
+        .and(warp::path::end())
+        .and(warp::query())
+        .and(store_filter.clone())
+        .and_then(routes::get_revision_diff::<S>);
+
+    let rollback_question = warp::post()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("revisions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("rollback"))
+        .and(warp::path::end())
+        .and(question_writer.clone())
+        .and(store_filter.clone())
+        .and_then(routes::rollback_question::<S>);
+
+    // Comments live under both `/questions/{id}/comments`
+    // and `/answers/{id}/comments`
+    let comments = warp::path("questions")
+        .and(warp::path::param::<i32>())
+        .map(CommentTarget::Question)
+        .or(warp::path("answers")
+            .and(warp::path::param::<i32>())
+            .map(CommentTarget::Answer))
+        .unify()
+        .and(warp::path("comments"));
+
+    let get_comments = warp::get()
+        .and(comments)
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and_then(routes::get_comments::<S>);
+
+    let add_comment = warp::post()
+        .and(comments)
+        .and(warp::path::end())
+        .and(comment_poster.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::add_comment::<S>);
+
+    let update_comment = warp::put()
+        .and(comments)
+        .and(warp::path::param::<i32>())
+        .and(warp::path::end())
+        .and(comment_writer.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::update_comment::<S>);
+
+    let delete_comment = warp::delete()
+        .and(comments)
+        .and(warp::path::param::<i32>())
+        .and(warp::path::end())
+        .and(comment_writer.clone())
+        .and(store_filter.clone())
+        .and_then(routes::delete_comment::<S>);
+
+    let accept_answer = warp::post()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("accept"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path::end())
+        .and(question_writer.clone())
+        .and(store_filter.clone())
+        .and_then(routes::accept_answer::<S>);
+
+    let unaccept_answer = warp::delete()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("accept"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path::end())
+        .and(question_writer.clone())
+        .and(store_filter.clone())
+        .and_then(routes::unaccept_answer::<S>);
+
+    let vote_on_question = warp::put()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("vote"))
+        .and(warp::path::end())
+        .and(voter.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::vote_on_question::<S>);
+
+    let retract_question_vote = warp::delete()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("vote"))
+        .and(warp::path::end())
+        .and(voter.clone())
+        .and(store_filter.clone())
+        .and_then(routes::retract_question_vote::<S>);
+
+    let vote_on_answer = warp::put()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("answers"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("vote"))
+        .and(warp::path::end())
+        .and(voter.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::vote_on_answer::<S>);
+
+    let retract_answer_vote = warp::delete()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("answers"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("vote"))
+        .and(warp::path::end())
+        .and(voter.clone())
+        .and(store_filter.clone())
+        .and_then(routes::retract_answer_vote::<S>);
+
+    let get_tags = warp::get()
+        .and(warp::path("tags"))
+        .and(warp::path::end())
+        .and(warp::query())
+        .and(store_filter.clone())
+        .and_then(routes::get_tags::<S>);
+
+    let get_tag = warp::get()
+        .and(warp::path("tags"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and_then(routes::get_tag::<S>);
+
+    let update_tag = warp::put()
+        .and(warp::path("tags"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(tag_writer.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::update_tag::<S>);
+
+    let add_tag_synonym = warp::post()
+        .and(warp::path("tags"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("synonyms"))
+        .and(warp::path::end())
+        .and(tag_writer.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::add_tag_synonym::<S>);
+
+    let remove_tag_synonym = warp::delete()
+        .and(warp::path("tags"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("synonyms"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(tag_writer.clone())
+        .and(store_filter.clone())
+        .and_then(routes::remove_tag_synonym::<S>);
+
+    let lock_question = warp::put()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("lock"))
+        .and(warp::path::end())
+        .and(moderator.clone())
+        .and(store_filter.clone())
+        .and_then(routes::lock_question::<S>);
+
+    let unlock_question = warp::delete()
+        .and(warp::path("questions"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("lock"))
+        .and(warp::path::end())
+        .and(moderator.clone())
+        .and(store_filter.clone())
+        .and_then(routes::unlock_question::<S>);
+
+    let set_account_status = warp::put()
+        .and(warp::path("accounts"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("status"))
+        .and(warp::path::end())
+        .and(moderator)
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::set_account_status::<S>);
+
+    let set_account_role = warp::put()
+        .and(warp::path("accounts"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("role"))
         .and(warp::path::end())
-        .and(routes::auth())
+        .and(admin)
         .and(store_filter.clone())
-        .and(warp::body::form())
-        .and_then(routes::add_answer);
+        .and(warp::body::json())
+        .and_then(routes::set_account_role::<S>);
 
     let registration = warp::post()
         .and(warp::path("registration"))
         .and(warp::path::end())
         .and(store_filter.clone())
+        .and(mailer_filter.clone())
         .and(warp::body::json())
-        .and_then(routes::register);
+        .and_then(routes::register::<S>);
 
     let login = warp::post()
         .and(warp::path("login"))
         .and(warp::path::end())
         .and(store_filter.clone())
+        .and(token_keys_filter.clone())
+        .and(warp::addr::remote())
+        .and(warp::body::json())
+        .and_then(routes::login::<S>);
+
+    let refresh_token = warp::post()
+        .and(warp::path("token"))
+        .and(warp::path("refresh"))
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and(token_keys_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::refresh_token::<S>);
+
+    let get_token_keys = warp::get()
+        .and(warp::path(".well-known"))
+        .and(warp::path("paseto-keys"))
+        .and(warp::path::end())
+        .and(token_keys_filter.clone())
+        .and_then(routes::get_token_keys);
+
+    let logout = warp::post()
+        .and(warp::path("logout"))
+        .and(warp::path::end())
+        // Any token may revoke itself, whatever its scopes
+        .and(auth.clone())
+        .and(store_filter.clone())
         .and(warp::body::json())
-        .and_then(routes::login);
+        .and_then(routes::logout::<S>);
+
+    let get_user = warp::get()
+        .and(warp::path("users"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and_then(routes::get_user::<S>);
 
-    get_questions
+    let update_profile = warp::patch()
+        .and(warp::path("account"))
+        .and(warp::path::end())
+        .and(account.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::update_profile::<S>);
+
+    let change_password = warp::put()
+        .and(warp::path("account"))
+        .and(warp::path("password"))
+        .and(warp::path::end())
+        .and(account.clone())
+        .and(store_filter.clone())
+        .and(warp::addr::remote())
+        .and(warp::body::json())
+        .and_then(routes::change_password::<S>);
+
+    let request_password_reset = warp::post()
+        .and(warp::path("password-reset"))
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and(mailer_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::request_password_reset::<S>);
+
+    let confirm_password_reset = warp::post()
+        .and(warp::path("password-reset"))
+        .and(warp::path("confirm"))
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::confirm_password_reset::<S>);
+
+    let resend_verification_email = warp::post()
+        .and(warp::path("email-verification"))
+        .and(warp::path::end())
+        .and(account.clone())
+        .and(store_filter.clone())
+        .and(mailer_filter)
+        .and_then(routes::resend_verification_email::<S>);
+
+    let confirm_email = warp::post()
+        .and(warp::path("email-verification"))
+        .and(warp::path("confirm"))
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::confirm_email::<S>);
+
+    let get_api_keys = warp::get()
+        .and(warp::path("account"))
+        .and(warp::path("api-keys"))
+        .and(warp::path::end())
+        .and(account.clone())
+        .and(store_filter.clone())
+        .and_then(routes::get_api_keys::<S>);
+
+    let add_api_key = warp::post()
+        .and(warp::path("account"))
+        .and(warp::path("api-keys"))
+        .and(warp::path::end())
+        .and(account.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::add_api_key::<S>);
+
+    let revoke_api_key = warp::delete()
+        .and(warp::path("account"))
+        .and(warp::path("api-keys"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path::end())
+        .and(account.clone())
+        .and(store_filter.clone())
+        .and_then(routes::revoke_api_key::<S>);
+
+    let oidc_login = warp::get()
+        .and(warp::path("oidc"))
+        .and(warp::path("login"))
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and(oidc_filter.clone())
+        .and_then(routes::oidc_login::<S>);
+
+    let oidc_callback = warp::get()
+        .and(warp::path("oidc"))
+        .and(warp::path("callback"))
+        .and(warp::path::end())
+        .and(warp::query())
+        .and(warp::cookie::optional::<String>("oidc_state"))
+        .and(store_filter.clone())
+        .and(token_keys_filter.clone())
+        .and(oidc_filter)
+        .and_then(routes::oidc_callback::<S>);
+
+    let login_two_factor = warp::post()
+        .and(warp::path("login"))
+        .and(warp::path("two-factor"))
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and(token_keys_filter.clone())
+        .and(warp::addr::remote())
+        .and(warp::body::json())
+        .and_then(routes::login_two_factor::<S>);
+
+    let enroll_two_factor = warp::post()
+        .and(warp::path("account"))
+        .and(warp::path("two-factor"))
+        .and(warp::path::end())
+        .and(account.clone())
+        .and(store_filter.clone())
+        .and_then(routes::enroll_two_factor::<S>);
+
+    let confirm_two_factor = warp::post()
+        .and(warp::path("account"))
+        .and(warp::path("two-factor"))
+        .and(warp::path("confirm"))
+        .and(warp::path::end())
+        .and(account.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::confirm_two_factor::<S>);
+
+    let disable_two_factor = warp::delete()
+        .and(warp::path("account"))
+        .and(warp::path("two-factor"))
+        .and(warp::path::end())
+        .and(account.clone())
+        .and(store_filter.clone())
+        .and(warp::addr::remote())
+        .and(warp::body::json())
+        .and_then(routes::disable_two_factor::<S>);
+
+    // Each group is boxed so the type of the whole filter stays shallow
+    // enough for the compiler to check that it is `Send`
+    let question_routes = get_questions
+        .or(get_question)
         .or(add_question)
         .or(update_question)
         .or(delete_question)
+        .or(restore_question)
+        .or(get_revisions)
+        .or(get_revision_diff)
+        .or(rollback_question)
+        .boxed();
+
+    let answer_routes = get_answers
+        .or(get_answer)
         .or(add_answer)
-        .or(registration)
+        .or(update_answer)
+        .or(delete_answer)
+        .or(accept_answer)
+        .or(unaccept_answer)
+        .boxed();
+
+    let post_routes = get_comments
+        .or(add_comment)
+        .or(update_comment)
+        .or(delete_comment)
+        .or(vote_on_question)
+        .or(retract_question_vote)
+        .or(vote_on_answer)
+        .or(retract_answer_vote)
+        .boxed();
+
+    let tag_routes = get_tags
+        .or(get_tag)
+        .or(update_tag)
+        .or(add_tag_synonym)
+        .or(remove_tag_synonym)
+        .boxed();
+
+    let moderation_routes = lock_question
+        .or(unlock_question)
+        .or(set_account_status)
+        .or(set_account_role)
+        .boxed();
+
+    let account_routes = registration
         .or(login)
+        .or(refresh_token)
+        .or(get_token_keys)
+        .or(logout)
+        .or(get_user)
+        .or(update_profile)
+        .or(change_password)
+        .or(request_password_reset)
+        .or(confirm_password_reset)
+        .or(resend_verification_email)
+        .or(confirm_email)
+        .boxed();
+
+    let api_key_routes = get_api_keys.or(add_api_key).or(revoke_api_key).boxed();
+
+    let two_factor_routes = login_two_factor
+        .or(enroll_two_factor)
+        .or(confirm_two_factor)
+        .or(disable_two_factor)
+        .boxed();
+
+    let oidc_routes = oidc_login.or(oidc_callback).boxed();
+
+    question_routes
+        .or(answer_routes)
+        .or(post_routes)
+        .or(tag_routes)
+        .or(moderation_routes)
+        .or(account_routes)
+        .or(two_factor_routes)
+        .or(api_key_routes)
+        .or(oidc_routes)
         .with(cors)
         .with(warp::trace::request())
         .recover(handle_errors::return_error)
 }
 
-pub async fn setup_store(config: &Config) -> Result<Store, Error> {
-    let store = Store::new(&format!(
+pub async fn setup_store(config: &Config) -> Result<PgStore, Error> {
+    let store = PgStore::new(&format!(
         "postgres://{}:{}@{}:{}/{}",
         config.db_user, config.db_password, config.db_host, config.db_port, config.db_name,
     ))
@@ -142,6 +739,20 @@ pub async fn setup_store(config: &Config) -> Result<Store, Error> {
         .await
         .map_err(Error::MigrationError)?;
 
+    setup_tracing(config);
+
+    Ok(store)
+}
+
+/// Set up a store that lives only as long as the process,
+/// for running the service without a database
+pub fn setup_memory_store(config: &Config) -> MemoryStore {
+    setup_tracing(config);
+
+    MemoryStore::new()
+}
+
+fn setup_tracing(config: &Config) {
     let log_filter = std::env::var("RUST_LOG").unwrap_or_else(|_| {
         format!(
             "{}={},handle_errors={},warp={}",
@@ -160,6 +771,4 @@ pub async fn setup_store(config: &Config) -> Result<Store, Error> {
         // routes' durations!
         .with_span_events(FmtSpan::CLOSE)
         .init();
-
-    Ok(store)
 }
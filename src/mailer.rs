@@ -0,0 +1,262 @@
+use std::fmt::Debug;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use handle_errors::Error;
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use rand::Rng;
+use tracing::{event, Level};
+
+use crate::config::{Config, MailerBackend};
+
+/// An email to a single recipient
+#[derive(Debug, Clone, PartialEq)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivers the emails the service sends, such as password resets
+#[async_trait]
+pub trait Mailer: Debug + Send + Sync {
+    /// Fails with `Error::MailerError` if the email could not be handed off
+    async fn send(&self, email: Email) -> Result<(), Error>;
+}
+
+/// The mailer the routes are handed, whichever backend it is
+pub type SharedMailer = Arc<dyn Mailer>;
+
+/// Pick the mailer the config asks for. Fails with `Error::MailerError`
+/// if the SMTP settings are unusable.
+pub fn from_config(config: &Config) -> Result<SharedMailer, Error> {
+    Ok(match config.mailer {
+        MailerBackend::Log => Arc::new(LogMailer),
+        MailerBackend::File => Arc::new(FileMailer::new(&config.mail_dir)),
+        MailerBackend::Smtp => Arc::new(SmtpMailer::new(config)?),
+    })
+}
+
+/// Writes emails to the log instead of sending them, for local use
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, email: Email) -> Result<(), Error> {
+        event!(
+            Level::INFO,
+            to = email.to,
+            subject = email.subject,
+            "{}",
+            email.body
+        );
+        Ok(())
+    }
+}
+
+/// Writes each email to its own file in a directory, for local use
+/// when the log is too noisy to fish emails out of
+#[derive(Debug, Clone)]
+pub struct FileMailer {
+    dir: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        FileMailer { dir: dir.into() }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: Email) -> Result<(), Error> {
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|err| Error::MailerError(err.to_string()))?;
+
+        // Sorts by the time it was sent, the suffix keeps emails sent
+        // in the same microsecond apart
+        let name = format!(
+            "{}-{:08x}.eml",
+            Utc::now().format("%Y%m%dT%H%M%S%.6f"),
+            rand::thread_rng().gen::<u32>()
+        );
+        let contents = format!(
+            "To: {}\nSubject: {}\n\n{}",
+            email.to, email.subject, email.body
+        );
+
+        tokio::fs::write(self.dir.join(name), contents)
+            .await
+            .map_err(|err| Error::MailerError(err.to_string()))
+    }
+}
+
+/// Sends emails through an SMTP relay, upgrading the connection
+/// with STARTTLS unless `smtp_insecure` is set
+#[derive(Debug, Clone)]
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(config: &Config) -> Result<Self, Error> {
+        let from = config
+            .mail_from
+            .parse::<Mailbox>()
+            .map_err(|err| Error::MailerError(format!("invalid sender address: {}", err)))?;
+
+        let builder = if config.smtp_insecure {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.smtp_host)
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.smtp_host)
+                .map_err(|err| Error::MailerError(err.to_string()))?
+        };
+        let builder = builder.port(config.smtp_port);
+        let builder = match (&config.smtp_username, &config.smtp_password) {
+            (Some(username), Some(password)) => {
+                builder.credentials(Credentials::new(username.clone(), password.clone()))
+            }
+            _ => builder,
+        };
+
+        Ok(SmtpMailer {
+            transport: builder.build(),
+            from,
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: Email) -> Result<(), Error> {
+        let to = email
+            .to
+            .parse::<Mailbox>()
+            .map_err(|err| Error::MailerError(format!("invalid recipient address: {}", err)))?;
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(email.subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(email.body)
+            .map_err(|err| Error::MailerError(err.to_string()))?;
+
+        self.transport
+            .send(message)
+            .await
+            .map(|_| ())
+            .map_err(|err| Error::MailerError(err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn file_mailer_writes_one_file_per_email() {
+        let dir = std::env::temp_dir().join(format!("mailer-{}", rand::random::<u64>()));
+        let mailer = FileMailer::new(&dir);
+
+        for subject in ["one", "two"] {
+            mailer
+                .send(Email {
+                    to: "a@b.c".to_string(),
+                    subject: subject.to_string(),
+                    body: "body".to_string(),
+                })
+                .await
+                .unwrap();
+        }
+
+        let mut contents: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+            .collect();
+        contents.sort();
+        assert_eq!(
+            vec![
+                "To: a@b.c\nSubject: one\n\nbody".to_string(),
+                "To: a@b.c\nSubject: two\n\nbody".to_string(),
+            ],
+            contents
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Accept one SMTP session, answering every command with success,
+    /// and return everything the client sent
+    async fn fake_smtp_relay(listener: tokio::net::TcpListener) -> String {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let mut received = String::new();
+        let mut in_data = false;
+
+        writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            received.push_str(&line);
+            received.push('\n');
+
+            let reply: &[u8] = if in_data {
+                if line != "." {
+                    continue;
+                }
+                in_data = false;
+                b"250 queued\r\n"
+            } else if line.starts_with("EHLO") {
+                b"250 localhost\r\n"
+            } else if line == "DATA" {
+                in_data = true;
+                b"354 go ahead\r\n"
+            } else if line == "QUIT" {
+                writer.write_all(b"221 bye\r\n").await.unwrap();
+                break;
+            } else {
+                b"250 ok\r\n"
+            };
+            writer.write_all(reply).await.unwrap();
+        }
+
+        received
+    }
+
+    #[tokio::test]
+    async fn smtp_mailer_hands_emails_to_the_relay() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let relay = tokio::spawn(fake_smtp_relay(listener));
+
+        let mut config = <Config as clap::Parser>::parse_from(["server"]);
+        config.smtp_host = "127.0.0.1".to_string();
+        config.smtp_port = port;
+        config.smtp_insecure = true;
+        config.mail_from = "Q&A <noreply@example.com>".to_string();
+
+        SmtpMailer::new(&config)
+            .unwrap()
+            .send(Email {
+                to: "a@example.com".to_string(),
+                subject: "Hello".to_string(),
+                body: "body".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let received = relay.await.unwrap();
+        assert!(received.contains("MAIL FROM:<noreply@example.com>"));
+        assert!(received.contains("RCPT TO:<a@example.com>"));
+        assert!(received.contains("Subject: Hello"));
+    }
+}
@@ -0,0 +1,357 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use handle_errors::Error;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use reqwest::{Client, Url};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+
+/// How long a request to the identity provider may take
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What ID tokens may be signed with. HMAC is left out, as it would let
+/// anyone who knows the client secret sign them.
+const ALGORITHMS: [Algorithm; 9] = [
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+/// Where the identity provider is and how the service is registered there
+#[derive(Debug, Clone, PartialEq)]
+pub struct OidcSettings {
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub redirect_uri: String,
+}
+
+/// The parts of the provider's discovery document the login needs
+#[derive(Debug, Clone, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// The claims of an ID token the service looks at, after the signature,
+/// issuer, audience, expiry and nonce were checked
+#[derive(Debug, Clone, Deserialize)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    nonce: Option<String>,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+}
+
+/// Runs the authorization code flow with PKCE against an OpenID Connect
+/// provider, found through its discovery document
+#[derive(Debug)]
+pub struct OidcClient {
+    settings: OidcSettings,
+    metadata: ProviderMetadata,
+    authorization_endpoint: Url,
+    http: Client,
+    /// The provider's signing keys, fetched again when a token is signed
+    /// with a key that isn't among them, as the provider rotates them
+    jwks: RwLock<JwkSet>,
+}
+
+/// The client the routes are handed
+pub type SharedOidcClient = Arc<OidcClient>;
+
+/// Set up the client for the provider the config points to, if it
+/// points to one. Fails with `Error::IdentityProviderError` if the
+/// settings are incomplete or the provider cannot be discovered.
+pub async fn from_config(config: &Config) -> Result<Option<SharedOidcClient>, Error> {
+    let Some(issuer) = &config.oidc_issuer else {
+        return Ok(None);
+    };
+    let (Some(client_id), Some(redirect_uri)) = (&config.oidc_client_id, &config.oidc_redirect_uri)
+    else {
+        return Err(Error::IdentityProviderError(
+            "oidc_client_id and oidc_redirect_uri need to be set along with oidc_issuer"
+                .to_string(),
+        ));
+    };
+
+    let settings = OidcSettings {
+        issuer: issuer.clone(),
+        client_id: client_id.clone(),
+        client_secret: config.oidc_client_secret.clone(),
+        redirect_uri: redirect_uri.clone(),
+    };
+    Ok(Some(Arc::new(OidcClient::discover(settings).await?)))
+}
+
+impl OidcClient {
+    /// Fetch the discovery document and the signing keys of the provider
+    pub async fn discover(settings: OidcSettings) -> Result<Self, Error> {
+        let http = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(Error::ReqwestAPIError)?;
+
+        let issuer = settings.issuer.trim_end_matches('/');
+        let metadata: ProviderMetadata = fetch_json(
+            &http,
+            &format!("{}/.well-known/openid-configuration", issuer),
+        )
+        .await?;
+        // Anyone else answering for the issuer could vouch for any user
+        if metadata.issuer.trim_end_matches('/') != issuer {
+            return Err(Error::IdentityProviderError(format!(
+                "discovery document is for {}",
+                metadata.issuer
+            )));
+        }
+
+        let authorization_endpoint = Url::parse(&metadata.authorization_endpoint)
+            .map_err(|err| Error::IdentityProviderError(err.to_string()))?;
+
+        let jwks = fetch_json(&http, &metadata.jwks_uri).await?;
+        Ok(OidcClient {
+            settings,
+            metadata,
+            authorization_endpoint,
+            http,
+            jwks: RwLock::new(jwks),
+        })
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.metadata.issuer
+    }
+
+    /// Whether the provider sends browsers back over HTTPS, so cookies
+    /// for the callback can be kept to it
+    pub fn redirects_securely(&self) -> bool {
+        self.settings.redirect_uri.starts_with("https://")
+    }
+
+    /// Where to send the browser to log in. The provider hands `state`
+    /// back with the code, puts `nonce` in the ID token and only gives
+    /// out tokens for the code to whoever knows the verifier `code_challenge`
+    /// was made from.
+    pub fn authorization_url(&self, state: &str, nonce: &str, code_challenge: &str) -> String {
+        let mut url = self.authorization_endpoint.clone();
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.settings.client_id)
+            .append_pair("redirect_uri", &self.settings.redirect_uri)
+            .append_pair("scope", "openid email")
+            .append_pair("state", state)
+            .append_pair("nonce", nonce)
+            .append_pair("code_challenge", code_challenge)
+            .append_pair("code_challenge_method", "S256");
+        url.to_string()
+    }
+
+    /// Trade an authorization code for an ID token and check it. Fails
+    /// with `Error::OidcLoginFailed` if the token does not check out.
+    pub async fn exchange_code(
+        &self,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> Result<IdTokenClaims, Error> {
+        let mut request = self.http.post(&self.metadata.token_endpoint).form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &self.settings.redirect_uri),
+            ("client_id", &self.settings.client_id),
+            ("code_verifier", code_verifier),
+        ]);
+        if let Some(secret) = &self.settings.client_secret {
+            request = request.basic_auth(&self.settings.client_id, Some(secret));
+        }
+
+        let res = request
+            .send()
+            .await
+            .map_err(|err| Error::IdentityProviderError(err.to_string()))?;
+        if res.status().is_client_error() {
+            // An unknown, used or stolen code, or a verifier that doesn't match
+            let body = res.text().await.unwrap_or_default();
+            return Err(Error::OidcLoginFailed(format!(
+                "code was refused: {}",
+                body
+            )));
+        }
+        let tokens: TokenResponse = res
+            .error_for_status()
+            .map_err(|err| Error::IdentityProviderError(err.to_string()))?
+            .json()
+            .await
+            .map_err(|err| Error::IdentityProviderError(err.to_string()))?;
+
+        self.validate_id_token(&tokens.id_token, nonce).await
+    }
+
+    async fn validate_id_token(&self, id_token: &str, nonce: &str) -> Result<IdTokenClaims, Error> {
+        let header = jsonwebtoken::decode_header(id_token)
+            .map_err(|err| Error::OidcLoginFailed(format!("malformed ID token: {}", err)))?;
+        if !ALGORITHMS.contains(&header.alg) {
+            return Err(Error::OidcLoginFailed(format!(
+                "ID token is signed with {:?}",
+                header.alg
+            )));
+        }
+
+        let key = match self.decoding_key(header.kid.as_deref())? {
+            Some(key) => key,
+            None => {
+                let jwks = fetch_json(&self.http, &self.metadata.jwks_uri).await?;
+                *self.jwks.write().unwrap() = jwks;
+                self.decoding_key(header.kid.as_deref())?.ok_or_else(|| {
+                    Error::OidcLoginFailed("ID token is signed with an unknown key".to_string())
+                })?
+            }
+        };
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.metadata.issuer]);
+        validation.set_audience(&[&self.settings.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        let claims = jsonwebtoken::decode::<IdTokenClaims>(id_token, &key, &validation)
+            .map_err(|err| Error::OidcLoginFailed(format!("invalid ID token: {}", err)))?
+            .claims;
+
+        // Without this, an ID token from another login could be replayed
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(Error::OidcLoginFailed(
+                "ID token is for another login".to_string(),
+            ));
+        }
+
+        Ok(claims)
+    }
+
+    /// The key `kid` names, or the only key if the token names none
+    fn decoding_key(&self, kid: Option<&str>) -> Result<Option<DecodingKey>, Error> {
+        let jwks = self.jwks.read().unwrap();
+        let jwk = match kid {
+            Some(kid) => jwks.find(kid),
+            None if jwks.keys.len() == 1 => jwks.keys.first(),
+            None => None,
+        };
+
+        jwk.map(DecodingKey::from_jwk)
+            .transpose()
+            .map_err(|err| Error::IdentityProviderError(format!("unusable signing key: {}", err)))
+    }
+}
+
+/// The PKCE code challenge for a code verifier, by the `S256` method
+pub fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+async fn fetch_json<T: DeserializeOwned>(http: &Client, url: &str) -> Result<T, Error> {
+    http.get(url)
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .map_err(|err| Error::IdentityProviderError(err.to_string()))?
+        .json()
+        .await
+        .map_err(|err| Error::IdentityProviderError(err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock_server::{MockServer, IDP_DEFAULT_USER};
+
+    const REDIRECT_URI: &str = "http://localhost:3030/oidc/callback";
+
+    async fn client(mock: &MockServer) -> OidcClient {
+        OidcClient::discover(OidcSettings {
+            issuer: mock.issuer(),
+            client_id: "rust-web-dev".to_string(),
+            client_secret: None,
+            redirect_uri: REDIRECT_URI.to_string(),
+        })
+        .await
+        .unwrap()
+    }
+
+    /// Follow the authorization URL to the code the provider sends back
+    async fn authorize(url: &str) -> String {
+        let res = Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap()
+            .get(url)
+            .send()
+            .await
+            .unwrap();
+        let location = Url::parse(res.headers()["location"].to_str().unwrap()).unwrap();
+        location
+            .query_pairs()
+            .find(|(name, _)| name == "code")
+            .map(|(_, code)| code.into_owned())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn id_tokens_are_checked_against_the_login() {
+        let mock = MockServer::new().await;
+        let handler = mock.oneshot();
+        let client = client(&mock).await;
+
+        let url = client.authorization_url("state", "nonce", &code_challenge("verifier"));
+        let code = authorize(&url).await;
+        let claims = client
+            .exchange_code(&code, "verifier", "nonce")
+            .await
+            .unwrap();
+        assert_eq!(mock.issuer(), claims.iss);
+        assert_eq!(Some(IDP_DEFAULT_USER), claims.email.as_deref());
+        assert!(claims.email_verified);
+
+        // The code was used up already
+        let err = client
+            .exchange_code(&code, "verifier", "nonce")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::OidcLoginFailed(_)));
+
+        let code = authorize(&url).await;
+        let err = client
+            .exchange_code(&code, "another verifier", "nonce")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::OidcLoginFailed(_)));
+
+        let code = authorize(&url).await;
+        let err = client
+            .exchange_code(&code, "verifier", "another nonce")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::OidcLoginFailed(_)));
+
+        let _ = handler.sender.send(());
+    }
+}
@@ -0,0 +1,185 @@
+use handle_errors::Error;
+
+use crate::store::AccountRepository;
+use crate::types::{AccountId, AccountStatus, Actor, Question, Role, Session};
+
+/// Look up the role and status of the account that made the request
+pub async fn actor<S: AccountRepository>(store: &S, session: &Session) -> Result<Actor, Error> {
+    let actor = store.get_actor(&session.account_id).await?;
+
+    Ok(Actor {
+        two_factor: session.two_factor,
+        ..actor
+    })
+}
+
+/// Any active account may ask a question or answer one
+pub fn can_post(actor: &Actor) -> Result<(), Error> {
+    match actor.status {
+        AccountStatus::Active => Ok(()),
+        AccountStatus::Suspended => Err(Error::AccountSuspended),
+    }
+}
+
+/// Only the owner of a question or answer, or a moderator,
+/// may edit or delete it
+pub fn can_modify(actor: &Actor, owner: &AccountId) -> Result<(), Error> {
+    can_post(actor)?;
+
+    if actor.account_id == *owner {
+        Ok(())
+    } else {
+        check_role(actor, Role::Moderator)
+    }
+}
+
+/// Curating shared data like tags, and locking questions,
+/// is left to moderators
+pub fn can_moderate(actor: &Actor) -> Result<(), Error> {
+    can_post(actor)?;
+    check_role(actor, Role::Moderator)
+}
+
+/// Nothing about a locked question changes unless a moderator does it
+pub fn check_unlocked(actor: &Actor, question: &Question) -> Result<(), Error> {
+    if question.locked && check_role(actor, Role::Moderator).is_err() {
+        Err(Error::QuestionLocked)
+    } else {
+        Ok(())
+    }
+}
+
+/// Moderators may suspend or reinstate accounts with a lesser role than
+/// their own, so moderators can only be suspended by admins
+pub fn can_set_status(actor: &Actor, target: &Actor) -> Result<(), Error> {
+    can_moderate(actor)?;
+
+    if actor.role > target.role {
+        Ok(())
+    } else {
+        Err(Error::Unauthorized)
+    }
+}
+
+/// Only admins hand out roles, and not to themselves
+/// so there is always an admin left to undo it
+pub fn can_set_role(actor: &Actor, target: &Actor) -> Result<(), Error> {
+    can_post(actor)?;
+    check_role(actor, Role::Admin)?;
+
+    if actor.account_id != target.account_id {
+        Ok(())
+    } else {
+        Err(Error::Unauthorized)
+    }
+}
+
+/// The rights of moderators and admins only come with a login that
+/// passed a second factor, without one they are those of any user
+fn check_role(actor: &Actor, role: Role) -> Result<(), Error> {
+    if actor.role < role {
+        Err(Error::Unauthorized)
+    } else if !actor.two_factor {
+        Err(Error::TwoFactorRequired)
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn actor(role: Role, status: AccountStatus) -> Actor {
+        Actor {
+            account_id: AccountId(1),
+            role,
+            status,
+            two_factor: true,
+        }
+    }
+
+    fn other(role: Role) -> Actor {
+        Actor {
+            account_id: AccountId(2),
+            role,
+            status: AccountStatus::Active,
+            two_factor: true,
+        }
+    }
+
+    #[test]
+    fn active_accounts_can_post() {
+        assert!(can_post(&actor(Role::User, AccountStatus::Active)).is_ok());
+    }
+
+    #[test]
+    fn suspended_accounts_cannot_post() {
+        let err = can_post(&actor(Role::Moderator, AccountStatus::Suspended)).unwrap_err();
+        assert!(matches!(err, Error::AccountSuspended));
+    }
+
+    #[test]
+    fn owner_can_modify() {
+        let actor = actor(Role::User, AccountStatus::Active);
+        assert!(can_modify(&actor, &AccountId(1)).is_ok());
+    }
+
+    #[test]
+    fn other_user_cannot_modify() {
+        let actor = actor(Role::User, AccountStatus::Active);
+        let err = can_modify(&actor, &AccountId(2)).unwrap_err();
+        assert!(matches!(err, Error::Unauthorized));
+    }
+
+    #[test]
+    fn moderator_can_modify_any_post() {
+        let actor = actor(Role::Moderator, AccountStatus::Active);
+        assert!(can_modify(&actor, &AccountId(2)).is_ok());
+    }
+
+    #[test]
+    fn moderators_without_a_second_factor_only_modify_their_own_posts() {
+        let moderator = Actor {
+            two_factor: false,
+            ..actor(Role::Admin, AccountStatus::Active)
+        };
+        assert!(can_modify(&moderator, &AccountId(1)).is_ok());
+
+        let err = can_modify(&moderator, &AccountId(2)).unwrap_err();
+        assert!(matches!(err, Error::TwoFactorRequired));
+        let err = can_moderate(&moderator).unwrap_err();
+        assert!(matches!(err, Error::TwoFactorRequired));
+        assert!(can_set_role(&moderator, &other(Role::User)).is_err());
+    }
+
+    #[test]
+    fn only_moderators_can_moderate() {
+        assert!(can_moderate(&actor(Role::Moderator, AccountStatus::Active)).is_ok());
+        assert!(can_moderate(&actor(Role::Admin, AccountStatus::Active)).is_ok());
+
+        let err = can_moderate(&actor(Role::User, AccountStatus::Active)).unwrap_err();
+        assert!(matches!(err, Error::Unauthorized));
+    }
+
+    #[test]
+    fn statuses_are_set_from_above() {
+        let moderator = actor(Role::Moderator, AccountStatus::Active);
+        assert!(can_set_status(&moderator, &other(Role::User)).is_ok());
+        assert!(can_set_status(&moderator, &other(Role::Moderator)).is_err());
+
+        let admin = actor(Role::Admin, AccountStatus::Active);
+        assert!(can_set_status(&admin, &other(Role::Moderator)).is_ok());
+        assert!(can_set_status(&admin, &other(Role::Admin)).is_err());
+    }
+
+    #[test]
+    fn only_admins_set_roles_of_others() {
+        let admin = actor(Role::Admin, AccountStatus::Active);
+        assert!(can_set_role(&admin, &other(Role::User)).is_ok());
+        assert!(can_set_role(&admin, &admin).is_err());
+
+        let moderator = actor(Role::Moderator, AccountStatus::Active);
+        assert!(can_set_role(&moderator, &other(Role::User)).is_err());
+    }
+}
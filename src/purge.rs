@@ -0,0 +1,45 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use tokio::task::JoinHandle;
+use tracing::{event, Level};
+
+use crate::store::Store;
+use crate::types::FAILURE_WINDOW_HOURS;
+
+/// How often deleted questions are checked for having outlived the retention
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Hard-delete the questions that were deleted more than `retention` ago,
+/// and forget expired tokens and failed logins that no longer count,
+/// once right away and then every `PURGE_INTERVAL`
+pub fn spawn_purge_job<S: Store>(store: S, retention: chrono::Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            let deleted_before = Utc::now().naive_utc() - retention;
+            match store.purge_deleted_questions(deleted_before).await {
+                Ok(0) => {}
+                Ok(purged) => event!(Level::INFO, purged, "purged deleted questions"),
+                Err(err) => event!(Level::ERROR, "purging deleted questions failed: {}", err),
+            }
+
+            match store.purge_expired_tokens(Utc::now().naive_utc()).await {
+                Ok(0) => {}
+                Ok(purged) => event!(Level::INFO, purged, "purged expired tokens"),
+                Err(err) => event!(Level::ERROR, "purging expired tokens failed: {}", err),
+            }
+
+            let failed_before =
+                Utc::now().naive_utc() - chrono::Duration::hours(FAILURE_WINDOW_HOURS);
+            match store.purge_login_failures(failed_before).await {
+                Ok(0) => {}
+                Ok(purged) => event!(Level::INFO, purged, "purged login failures"),
+                Err(err) => event!(Level::ERROR, "purging login failures failed: {}", err),
+            }
+        }
+    })
+}
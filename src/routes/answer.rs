@@ -1,36 +1,151 @@
+use crate::policy;
 use crate::profanity::check_profanity;
 use crate::store::Store;
 use crate::types::{NewAnswer, Session};
 
+use handle_errors::Error;
 use warp::http::StatusCode;
 use warp::{Rejection, Reply};
 
-use handle_errors::Error;
+pub async fn get_answers<S: Store>(question_id: i32, store: S) -> Result<impl Reply, Rejection> {
+    store
+        .get_question(question_id)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    store
+        .get_answers_for_question(question_id)
+        .await
+        .map(|answers| warp::reply::json(&answers))
+        .map_err(warp::reject::custom)
+}
+
+pub async fn get_answer<S: Store>(
+    question_id: i32,
+    answer_id: i32,
+    store: S,
+) -> Result<impl Reply, Rejection> {
+    store
+        .get_answer(question_id, answer_id)
+        .await
+        .map(|answer| warp::reply::json(&answer))
+        .map_err(warp::reject::custom)
+}
 
-pub async fn add_answer(
+pub async fn add_answer<S: Store>(
+    question_id: i32,
     session: Session,
-    store: Store,
+    store: S,
     new_answer: NewAnswer,
 ) -> Result<impl Reply, Rejection> {
-    let account_id = session.account_id;
-    if !store
-        .is_question_owner(new_answer.question_id.0, &account_id)
-        .await?
-    {
-        return Err(warp::reject::custom(Error::Unauthorized));
-    }
+    let actor = policy::actor(&store, &session).await?;
+    policy::can_post(&actor)?;
+    let question = store.get_question(question_id).await?;
+    policy::check_unlocked(&actor, &question)?;
 
     let content = check_profanity(new_answer.content)
         .await
         .map_err(warp::reject::custom)?;
-    let answer = NewAnswer {
-        content,
-        ..new_answer
-    };
+    let answer = NewAnswer { content };
+
+    store
+        .add_answer(question_id, answer, actor.account_id)
+        .await
+        .map(|answer| warp::reply::json(&answer))
+        .map_err(warp::reject::custom)
+}
+
+pub async fn update_answer<S: Store>(
+    question_id: i32,
+    answer_id: i32,
+    session: Session,
+    store: S,
+    answer: NewAnswer,
+) -> Result<impl Reply, Rejection> {
+    let actor = policy::actor(&store, &session).await?;
+    let existing = store.get_answer(question_id, answer_id).await?;
+    policy::can_modify(&actor, &existing.account_id)?;
+    let question = store.get_question(question_id).await?;
+    policy::check_unlocked(&actor, &question)?;
+
+    let content = check_profanity(answer.content)
+        .await
+        .map_err(warp::reject::custom)?;
+    let answer = NewAnswer { content };
 
     store
-        .add_answer(answer, account_id)
+        .update_answer(question_id, answer_id, answer)
         .await
-        .map(|_| warp::reply::with_status("Answer added", StatusCode::OK))
+        .map(|answer| warp::reply::json(&answer))
         .map_err(warp::reject::custom)
 }
+
+pub async fn delete_answer<S: Store>(
+    question_id: i32,
+    answer_id: i32,
+    session: Session,
+    store: S,
+) -> Result<impl Reply, Rejection> {
+    let actor = policy::actor(&store, &session).await?;
+    let existing = store.get_answer(question_id, answer_id).await?;
+    policy::can_modify(&actor, &existing.account_id)?;
+    let question = store.get_question(question_id).await?;
+    policy::check_unlocked(&actor, &question)?;
+
+    store
+        .delete_answer(question_id, answer_id)
+        .await
+        .map(|_| warp::reply::with_status(format!("Answer {} deleted", answer_id), StatusCode::OK))
+        .map_err(warp::reject::custom)
+}
+
+pub async fn accept_answer<S: Store>(
+    question_id: i32,
+    answer_id: i32,
+    session: Session,
+    store: S,
+) -> Result<impl Reply, Rejection> {
+    check_question_owner(question_id, &session, &store).await?;
+
+    store
+        .accept_answer(question_id, answer_id)
+        .await
+        .map(|question| warp::reply::json(&question))
+        .map_err(warp::reject::custom)
+}
+
+pub async fn unaccept_answer<S: Store>(
+    question_id: i32,
+    answer_id: i32,
+    session: Session,
+    store: S,
+) -> Result<impl Reply, Rejection> {
+    check_question_owner(question_id, &session, &store).await?;
+
+    store
+        .unaccept_answer(question_id, answer_id)
+        .await
+        .map(|question| warp::reply::json(&question))
+        .map_err(warp::reject::custom)
+}
+
+/// Only the owner of a question may pick the answer that solved it
+async fn check_question_owner<S: Store>(
+    question_id: i32,
+    session: &Session,
+    store: &S,
+) -> Result<(), Error> {
+    let actor = policy::actor(store, session).await?;
+    policy::can_post(&actor)?;
+
+    let question = store.get_question(question_id).await?;
+    policy::check_unlocked(&actor, &question)?;
+    if store
+        .is_question_owner(question_id, &actor.account_id)
+        .await?
+    {
+        Ok(())
+    } else {
+        Err(Error::Unauthorized)
+    }
+}
@@ -0,0 +1,62 @@
+use warp::http::StatusCode;
+use warp::{Rejection, Reply};
+
+use crate::store::Store;
+use crate::types::{ApiKeyId, CreatedApiKey, NewApiKey, Scope, Session};
+
+use super::authentication::{hash_token, random_token, requested_scopes, API_KEY_PREFIX};
+
+/// How many characters of a key after `API_KEY_PREFIX` are kept to tell
+/// it apart from the account's other keys
+const API_KEY_VISIBLE_CHARS: usize = 6;
+
+/// The API keys of the account making the request, without the keys
+pub async fn get_api_keys<S: Store>(session: Session, store: S) -> Result<impl Reply, Rejection> {
+    let api_keys = store
+        .get_api_keys(&session.account_id)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    Ok(warp::reply::json(&api_keys))
+}
+
+/// Mint an API key for the account making the request. The answer is
+/// the only place the key shows up, only its hash is kept. A session
+/// limited to some scopes can only mint keys within them.
+pub async fn add_api_key<S: Store>(
+    session: Session,
+    store: S,
+    new_api_key: NewApiKey,
+) -> Result<impl Reply, Rejection> {
+    let new_api_key = new_api_key.validate().map_err(warp::reject::custom)?;
+    requested_scopes(
+        session.scopes.as_deref(),
+        Some(&Scope::format_list(&new_api_key.scopes)),
+    )
+    .map_err(warp::reject::custom)?;
+
+    let key = format!("{}{}", API_KEY_PREFIX, random_token(32));
+    let prefix = &key[..API_KEY_PREFIX.len() + API_KEY_VISIBLE_CHARS];
+    let api_key = store
+        .add_api_key(&session.account_id, new_api_key, prefix, &hash_token(&key))
+        .await
+        .map_err(warp::reject::custom)?;
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&CreatedApiKey { api_key, key }),
+        StatusCode::CREATED,
+    ))
+}
+
+pub async fn revoke_api_key<S: Store>(
+    api_key_id: i32,
+    session: Session,
+    store: S,
+) -> Result<impl Reply, Rejection> {
+    store
+        .revoke_api_key(&session.account_id, ApiKeyId(api_key_id))
+        .await
+        .map_err(warp::reject::custom)?;
+
+    Ok(warp::reply::json(&"API key revoked".to_string()))
+}
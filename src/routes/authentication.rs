@@ -1,20 +1,56 @@
-use std::env;
 use std::future;
+use std::net::SocketAddr;
 
 use warp::{Filter, Rejection, Reply};
 
-use crate::store::Store;
-use crate::types::{Account, AccountId, Session};
+use crate::mailer::SharedMailer;
+use crate::store::{Store, TokenRepository};
+use crate::token_keys::{SharedTokenKeys, TokenKeys};
+use crate::types::{
+    account_key, ip_key, normalize_email, Account, AccountId, AccountStatus, Actor, Login,
+    RefreshRequest, RefreshToken, Role, Scope, Session, TokenPair, ACCOUNT_FREE_FAILURES,
+    FAILURE_WINDOW_HOURS, IP_FREE_FAILURES,
+};
 
 use argon2::{self, Config};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
 use chrono::prelude::*;
 use rand::Rng;
+use sha2::{Digest, Sha256};
+use tracing::{event, Level};
 
 use handle_errors::Error;
 
-pub async fn register(store: Store, account: Account) -> Result<impl Reply, Rejection> {
+use super::two_factor::{has_two_factor, login_challenge};
+use super::verification::send_verification_email;
+
+/// How long an access token is accepted, clients refresh it before then
+const ACCESS_TOKEN_TTL_MINUTES: i64 = 15;
+/// How long a refresh token can be traded for a new pair of tokens
+const REFRESH_TOKEN_TTL_DAYS: i64 = 30;
+
+/// What every API key starts with, to tell them apart from access tokens
+pub(super) const API_KEY_PREFIX: &str = "rwd_";
+
+/// Target of security events worth keeping apart from the rest of the log
+pub(super) const AUDIT: &str = concat!(env!("CARGO_CRATE_NAME"), "::audit");
+
+/// Hash checked against when the email belongs to no account, so that
+/// it takes as long as a wrong password. It is a constant rather than
+/// hashed on first use, which would make that first login slower.
+const DUMMY_HASH: &str = "$argon2id$v=19$m=19456,t=2,p=1$bm8gYWNjb3VudCB1c2VzIHRoaXMgZW1haWwsIHNhbHQ$LzbYUEGzpPwV+jpUlFNSADUBAwKmxphk2xwmvNDWBz8";
+
+/// Create an account and mail it a token to verify its email address with
+pub async fn register<S: Store>(
+    store: S,
+    mailer: SharedMailer,
+    account: Account,
+) -> Result<impl Reply, Rejection> {
+    let email = normalize_email(&account.email).map_err(warp::reject::custom)?;
     let hashed_password = hashed_password(account.password.as_bytes());
     let account = Account {
+        email: email.clone(),
         password: hashed_password,
         ..account
     };
@@ -22,8 +58,22 @@ pub async fn register(store: Store, account: Account) -> Result<impl Reply, Reje
     store
         .add_account(account)
         .await
-        .map(|_| warp::reply::json(&"Account added".to_string()))
-        .map_err(warp::reject::custom)
+        .map_err(warp::reject::custom)?;
+
+    let account = store
+        .get_account(email)
+        .await
+        .map_err(warp::reject::custom)?;
+    send_verification_email(
+        &store,
+        &mailer,
+        account.id.expect("id not found"),
+        account.email,
+    )
+    .await
+    .map_err(warp::reject::custom)?;
+
+    Ok(warp::reply::json(&"Account added".to_string()))
 }
 
 pub fn hashed_password(password: &[u8]) -> String {
@@ -32,78 +82,971 @@ pub fn hashed_password(password: &[u8]) -> String {
     argon2::hash_encoded(password, &salt, &config).unwrap()
 }
 
-pub async fn login(store: Store, login: Account) -> Result<impl Reply, Rejection> {
-    let account = store
-        .get_account(login.email)
+/// Failed logins are counted per email and per client IP, and either
+/// locks out for a while once there were too many. An unknown email
+/// fails exactly like a wrong password, down to the time it takes, so
+/// logging in cannot be used to find out who has an account. Accounts
+/// with two-factor authentication get a challenge instead of tokens,
+/// to answer at `POST /login/two-factor`, which takes the `scope` then.
+pub async fn login<S: Store>(
+    store: S,
+    token_keys: SharedTokenKeys,
+    remote: Option<SocketAddr>,
+    login: Login,
+) -> Result<impl Reply, Rejection> {
+    let scopes = requested_scopes(None, login.scope.as_deref()).map_err(warp::reject::custom)?;
+
+    // Nobody can have registered with an invalid email
+    let email = normalize_email(&login.email).ok();
+
+    let keys = login_keys(email.as_deref().unwrap_or(login.email.trim()), remote);
+    check_login_lockout(&store, &keys)
         .await
         .map_err(warp::reject::custom)?;
 
-    match verify_password(&account.password, login.password.as_bytes()) {
-        Ok(verified) => {
-            if verified {
-                Ok(warp::reply::json(&issue_token(
-                    account.id.expect("id not found"),
-                )))
-            } else {
-                Err(warp::reject::custom(Error::WrongPassword))
+    let account = match email {
+        Some(email) => match store.get_account(email).await {
+            Ok(account) => Some(account),
+            Err(Error::AccountNotFound) => None,
+            Err(e) => return Err(warp::reject::custom(e)),
+        },
+        None => None,
+    };
+
+    let hash = match &account {
+        Some(account) => account.password.as_str(),
+        None => DUMMY_HASH,
+    };
+    let verified = verify_password(hash, login.password.as_bytes())
+        .map_err(|e| warp::reject::custom(Error::ArgonLibraryError(e)))?;
+
+    match account {
+        Some(account) if verified => {
+            let account_id = account.id.expect("id not found");
+            // Failures are only cleared once the second factor passed too
+            if has_two_factor(&store, &account_id)
+                .await
+                .map_err(warp::reject::custom)?
+            {
+                let challenge = login_challenge(&store, account_id)
+                    .await
+                    .map_err(warp::reject::custom)?;
+                return Ok(warp::reply::json(&challenge));
             }
+
+            store
+                .clear_login_failures(&keys[0].0)
+                .await
+                .map_err(warp::reject::custom)?;
+
+            let actor = store
+                .get_actor(&account_id)
+                .await
+                .map_err(warp::reject::custom)?;
+            let tokens = issue_tokens(
+                &store,
+                &token_keys,
+                &actor,
+                random_token(16),
+                false,
+                scopes.clone(),
+                scopes,
+            )
+            .await
+            .map_err(warp::reject::custom)?;
+            Ok(warp::reply::json(&tokens))
         }
+        _ => {
+            record_login_failure(&store, &keys)
+                .await
+                .map_err(warp::reject::custom)?;
+            Err(warp::reject::custom(Error::WrongPassword))
+        }
+    }
+}
 
-        Err(e) => Err(warp::reject::custom(Error::ArgonLibraryError(e))),
+/// The keys failed logins to an email from `remote` are counted under,
+/// each with the failures it may have for free. The account's comes first.
+pub(super) fn login_keys(email: &str, remote: Option<SocketAddr>) -> Vec<(String, i32)> {
+    let mut keys = vec![(account_key(email), ACCOUNT_FREE_FAILURES)];
+    if let Some(remote) = remote {
+        keys.push((ip_key(remote.ip()), IP_FREE_FAILURES));
     }
+    keys
 }
 
-fn verify_password(hash: &str, password: &[u8]) -> Result<bool, argon2::Error> {
+/// Fails with `Error::LoginLocked` if any of the keys is locked out
+pub(super) async fn check_login_lockout<S: Store>(
+    store: &S,
+    keys: &[(String, i32)],
+) -> Result<(), Error> {
+    let now = Utc::now().naive_utc();
+
+    for (key, free_failures) in keys {
+        let locked_until = store
+            .get_login_failures(key)
+            .await?
+            .and_then(|failures| failures.locked_until(*free_failures))
+            .filter(|until| *until > now);
+
+        if let Some(until) = locked_until {
+            event!(target: AUDIT, Level::WARN, key, "login refused while locked out");
+            // Round up, so the client never retries a moment too early
+            let seconds = ((until - now).num_milliseconds() + 999) / 1000;
+            return Err(Error::LoginLocked(seconds));
+        }
+    }
+
+    Ok(())
+}
+
+pub(super) async fn record_login_failure<S: Store>(
+    store: &S,
+    keys: &[(String, i32)],
+) -> Result<(), Error> {
+    let now = Utc::now().naive_utc();
+    let reset_before = now - chrono::Duration::hours(FAILURE_WINDOW_HOURS);
+
+    for (key, free_failures) in keys {
+        let failures = store.record_login_failure(key, now, reset_before).await?;
+        event!(
+            target: AUDIT,
+            Level::WARN,
+            key,
+            failures = failures.failures,
+            locked = failures.locked_until(*free_failures).is_some(),
+            "login failed"
+        );
+    }
+
+    Ok(())
+}
+
+/// Trade a refresh token for a new access token and the refresh token
+/// that replaces it. The access token carries the account's current role,
+/// and is limited to the `scope` of the request if it has one.
+pub async fn refresh_token<S: Store>(
+    store: S,
+    token_keys: SharedTokenKeys,
+    request: RefreshRequest,
+) -> Result<impl Reply, Rejection> {
+    let token = store
+        .use_refresh_token(&hash_token(&request.refresh_token))
+        .await
+        .map_err(warp::reject::custom)?;
+
+    let actor = store
+        .get_actor(&token.account_id)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    let scopes = requested_scopes(token.scopes.as_deref(), request.scope.as_deref())
+        .map_err(warp::reject::custom)?;
+    issue_tokens(
+        &store,
+        &token_keys,
+        &actor,
+        token.family,
+        token.two_factor,
+        token.scopes,
+        scopes,
+    )
+    .await
+    .map(|tokens| warp::reply::json(&tokens))
+    .map_err(warp::reject::custom)
+}
+
+/// Revoke the refresh token, along with every token rotated from the
+/// same login, and the access token the request was made with
+pub async fn logout<S: Store>(
+    session: Session,
+    store: S,
+    request: RefreshRequest,
+) -> Result<impl Reply, Rejection> {
+    store
+        .revoke_refresh_token_family(&hash_token(&request.refresh_token), &session.account_id)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    if let Some(jti) = &session.jti {
+        store
+            .revoke_access_token(jti, session.exp.naive_utc())
+            .await
+            .map_err(warp::reject::custom)?;
+    }
+
+    Ok(warp::reply::json(&"Logged out".to_string()))
+}
+
+pub(super) fn verify_password(hash: &str, password: &[u8]) -> Result<bool, argon2::Error> {
     argon2::verify_encoded(hash, password)
 }
 
-fn issue_token(account_id: AccountId) -> String {
-    let key = env::var("PASETO_KEY").unwrap();
+fn issue_token(
+    token_keys: &TokenKeys,
+    account_id: AccountId,
+    role: Role,
+    two_factor: bool,
+    scopes: Option<&[Scope]>,
+) -> String {
     let current_date_time = Utc::now();
-    let dt = current_date_time + chrono::Duration::days(1);
-
-    paseto::tokens::PasetoBuilder::new()
-        .set_encryption_key(key.as_bytes())
-        .set_expiration(&dt)
-        .set_claim("account_id", serde_json::json!(account_id))
-        .build()
-        .expect("Failed to construct paseto token w/ builder!")
-}
-
-pub fn verify_token(token: String) -> Result<Session, Error> {
-    let key = env::var("PASETO_KEY").unwrap();
-    let token = paseto::tokens::validate_local_token(
-        &token,
-        None,
-        key.as_bytes(),
-        &paseto::tokens::TimeBackend::Chrono,
-    )
-    .map_err(|_| Error::CannotDecryptToken)?;
+    let dt = current_date_time + chrono::Duration::minutes(ACCESS_TOKEN_TTL_MINUTES);
+
+    token_keys.issue(&serde_json::json!({
+        "exp": dt,
+        "account_id": account_id,
+        "role": role,
+        "jti": random_token(16),
+        "two_factor": two_factor,
+        // Null for tokens that may do all the account may
+        "scopes": scopes,
+    }))
+}
+
+/// The public keys access tokens can be checked with, so other services
+/// can accept them without asking us
+pub async fn get_token_keys(token_keys: SharedTokenKeys) -> Result<impl Reply, Rejection> {
+    Ok(warp::reply::json(&serde_json::json!({
+        "keys": token_keys.public_keys()
+    })))
+}
+
+/// Issue an access token and a refresh token in `family`, storing only
+/// the hash of the refresh token. `two_factor` tells whether the login
+/// passed a second factor. The refresh token is limited to `granted`
+/// and the access token to `scopes`, `None` meaning no limit. Fails
+/// with `Error::AccountSuspended` for suspended accounts, whichever way
+/// they logged in.
+pub(super) async fn issue_tokens<S: Store>(
+    store: &S,
+    token_keys: &TokenKeys,
+    actor: &Actor,
+    family: String,
+    two_factor: bool,
+    granted: Option<Vec<Scope>>,
+    scopes: Option<Vec<Scope>>,
+) -> Result<TokenPair, Error> {
+    if actor.status == AccountStatus::Suspended {
+        return Err(Error::AccountSuspended);
+    }
+
+    let refresh_token = random_token(32);
+    store
+        .add_refresh_token(RefreshToken {
+            token_hash: hash_token(&refresh_token),
+            family,
+            account_id: actor.account_id,
+            expires_on: Utc::now().naive_utc() + chrono::Duration::days(REFRESH_TOKEN_TTL_DAYS),
+            two_factor,
+            scopes: granted,
+        })
+        .await?;
+
+    Ok(TokenPair {
+        access_token: issue_token(
+            token_keys,
+            actor.account_id,
+            actor.role,
+            two_factor,
+            scopes.as_deref(),
+        ),
+        expires_in: ACCESS_TOKEN_TTL_MINUTES * 60,
+        refresh_token,
+        scope: scopes.as_deref().map(Scope::format_list),
+    })
+}
+
+/// The scopes a `scope` parameter asks for, which have to be among the
+/// `granted` ones if those are limited. Without one, it is all of them.
+pub(super) fn requested_scopes(
+    granted: Option<&[Scope]>,
+    requested: Option<&str>,
+) -> Result<Option<Vec<Scope>>, Error> {
+    let Some(requested) = requested else {
+        return Ok(granted.map(<[Scope]>::to_vec));
+    };
+
+    let scopes = Scope::parse_list(requested)?;
+    if let Some(scope) =
+        granted.and_then(|granted| scopes.iter().find(|scope| !granted.contains(scope)))
+    {
+        return Err(Error::InvalidContent(format!(
+            "scope `{}` was not granted",
+            scope.as_db()
+        )));
+    }
+
+    Ok(Some(scopes))
+}
+
+/// `len` random bytes, encoded to be safe in URLs and JSON
+pub(super) fn random_token(len: usize) -> String {
+    let mut bytes = vec![0u8; len];
+    rand::thread_rng().fill(&mut bytes[..]);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Refresh and password reset tokens are random enough that a plain hash
+/// is as good as a password hash, and it lets them be looked up by it
+pub(super) fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Check an access token against the keys and that it was not revoked
+/// by logging out
+pub async fn verify_token<S: TokenRepository>(
+    token: String,
+    token_keys: &TokenKeys,
+    store: &S,
+) -> Result<Session, Error> {
+    let token = token_keys.verify(&token)?;
+
+    let session =
+        serde_json::from_value::<Session>(token).map_err(|_| Error::CannotDecryptToken)?;
+
+    if let Some(jti) = &session.jti {
+        if store.is_access_token_revoked(jti).await? {
+            return Err(Error::TokenRevoked);
+        }
+    }
+
+    Ok(session)
+}
+
+/// Look up an API key and make a session limited to its scopes. The
+/// session lasts for the request, the key until it is revoked.
+pub async fn verify_api_key<S: Store>(key: &str, store: &S) -> Result<Session, Error> {
+    let api_key = store
+        .use_api_key(&hash_token(key), Utc::now().naive_utc())
+        .await?;
+    let actor = store.get_actor(&api_key.account_id).await?;
+
+    Ok(Session {
+        exp: Utc::now(),
+        account_id: api_key.account_id,
+        role: actor.role,
+        jti: None,
+        two_factor: false,
+        scopes: Some(api_key.scopes),
+    })
+}
+
+/// Takes an access token or an API key as `Authorization: Bearer <token>`,
+/// or an API key as the `X-API-Key` header. Requests without either
+/// are rejected with `Error::MissingCredentials`.
+pub fn auth<S: Store>(
+    store: S,
+    token_keys: SharedTokenKeys,
+) -> impl Filter<Extract = (Session,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("X-API-Key")
+        .and(warp::header::optional::<String>("Authorization"))
+        .and_then(
+            move |api_key: Option<String>, authorization: Option<String>| {
+                let store = store.clone();
+                let token_keys = token_keys.clone();
+                async move {
+                    let session = match (api_key, authorization) {
+                        (Some(key), _) => verify_api_key(&key, &store).await,
+                        (None, Some(value)) => match bearer_token(&value) {
+                            key if key.starts_with(API_KEY_PREFIX) => {
+                                verify_api_key(key, &store).await
+                            }
+                            token => verify_token(token.to_string(), &token_keys, &store).await,
+                        },
+                        (None, None) => Err(Error::MissingCredentials),
+                    };
+                    session.map_err(warp::reject::custom)
+                }
+            },
+        )
+}
+
+/// The token of an `Authorization` header using the `Bearer` scheme,
+/// whose name is case-insensitive. Clients from before the scheme was
+/// required send the bare token, which is taken as it is.
+fn bearer_token(authorization: &str) -> &str {
+    match authorization.trim().split_once(' ') {
+        Some((scheme, token)) if scheme.eq_ignore_ascii_case("bearer") => token.trim(),
+        _ => authorization.trim(),
+    }
+}
+
+/// Like `filter`, but also rejects sessions limited to scopes that
+/// don't include `scope`
+pub fn require_scope<F>(
+    filter: F,
+    scope: Scope,
+) -> impl Filter<Extract = (Session,), Error = Rejection> + Clone
+where
+    F: Filter<Extract = (Session,), Error = Rejection> + Clone,
+{
+    filter.and_then(move |session: Session| {
+        future::ready(if session.allows(scope) {
+            Ok(session)
+        } else {
+            Err(warp::reject::custom(Error::InsufficientScope(
+                scope.as_db(),
+            )))
+        })
+    })
+}
 
-    serde_json::from_value::<Session>(token).map_err(|_| Error::CannotDecryptToken)
+/// Like `auth()`, but also rejects accounts that have not verified their
+/// email address yet
+pub fn require_verified_email<S: Store>(
+    store: S,
+    token_keys: SharedTokenKeys,
+) -> impl Filter<Extract = (Session,), Error = Rejection> + Clone {
+    auth(store.clone(), token_keys).and_then(move |session: Session| {
+        let store = store.clone();
+        async move {
+            match store.is_email_verified(&session.account_id).await {
+                Ok(true) => Ok(session),
+                Ok(false) => Err(warp::reject::custom(Error::EmailNotVerified)),
+                Err(e) => Err(warp::reject::custom(e)),
+            }
+        }
+    })
 }
 
-pub fn auth() -> impl Filter<Extract = (Session,), Error = Rejection> + Clone {
-    warp::header::<String>("Authorization").and_then(|token: String| match verify_token(token) {
-        Ok(t) => future::ready(Ok(t)),
-        Err(_) => future::ready(Err(warp::reject::reject())),
+/// Like `auth()`, but also rejects tokens issued to a lesser role. The
+/// role in the token is only as recent as the login, so handlers still
+/// check the account's current role before acting on it. Moderator and
+/// admin routes also take the `admin` scope and a login that passed a
+/// second factor.
+pub fn require_role<S: Store>(
+    store: S,
+    token_keys: SharedTokenKeys,
+    role: Role,
+) -> impl Filter<Extract = (Session,), Error = Rejection> + Clone {
+    auth(store, token_keys).and_then(move |session: Session| {
+        future::ready(if session.role < role {
+            Err(warp::reject::custom(Error::Unauthorized))
+        } else if role >= Role::Moderator && !session.allows(Scope::Admin) {
+            Err(warp::reject::custom(Error::InsufficientScope(
+                Scope::Admin.as_db(),
+            )))
+        } else if role >= Role::Moderator && !session.two_factor {
+            Err(warp::reject::custom(Error::TwoFactorRequired))
+        } else {
+            Ok(session)
+        })
     })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::routes::api_key::add_api_key;
+    use crate::store::{AccountRepository, ApiKeyRepository, LoginAttemptRepository, MemoryStore};
+    use crate::types::NewApiKey;
+    use std::sync::Arc;
 
     #[tokio::test]
     async fn post_questions_auth() {
-        env::set_var("PASETO_KEY", "RANDOM WORDS WINTER MACINTOSH PC");
-        let token = issue_token(AccountId(3));
+        let token_keys = Arc::new(TokenKeys::generate());
+        let token = issue_token(&token_keys, AccountId(3), Role::Moderator, false, None);
+
+        let filter = auth(MemoryStore::new(), token_keys.clone());
+        let res = warp::test::request()
+            .header("Authorization", token)
+            .filter(&filter);
+
+        let session = res.await.unwrap();
+        assert_eq!(AccountId(3), session.account_id);
+        assert_eq!(Role::Moderator, session.role);
+    }
+
+    #[tokio::test]
+    async fn takes_bearer_tokens_and_challenges_without_them() {
+        let token_keys = Arc::new(TokenKeys::generate());
+        let filter = auth(MemoryStore::new(), token_keys.clone())
+            .map(|session: Session| session.account_id.0.to_string())
+            .recover(handle_errors::return_error);
+
+        let token = issue_token(&token_keys, AccountId(3), Role::User, false, None);
+        for value in [format!("Bearer {}", token), format!("bearer  {} ", token)] {
+            let res = warp::test::request()
+                .header("Authorization", value)
+                .reply(&filter)
+                .await;
+            assert_eq!(200, res.status());
+        }
+
+        let res = warp::test::request().reply(&filter).await;
+        assert_eq!(401, res.status());
+        assert_eq!(
+            "Bearer realm=\"rust-web-dev\"",
+            res.headers()["www-authenticate"]
+        );
+
+        let res = warp::test::request()
+            .header("Authorization", "Bearer not-a-token")
+            .reply(&filter)
+            .await;
+        assert_eq!(401, res.status());
+        assert_eq!(
+            "Bearer realm=\"rust-web-dev\", error=\"invalid_token\"",
+            res.headers()["www-authenticate"]
+        );
+    }
+
+    #[tokio::test]
+    async fn require_role_rejects_lesser_roles() {
+        let token_keys = Arc::new(TokenKeys::generate());
+        let filter = require_role(MemoryStore::new(), token_keys.clone(), Role::Moderator);
 
-        let filter = auth();
+        let token = issue_token(&token_keys, AccountId(3), Role::Admin, true, None);
         let res = warp::test::request()
             .header("Authorization", token)
             .filter(&filter);
+        assert!(res.await.is_ok());
+
+        let token = issue_token(&token_keys, AccountId(3), Role::User, true, None);
+        let res = warp::test::request()
+            .header("Authorization", token)
+            .filter(&filter);
+        assert!(res.await.is_err());
+    }
+
+    #[tokio::test]
+    async fn require_role_takes_a_second_factor_for_moderators() {
+        let token_keys = Arc::new(TokenKeys::generate());
+        let filter = require_role(MemoryStore::new(), token_keys.clone(), Role::Moderator);
+
+        let token = issue_token(&token_keys, AccountId(3), Role::Moderator, false, None);
+        let rejection = warp::test::request()
+            .header("Authorization", token)
+            .filter(&filter)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            rejection.find::<Error>(),
+            Some(Error::TwoFactorRequired)
+        ));
+
+        let filter = require_role(MemoryStore::new(), token_keys.clone(), Role::User);
+        let token = issue_token(&token_keys, AccountId(3), Role::Moderator, false, None);
+        let res = warp::test::request()
+            .header("Authorization", token)
+            .filter(&filter);
+        assert!(res.await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn require_role_takes_the_admin_scope() {
+        let token_keys = Arc::new(TokenKeys::generate());
+        let filter = require_role(MemoryStore::new(), token_keys.clone(), Role::Moderator);
+
+        let token = issue_token(
+            &token_keys,
+            AccountId(3),
+            Role::Admin,
+            true,
+            Some(&[Scope::VotesWrite]),
+        );
+        let rejection = warp::test::request()
+            .header("Authorization", format!("Bearer {}", token))
+            .filter(&filter)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            rejection.find::<Error>(),
+            Some(Error::InsufficientScope("admin"))
+        ));
+
+        let token = issue_token(
+            &token_keys,
+            AccountId(3),
+            Role::Admin,
+            true,
+            Some(&[Scope::Admin]),
+        );
+        let res = warp::test::request()
+            .header("Authorization", format!("Bearer {}", token))
+            .filter(&filter);
+        assert!(res.await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn tokens_can_be_limited_to_scopes() {
+        let token_keys = Arc::new(TokenKeys::generate());
+        let store = MemoryStore::new();
+        store
+            .add_account(Account {
+                id: None,
+                email: "a@b.c".to_string(),
+                password: hashed_password(b"password"),
+            })
+            .await
+            .unwrap();
+
+        let login = Login {
+            email: "a@b.c".to_string(),
+            password: "password".to_string(),
+            scope: Some("votes:write questions:write".to_string()),
+        };
+        let reply = super::login(store.clone(), token_keys.clone(), None, login)
+            .await
+            .unwrap();
+        let body = warp::hyper::body::to_bytes(reply.into_response().into_body())
+            .await
+            .unwrap();
+        let tokens: TokenPair = serde_json::from_slice(&body).unwrap();
+        assert_eq!(Some("questions:write votes:write"), tokens.scope.as_deref());
+
+        let poster = require_scope(
+            auth(store.clone(), token_keys.clone()),
+            Scope::QuestionsWrite,
+        );
+        let res = warp::test::request()
+            .header("Authorization", format!("Bearer {}", tokens.access_token))
+            .filter(&poster);
+        assert!(res.await.is_ok());
+        let account = require_scope(auth(store.clone(), token_keys.clone()), Scope::Account);
+        let res = warp::test::request()
+            .header("Authorization", format!("Bearer {}", tokens.access_token))
+            .filter(&account);
+        assert!(res.await.is_err());
+
+        // Refreshing narrows the access token, but never widens it
+        let request = RefreshRequest {
+            refresh_token: tokens.refresh_token,
+            scope: Some("account".to_string()),
+        };
+        let rejection = refresh_token(store.clone(), token_keys.clone(), request)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            rejection.find::<Error>(),
+            Some(Error::InvalidContent(_))
+        ));
+        assert!(requested_scopes(Some(&[Scope::VotesWrite]), None)
+            .unwrap()
+            .is_some_and(|scopes| scopes == [Scope::VotesWrite]));
+    }
+
+    #[tokio::test]
+    async fn api_keys_are_limited_to_their_scopes() {
+        let token_keys = Arc::new(TokenKeys::generate());
+        let store = MemoryStore::new();
+        store
+            .add_account(Account {
+                id: None,
+                email: "a@b.c".to_string(),
+                password: "password".to_string(),
+            })
+            .await
+            .unwrap();
+        let key = format!("{}secret", API_KEY_PREFIX);
+        let new_api_key = NewApiKey {
+            name: "ci".to_string(),
+            scopes: vec![Scope::VotesWrite],
+        };
+        store
+            .add_api_key(&AccountId(1), new_api_key, "rwd_se", &hash_token(&key))
+            .await
+            .unwrap();
+
+        let voter = require_scope(auth(store.clone(), token_keys.clone()), Scope::VotesWrite);
+        let session = warp::test::request()
+            .header("X-API-Key", &key)
+            .filter(&voter)
+            .await
+            .unwrap();
+        assert_eq!(AccountId(1), session.account_id);
+        assert!(warp::test::request()
+            .header("Authorization", format!("Bearer {}", key))
+            .filter(&voter)
+            .await
+            .is_ok());
+
+        let poster = require_scope(
+            auth(store.clone(), token_keys.clone()),
+            Scope::QuestionsWrite,
+        );
+        let rejection = warp::test::request()
+            .header("X-API-Key", &key)
+            .filter(&poster)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            rejection.find::<Error>(),
+            Some(Error::InsufficientScope("questions:write"))
+        ));
+
+        let api_keys = store.get_api_keys(&AccountId(1)).await.unwrap();
+        assert!(api_keys[0].last_used_on.is_some());
+        store
+            .revoke_api_key(&AccountId(1), api_keys[0].id)
+            .await
+            .unwrap();
+        assert!(warp::test::request()
+            .header("X-API-Key", &key)
+            .filter(&voter)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn api_keys_stay_within_the_scopes_of_their_session() {
+        let token_keys = Arc::new(TokenKeys::generate());
+        let store = MemoryStore::new();
+        let token = issue_token(
+            &token_keys,
+            AccountId(1),
+            Role::User,
+            false,
+            Some(&[Scope::Account]),
+        );
+        let session = verify_token(token, &token_keys, &store).await.unwrap();
+
+        let rejection = add_api_key(
+            session,
+            store.clone(),
+            NewApiKey {
+                name: "ci".to_string(),
+                scopes: vec![Scope::QuestionsWrite],
+            },
+        )
+        .await
+        .err()
+        .unwrap();
+        assert!(matches!(
+            rejection.find::<Error>(),
+            Some(Error::InvalidContent(_))
+        ));
+        assert!(store.get_api_keys(&AccountId(1)).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn revoked_tokens_are_rejected() {
+        let token_keys = Arc::new(TokenKeys::generate());
+        let store = MemoryStore::new();
+        let token = issue_token(&token_keys, AccountId(3), Role::User, false, None);
+
+        let session = verify_token(token.clone(), &token_keys, &store)
+            .await
+            .unwrap();
+        store
+            .revoke_access_token(session.jti.as_ref().unwrap(), session.exp.naive_utc())
+            .await
+            .unwrap();
+
+        let err = verify_token(token, &token_keys, &store).await.unwrap_err();
+        assert!(matches!(err, Error::TokenRevoked));
+    }
+
+    #[tokio::test]
+    async fn refresh_tokens_can_only_be_used_once() {
+        let token_keys = Arc::new(TokenKeys::generate());
+        let store = MemoryStore::new();
+        store
+            .add_account(Account {
+                id: None,
+                email: "a@b.c".to_string(),
+                password: "password".to_string(),
+            })
+            .await
+            .unwrap();
+        let actor = store.get_actor(&AccountId(1)).await.unwrap();
+
+        let tokens = issue_tokens(
+            &store,
+            &token_keys,
+            &actor,
+            random_token(16),
+            false,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        let request = RefreshRequest {
+            refresh_token: tokens.refresh_token,
+            scope: None,
+        };
+        assert!(
+            refresh_token(store.clone(), token_keys.clone(), request.clone())
+                .await
+                .is_ok()
+        );
+        assert!(refresh_token(store, token_keys, request).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn scoped_tokens_can_log_out() {
+        let token_keys = Arc::new(TokenKeys::generate());
+        let store = MemoryStore::new();
+        store
+            .add_account(Account {
+                id: None,
+                email: "a@b.c".to_string(),
+                password: "password".to_string(),
+            })
+            .await
+            .unwrap();
+        let actor = store.get_actor(&AccountId(1)).await.unwrap();
+        let tokens = issue_tokens(
+            &store,
+            &token_keys,
+            &actor,
+            random_token(16),
+            false,
+            None,
+            Some(vec![Scope::QuestionsWrite]),
+        )
+        .await
+        .unwrap();
+
+        let store_filter = warp::any().map({
+            let store = store.clone();
+            move || store.clone()
+        });
+        let filter = warp::post()
+            .and(warp::path("logout"))
+            .and(auth(store.clone(), token_keys.clone()))
+            .and(store_filter)
+            .and(warp::body::json())
+            .and_then(logout::<MemoryStore>)
+            .recover(handle_errors::return_error);
+        let res = warp::test::request()
+            .method("POST")
+            .path("/logout")
+            .header("Authorization", format!("Bearer {}", tokens.access_token))
+            .json(&serde_json::json!({ "refresh_token": tokens.refresh_token }))
+            .reply(&filter)
+            .await;
+        assert_eq!(200, res.status());
+
+        let err = verify_token(tokens.access_token, &token_keys, &store)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::TokenRevoked));
+        let request = RefreshRequest {
+            refresh_token: tokens.refresh_token,
+            scope: None,
+        };
+        assert!(refresh_token(store, token_keys, request).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn suspended_accounts_get_no_tokens() {
+        let token_keys = Arc::new(TokenKeys::generate());
+        let store = MemoryStore::new();
+        store
+            .add_account(Account {
+                id: None,
+                email: "a@b.c".to_string(),
+                password: hashed_password(b"password"),
+            })
+            .await
+            .unwrap();
+        let actor = store.get_actor(&AccountId(1)).await.unwrap();
+        let tokens = issue_tokens(
+            &store,
+            &token_keys,
+            &actor,
+            random_token(16),
+            false,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        store
+            .set_account_status(&AccountId(1), AccountStatus::Suspended)
+            .await
+            .unwrap();
+
+        let login = Login {
+            email: "a@b.c".to_string(),
+            password: "password".to_string(),
+            scope: None,
+        };
+        let rejection = super::login(store.clone(), token_keys.clone(), None, login)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            rejection.find::<Error>(),
+            Some(Error::AccountSuspended)
+        ));
+
+        let request = RefreshRequest {
+            refresh_token: tokens.refresh_token,
+            scope: None,
+        };
+        let rejection = refresh_token(store, token_keys, request)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            rejection.find::<Error>(),
+            Some(Error::AccountSuspended)
+        ));
+    }
+
+    #[test]
+    fn dummy_hash_costs_as_much_as_a_password_hash() {
+        // Algorithm, version and cost parameters, without salt and hash
+        let params = |hash: &str| hash.split('$').take(4).collect::<Vec<_>>().join("$");
+        assert_eq!(params(&hashed_password(b"password")), params(DUMMY_HASH));
+        assert!(!verify_password(DUMMY_HASH, b"password").unwrap());
+    }
+
+    #[tokio::test]
+    async fn logins_lock_out_after_repeated_failures() {
+        let token_keys = Arc::new(TokenKeys::generate());
+        let store = MemoryStore::new();
+        store
+            .add_account(Account {
+                id: None,
+                email: "a@b.c".to_string(),
+                password: hashed_password(b"password"),
+            })
+            .await
+            .unwrap();
+
+        let attempt = |email: &str, password: &str| {
+            let store = store.clone();
+            let token_keys = token_keys.clone();
+            let account = Login {
+                email: email.to_string(),
+                password: password.to_string(),
+                scope: None,
+            };
+            async move {
+                match login(store, token_keys, None, account).await {
+                    Ok(_) => None,
+                    Err(rejection) => rejection.find::<Error>().map(|e| e.to_string()),
+                }
+            }
+        };
+
+        let wrong_password = attempt("a@b.c", "wrong").await.unwrap();
+        assert_eq!(wrong_password, attempt("x@b.c", "wrong").await.unwrap());
+        for _ in 1..ACCOUNT_FREE_FAILURES {
+            assert_eq!(
+                Some(&wrong_password),
+                attempt("a@b.c", "wrong").await.as_ref()
+            );
+        }
+
+        // One more failure starts the lockout, which even the right
+        // password cannot get past
+        assert_eq!(Some(wrong_password), attempt("a@b.c", "wrong").await);
+        let locked = attempt("a@b.c", "password").await.unwrap();
+        assert!(locked.starts_with("Too many failed logins"));
 
-        assert_eq!(AccountId(3), res.await.unwrap().account_id);
+        store
+            .clear_login_failures(&account_key("a@b.c"))
+            .await
+            .unwrap();
+        assert_eq!(None, attempt("a@b.c", "password").await);
     }
 }
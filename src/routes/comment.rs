@@ -0,0 +1,101 @@
+use crate::policy;
+use crate::profanity::check_profanity;
+use crate::store::Store;
+use crate::types::{Actor, CommentTarget, NewComment, Session};
+
+use handle_errors::Error;
+use warp::http::StatusCode;
+use warp::{Rejection, Reply};
+
+pub async fn get_comments<S: Store>(
+    target: CommentTarget,
+    store: S,
+) -> Result<impl Reply, Rejection> {
+    store
+        .get_comments(target)
+        .await
+        .map(|comments| warp::reply::json(&comments))
+        .map_err(warp::reject::custom)
+}
+
+pub async fn add_comment<S: Store>(
+    target: CommentTarget,
+    session: Session,
+    store: S,
+    new_comment: NewComment,
+) -> Result<impl Reply, Rejection> {
+    let actor = policy::actor(&store, &session).await?;
+    policy::can_post(&actor)?;
+    check_unlocked(&store, &actor, target).await?;
+    new_comment.validate()?;
+
+    let content = check_profanity(new_comment.content.trim().to_string())
+        .await
+        .map_err(warp::reject::custom)?;
+    let comment = NewComment { content };
+
+    store
+        .add_comment(target, comment, actor.account_id)
+        .await
+        .map(|comment| warp::reply::json(&comment))
+        .map_err(warp::reject::custom)
+}
+
+pub async fn update_comment<S: Store>(
+    target: CommentTarget,
+    comment_id: i32,
+    session: Session,
+    store: S,
+    comment: NewComment,
+) -> Result<impl Reply, Rejection> {
+    let actor = policy::actor(&store, &session).await?;
+    let existing = store.get_comment(target, comment_id).await?;
+    policy::can_modify(&actor, &existing.account_id)?;
+    check_unlocked(&store, &actor, target).await?;
+    comment.validate()?;
+
+    let content = check_profanity(comment.content.trim().to_string())
+        .await
+        .map_err(warp::reject::custom)?;
+    let comment = NewComment { content };
+
+    store
+        .update_comment(target, comment_id, comment)
+        .await
+        .map(|comment| warp::reply::json(&comment))
+        .map_err(warp::reject::custom)
+}
+
+pub async fn delete_comment<S: Store>(
+    target: CommentTarget,
+    comment_id: i32,
+    session: Session,
+    store: S,
+) -> Result<impl Reply, Rejection> {
+    let actor = policy::actor(&store, &session).await?;
+    let existing = store.get_comment(target, comment_id).await?;
+    policy::can_modify(&actor, &existing.account_id)?;
+    check_unlocked(&store, &actor, target).await?;
+
+    store
+        .delete_comment(target, comment_id)
+        .await
+        .map(|_| {
+            warp::reply::with_status(format!("Comment {} deleted", comment_id), StatusCode::OK)
+        })
+        .map_err(warp::reject::custom)
+}
+
+/// Comments on a locked question, or on its answers, are frozen with it
+async fn check_unlocked<S: Store>(
+    store: &S,
+    actor: &Actor,
+    target: CommentTarget,
+) -> Result<(), Error> {
+    let question = match target {
+        CommentTarget::Question(id) => store.get_question(id).await?,
+        CommentTarget::Answer(id) => store.get_answer_question(id).await?,
+    };
+
+    policy::check_unlocked(actor, &question)
+}
@@ -1,7 +1,37 @@
 mod answer;
+mod api_key;
 mod authentication;
+mod comment;
+mod moderation;
+mod oidc;
+mod password;
+mod profile;
 mod question;
+mod revision;
+mod tag;
+mod two_factor;
+mod verification;
+mod vote;
 
-pub use answer::add_answer;
-pub use authentication::{auth, login, register};
-pub use question::{add_question, delete_question, get_questions, update_question};
+pub use answer::{
+    accept_answer, add_answer, delete_answer, get_answer, get_answers, unaccept_answer,
+    update_answer,
+};
+pub use api_key::{add_api_key, get_api_keys, revoke_api_key};
+pub use authentication::{
+    auth, get_token_keys, login, logout, refresh_token, register, require_role, require_scope,
+    require_verified_email,
+};
+pub use comment::{add_comment, delete_comment, get_comments, update_comment};
+pub use moderation::{lock_question, set_account_role, set_account_status, unlock_question};
+pub use oidc::{oidc_callback, oidc_login};
+pub use password::{change_password, confirm_password_reset, request_password_reset};
+pub use profile::{get_user, update_profile};
+pub use question::{
+    add_question, delete_question, get_question, get_questions, restore_question, update_question,
+};
+pub use revision::{get_revision_diff, get_revisions, rollback_question};
+pub use tag::{add_tag_synonym, get_tag, get_tags, remove_tag_synonym, update_tag};
+pub use two_factor::{confirm_two_factor, disable_two_factor, enroll_two_factor, login_two_factor};
+pub use verification::{confirm_email, resend_verification_email};
+pub use vote::{retract_answer_vote, retract_question_vote, vote_on_answer, vote_on_question};
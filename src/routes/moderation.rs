@@ -0,0 +1,81 @@
+use crate::policy;
+use crate::store::Store;
+use crate::types::{AccountId, Actor, RoleUpdate, Session, StatusUpdate};
+
+use handle_errors::Error;
+use warp::{Rejection, Reply};
+
+pub async fn lock_question<S: Store>(
+    question_id: i32,
+    session: Session,
+    store: S,
+) -> Result<impl Reply, Rejection> {
+    set_question_locked(question_id, session, store, true).await
+}
+
+pub async fn unlock_question<S: Store>(
+    question_id: i32,
+    session: Session,
+    store: S,
+) -> Result<impl Reply, Rejection> {
+    set_question_locked(question_id, session, store, false).await
+}
+
+async fn set_question_locked<S: Store>(
+    question_id: i32,
+    session: Session,
+    store: S,
+    locked: bool,
+) -> Result<impl Reply, Rejection> {
+    let actor = policy::actor(&store, &session).await?;
+    policy::can_moderate(&actor)?;
+
+    store
+        .set_question_locked(question_id, locked)
+        .await
+        .map(|question| warp::reply::json(&question))
+        .map_err(warp::reject::custom)
+}
+
+pub async fn set_account_status<S: Store>(
+    account_id: i32,
+    session: Session,
+    store: S,
+    update: StatusUpdate,
+) -> Result<impl Reply, Rejection> {
+    let actor = policy::actor(&store, &session).await?;
+    let target = target_account(&store, account_id).await?;
+    policy::can_set_status(&actor, &target)?;
+
+    store
+        .set_account_status(&target.account_id, update.status)
+        .await
+        .map(|account| warp::reply::json(&account))
+        .map_err(warp::reject::custom)
+}
+
+pub async fn set_account_role<S: Store>(
+    account_id: i32,
+    session: Session,
+    store: S,
+    update: RoleUpdate,
+) -> Result<impl Reply, Rejection> {
+    let actor = policy::actor(&store, &session).await?;
+    let target = target_account(&store, account_id).await?;
+    policy::can_set_role(&actor, &target)?;
+
+    store
+        .set_account_role(&target.account_id, update.role)
+        .await
+        .map(|account| warp::reply::json(&account))
+        .map_err(warp::reject::custom)
+}
+
+/// The account a moderation action is aimed at
+async fn target_account<S: Store>(store: &S, account_id: i32) -> Result<Actor, Error> {
+    match store.get_actor(&AccountId(account_id)).await {
+        // `get_actor` is meant for the account making the request
+        Err(Error::Unauthorized) => Err(Error::UnknownAccount),
+        result => result,
+    }
+}
@@ -0,0 +1,342 @@
+use chrono::Utc;
+use tracing::{event, Level};
+use warp::http::Uri;
+use warp::{Rejection, Reply};
+
+use crate::oidc::{code_challenge, IdTokenClaims, SharedOidcClient};
+use crate::store::Store;
+use crate::token_keys::SharedTokenKeys;
+use crate::types::{normalize_email, Account, AccountId, OidcCallback, OidcLogin};
+
+use handle_errors::Error;
+
+use super::authentication::{hash_token, hashed_password, issue_tokens, random_token, AUDIT};
+use super::two_factor::{has_two_factor, login_challenge};
+
+/// Cookie tying the callback to the browser that started the login, so
+/// nobody can slip their own login into someone else's browser
+const STATE_COOKIE: &str = "oidc_state";
+/// How long logging in at the identity provider may take
+const OIDC_LOGIN_TTL_MINUTES: i64 = 10;
+
+/// Send the browser to the identity provider to log in, keeping the
+/// state, nonce and PKCE code verifier to finish the login with
+pub async fn oidc_login<S: Store>(
+    store: S,
+    oidc: SharedOidcClient,
+) -> Result<impl Reply, Rejection> {
+    let state = random_token(16);
+    let nonce = random_token(16);
+    let code_verifier = random_token(32);
+    let url = oidc.authorization_url(&state, &nonce, &code_challenge(&code_verifier));
+
+    store
+        .add_oidc_login(OidcLogin {
+            state_hash: hash_token(&state),
+            nonce,
+            code_verifier,
+            expires_on: Utc::now().naive_utc() + chrono::Duration::minutes(OIDC_LOGIN_TTL_MINUTES),
+        })
+        .await
+        .map_err(warp::reject::custom)?;
+
+    let uri = url
+        .parse::<Uri>()
+        .map_err(|err| warp::reject::custom(Error::IdentityProviderError(err.to_string())))?;
+    Ok(warp::reply::with_header(
+        warp::redirect::found(uri),
+        "set-cookie",
+        state_cookie(&oidc, &state, OIDC_LOGIN_TTL_MINUTES * 60),
+    ))
+}
+
+/// Where the identity provider sends the browser back to. Trades the
+/// code for an ID token and answers like `POST /login`, linking the
+/// identity to an account on its first login.
+pub async fn oidc_callback<S: Store>(
+    callback: OidcCallback,
+    cookie_state: Option<String>,
+    store: S,
+    token_keys: SharedTokenKeys,
+    oidc: SharedOidcClient,
+) -> Result<impl Reply, Rejection> {
+    if let Some(error) = callback.error {
+        return Err(warp::reject::custom(Error::OidcLoginFailed(format!(
+            "provider answered {}: {}",
+            error,
+            callback.error_description.unwrap_or_default()
+        ))));
+    }
+    let (Some(code), Some(state)) = (callback.code, callback.state) else {
+        return Err(warp::reject::custom(Error::MissingParameters));
+    };
+    if cookie_state.as_deref() != Some(state.as_str()) {
+        return Err(warp::reject::custom(Error::InvalidOidcState));
+    }
+
+    let login = store
+        .use_oidc_login(&hash_token(&state))
+        .await
+        .map_err(warp::reject::custom)?;
+    let claims = oidc
+        .exchange_code(&code, &login.code_verifier, &login.nonce)
+        .await
+        .map_err(warp::reject::custom)?;
+    let account_id = linked_account(&store, &claims)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    let reply = if has_two_factor(&store, &account_id)
+        .await
+        .map_err(warp::reject::custom)?
+    {
+        let challenge = login_challenge(&store, account_id)
+            .await
+            .map_err(warp::reject::custom)?;
+        warp::reply::json(&challenge)
+    } else {
+        let actor = store
+            .get_actor(&account_id)
+            .await
+            .map_err(warp::reject::custom)?;
+        let tokens = issue_tokens(
+            &store,
+            &token_keys,
+            &actor,
+            random_token(16),
+            false,
+            None,
+            None,
+        )
+        .await
+        .map_err(warp::reject::custom)?;
+        warp::reply::json(&tokens)
+    };
+
+    Ok(warp::reply::with_header(
+        reply,
+        "set-cookie",
+        state_cookie(&oidc, "", 0),
+    ))
+}
+
+/// The account an identity is linked to. On its first login it is
+/// linked to the account using its email if the provider verified the
+/// address, and to a new account if no account uses it. A new account
+/// has a random password, which a password reset can replace.
+async fn linked_account<S: Store>(store: &S, claims: &IdTokenClaims) -> Result<AccountId, Error> {
+    if let Some(account_id) = store.get_identity_account(&claims.iss, &claims.sub).await? {
+        return Ok(account_id);
+    }
+
+    let email = claims.email.as_deref().ok_or_else(|| {
+        Error::OidcLoginFailed("the provider did not share an email address".to_string())
+    })?;
+    let email = normalize_email(email)?;
+
+    let account_id = match store.get_account(email.clone()).await {
+        // Taking over an account needs proof of owning its email
+        Ok(_) if !claims.email_verified => return Err(Error::AccountAlreadyExists),
+        Ok(account) => account.id.expect("id not found"),
+        Err(Error::AccountNotFound) => {
+            store
+                .add_account(Account {
+                    id: None,
+                    email: email.clone(),
+                    password: hashed_password(random_token(32).as_bytes()),
+                })
+                .await?;
+            let account_id = store.get_account(email).await?.id.expect("id not found");
+            if claims.email_verified {
+                store.set_email_verified(&account_id).await?;
+            }
+            account_id
+        }
+        Err(e) => return Err(e),
+    };
+
+    store
+        .link_identity(&claims.iss, &claims.sub, &account_id)
+        .await?;
+    event!(
+        target: AUDIT,
+        Level::INFO,
+        account_id = account_id.0,
+        issuer = claims.iss,
+        subject = claims.sub,
+        "identity linked"
+    );
+
+    Ok(account_id)
+}
+
+/// The state cookie, or with `max_age` 0 the header removing it
+fn state_cookie(oidc: &SharedOidcClient, state: &str, max_age: i64) -> String {
+    let secure = if oidc.redirects_securely() {
+        "; Secure"
+    } else {
+        ""
+    };
+    format!(
+        "{}={}; Path=/oidc; Max-Age={}; HttpOnly; SameSite=Lax{}",
+        STATE_COOKIE, state, max_age, secure
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oidc::{OidcClient, OidcSettings};
+    use crate::store::{AccountRepository, IdentityRepository, MemoryStore};
+    use crate::token_keys::TokenKeys;
+    use crate::types::TokenPair;
+    use mock_server::{MockServer, IDP_DEFAULT_USER, IDP_DENYING_USER, IDP_UNVERIFIED_PREFIX};
+    use reqwest::Url;
+    use std::sync::Arc;
+
+    async fn client(mock: &MockServer) -> SharedOidcClient {
+        Arc::new(
+            OidcClient::discover(OidcSettings {
+                issuer: mock.issuer(),
+                client_id: "rust-web-dev".to_string(),
+                client_secret: None,
+                redirect_uri: "http://localhost:3030/oidc/callback".to_string(),
+            })
+            .await
+            .unwrap(),
+        )
+    }
+
+    /// Start a login as `login_hint` and follow it through the provider,
+    /// returning the callback and the state cookie
+    async fn log_in_at_provider<S: Store>(
+        store: &S,
+        oidc: &SharedOidcClient,
+        login_hint: &str,
+    ) -> (OidcCallback, String) {
+        let res = oidc_login(store.clone(), oidc.clone())
+            .await
+            .unwrap()
+            .into_response();
+        let cookie = res.headers()["set-cookie"].to_str().unwrap();
+        let state = cookie
+            .strip_prefix("oidc_state=")
+            .and_then(|rest| rest.split(';').next())
+            .unwrap()
+            .to_string();
+
+        let mut url = Url::parse(res.headers()["location"].to_str().unwrap()).unwrap();
+        url.query_pairs_mut().append_pair("login_hint", login_hint);
+        let res = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap()
+            .get(url)
+            .send()
+            .await
+            .unwrap();
+        let location = Url::parse(res.headers()["location"].to_str().unwrap()).unwrap();
+        let callback = serde_urlencoded::from_str(location.query().unwrap()).unwrap();
+
+        (callback, state)
+    }
+
+    async fn finish<S: Store>(
+        callback: OidcCallback,
+        state: String,
+        store: &S,
+        oidc: &SharedOidcClient,
+    ) -> Result<TokenPair, Option<String>> {
+        let token_keys = Arc::new(TokenKeys::generate());
+        match oidc_callback(
+            callback,
+            Some(state),
+            store.clone(),
+            token_keys,
+            oidc.clone(),
+        )
+        .await
+        {
+            Ok(reply) => {
+                let body = warp::hyper::body::to_bytes(reply.into_response().into_body())
+                    .await
+                    .unwrap();
+                Ok(serde_json::from_slice(&body).unwrap())
+            }
+            Err(rejection) => Err(rejection.find::<Error>().map(|e| e.to_string())),
+        }
+    }
+
+    #[tokio::test]
+    async fn logs_in_through_the_identity_provider() {
+        let mock = MockServer::new().await;
+        let handler = mock.oneshot();
+        let oidc = client(&mock).await;
+        let store = MemoryStore::new();
+
+        // The first login creates an account, the ones after it reuse it
+        for _ in 0..2 {
+            let (callback, state) = log_in_at_provider(&store, &oidc, IDP_DEFAULT_USER).await;
+            assert!(finish(callback, state, &store, &oidc).await.is_ok());
+        }
+        let account = store
+            .get_account(IDP_DEFAULT_USER.to_string())
+            .await
+            .unwrap();
+        assert_eq!(Some(AccountId(1)), account.id);
+        assert!(store.is_email_verified(&AccountId(1)).await.unwrap());
+
+        // Each state finishes one login, in the browser that started it
+        let (callback, state) = log_in_at_provider(&store, &oidc, IDP_DEFAULT_USER).await;
+        let err = finish(callback.clone(), "forged".to_string(), &store, &oidc).await;
+        assert_eq!(Some(Error::InvalidOidcState.to_string()), err.unwrap_err());
+        assert!(finish(callback.clone(), state.clone(), &store, &oidc)
+            .await
+            .is_ok());
+        let err = finish(callback, state, &store, &oidc).await;
+        assert_eq!(Some(Error::InvalidOidcState.to_string()), err.unwrap_err());
+
+        let (callback, state) = log_in_at_provider(&store, &oidc, IDP_DENYING_USER).await;
+        assert_eq!(Some("access_denied"), callback.error.as_deref());
+        assert!(finish(callback, state, &store, &oidc).await.is_err());
+
+        let _ = handler.sender.send(());
+    }
+
+    #[tokio::test]
+    async fn links_accounts_by_verified_email_only() {
+        let mock = MockServer::new().await;
+        let handler = mock.oneshot();
+        let oidc = client(&mock).await;
+        let store = MemoryStore::new();
+        let unverified = format!("{}@example.com", IDP_UNVERIFIED_PREFIX);
+        for email in [IDP_DEFAULT_USER, unverified.as_str()] {
+            store
+                .add_account(Account {
+                    id: None,
+                    email: email.to_string(),
+                    password: hashed_password(b"password"),
+                })
+                .await
+                .unwrap();
+        }
+
+        let (callback, state) = log_in_at_provider(&store, &oidc, IDP_DEFAULT_USER).await;
+        assert!(finish(callback, state, &store, &oidc).await.is_ok());
+        let issuer = oidc.issuer();
+        let subject = format!("mock|{}", IDP_DEFAULT_USER);
+        assert_eq!(
+            Some(AccountId(1)),
+            store.get_identity_account(issuer, &subject).await.unwrap()
+        );
+
+        let (callback, state) = log_in_at_provider(&store, &oidc, &unverified).await;
+        let err = finish(callback, state, &store, &oidc).await;
+        assert_eq!(
+            Some(Error::AccountAlreadyExists.to_string()),
+            err.unwrap_err()
+        );
+
+        let _ = handler.sender.send(());
+    }
+}
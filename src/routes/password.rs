@@ -0,0 +1,266 @@
+use std::net::SocketAddr;
+
+use chrono::Utc;
+use tracing::{event, Level};
+use warp::http::StatusCode;
+use warp::{Rejection, Reply};
+
+use crate::mailer::{Email, SharedMailer};
+use crate::store::Store;
+use crate::types::{
+    normalize_email, validate_password, AccountId, AccountToken, PasswordChange,
+    PasswordResetConfirm, PasswordResetRequest, Session,
+};
+
+use handle_errors::Error;
+
+use super::authentication::{
+    check_login_lockout, hash_token, hashed_password, login_keys, random_token,
+    record_login_failure, verify_password,
+};
+
+/// How long a password reset token can be used
+const RESET_TOKEN_TTL_MINUTES: i64 = 60;
+
+/// Change the password of the account making the request. Signs the
+/// account out everywhere else by revoking its refresh tokens. Wrong
+/// current passwords count as failed logins, so a stolen access token
+/// cannot be used to guess the password.
+pub async fn change_password<S: Store>(
+    session: Session,
+    store: S,
+    remote: Option<SocketAddr>,
+    change: PasswordChange,
+) -> Result<impl Reply, Rejection> {
+    validate_password(&change.new_password).map_err(warp::reject::custom)?;
+
+    let account = store
+        .get_account_by_id(&session.account_id)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    let keys = login_keys(&account.email, remote);
+    check_login_lockout(&store, &keys)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    match verify_password(&account.password, change.current_password.as_bytes()) {
+        Ok(true) => {}
+        Ok(false) => {
+            record_login_failure(&store, &keys)
+                .await
+                .map_err(warp::reject::custom)?;
+            return Err(warp::reject::custom(Error::WrongPassword));
+        }
+        Err(e) => return Err(warp::reject::custom(Error::ArgonLibraryError(e))),
+    }
+
+    store
+        .clear_login_failures(&keys[0].0)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    set_password(&store, &session.account_id, &change.new_password).await?;
+
+    Ok(warp::reply::json(&"Password changed".to_string()))
+}
+
+/// Email a single-use token for choosing a new password. Answers the
+/// same whether or not the email belongs to an account, so the route
+/// cannot be used to find out who is registered. The account is looked
+/// up and mailed after answering, so neither can how long it takes.
+pub async fn request_password_reset<S: Store>(
+    store: S,
+    mailer: SharedMailer,
+    request: PasswordResetRequest,
+) -> Result<impl Reply, Rejection> {
+    if let Ok(email) = normalize_email(&request.email) {
+        tokio::spawn(async move {
+            if let Err(err) = send_password_reset(&store, &mailer, email).await {
+                event!(Level::ERROR, "sending a password reset failed: {}", err);
+            }
+        });
+    }
+
+    Ok(reset_requested())
+}
+
+/// Mail a password reset token to the account using `email`, if any
+async fn send_password_reset<S: Store>(
+    store: &S,
+    mailer: &SharedMailer,
+    email: String,
+) -> Result<(), Error> {
+    let account = match store.get_account(email).await {
+        Ok(account) => account,
+        Err(Error::AccountNotFound) => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    let token = random_token(32);
+    store
+        .add_password_reset(AccountToken {
+            token_hash: hash_token(&token),
+            account_id: account.id.expect("id not found"),
+            expires_on: Utc::now().naive_utc() + chrono::Duration::minutes(RESET_TOKEN_TTL_MINUTES),
+        })
+        .await?;
+
+    mailer
+        .send(Email {
+            to: account.email,
+            subject: "Reset your password".to_string(),
+            body: format!(
+                "Someone asked to reset the password of your account. \
+                If it was you, use this token within {} minutes to \
+                choose a new password:\n\n{}\n\nOtherwise you can \
+                ignore this email.\n",
+                RESET_TOKEN_TTL_MINUTES, token
+            ),
+        })
+        .await
+}
+
+fn reset_requested() -> warp::reply::WithStatus<warp::reply::Json> {
+    warp::reply::with_status(
+        warp::reply::json(&"If the account exists, a password reset email was sent".to_string()),
+        StatusCode::ACCEPTED,
+    )
+}
+
+/// Set a new password with a token from `request_password_reset`
+pub async fn confirm_password_reset<S: Store>(
+    store: S,
+    confirm: PasswordResetConfirm,
+) -> Result<impl Reply, Rejection> {
+    validate_password(&confirm.new_password).map_err(warp::reject::custom)?;
+
+    let account_id = store
+        .use_password_reset(&hash_token(&confirm.token))
+        .await
+        .map_err(warp::reject::custom)?;
+
+    set_password(&store, &account_id, &confirm.new_password).await?;
+
+    Ok(warp::reply::json(&"Password changed".to_string()))
+}
+
+async fn set_password<S: Store>(
+    store: &S,
+    account_id: &AccountId,
+    password: &str,
+) -> Result<(), Rejection> {
+    store
+        .set_account_password(account_id, hashed_password(password.as_bytes()))
+        .await
+        .map_err(warp::reject::custom)?;
+    store
+        .revoke_refresh_tokens(account_id)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mailer::Mailer;
+    use crate::store::{AccountRepository, MemoryStore};
+    use crate::types::{Account, Role, ACCOUNT_FREE_FAILURES};
+    use async_trait::async_trait;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    /// Takes emails but never finishes sending them
+    #[derive(Debug)]
+    struct StuckMailer(mpsc::UnboundedSender<Email>);
+
+    #[async_trait]
+    impl Mailer for StuckMailer {
+        async fn send(&self, email: Email) -> Result<(), Error> {
+            self.0.send(email).unwrap();
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn answers_before_mailing_the_reset() {
+        let store = MemoryStore::new();
+        store
+            .add_account(Account {
+                id: None,
+                email: "ada@example.com".to_string(),
+                password: "password".to_string(),
+            })
+            .await
+            .unwrap();
+        let (sender, mut emails) = mpsc::unbounded_channel();
+        let mailer: SharedMailer = Arc::new(StuckMailer(sender));
+
+        for email in ["nobody@example.com", "ada@example.com"] {
+            let request = PasswordResetRequest {
+                email: email.to_string(),
+            };
+            let reply = tokio::time::timeout(
+                Duration::from_secs(1),
+                request_password_reset(store.clone(), mailer.clone(), request),
+            )
+            .await
+            .expect("the answer waited for the email")
+            .unwrap();
+            assert_eq!(StatusCode::ACCEPTED, reply.into_response().status());
+        }
+
+        let email = emails.recv().await.unwrap();
+        assert_eq!("ada@example.com", email.to);
+        assert!(emails.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn wrong_current_passwords_count_as_failed_logins() {
+        let store = MemoryStore::new();
+        store
+            .add_account(Account {
+                id: None,
+                email: "ada@example.com".to_string(),
+                password: hashed_password(b"password"),
+            })
+            .await
+            .unwrap();
+        let session = Session {
+            exp: Utc::now() + chrono::Duration::minutes(5),
+            account_id: AccountId(1),
+            role: Role::User,
+            jti: None,
+            two_factor: false,
+            scopes: None,
+        };
+
+        let attempt = |current_password: &str| {
+            let change = PasswordChange {
+                current_password: current_password.to_string(),
+                new_password: "a new password".to_string(),
+            };
+            let reply = change_password(session.clone(), store.clone(), None, change);
+            async move {
+                match reply.await {
+                    Ok(_) => None,
+                    Err(rejection) => rejection.find::<Error>().map(|e| e.to_string()),
+                }
+            }
+        };
+
+        for _ in 0..ACCOUNT_FREE_FAILURES {
+            assert_eq!(
+                Some(Error::WrongPassword.to_string()),
+                attempt("not the password").await
+            );
+        }
+        // One more wrong guess locks out even the right password
+        attempt("not the password").await;
+        let locked = attempt("password").await.unwrap();
+        assert!(locked.starts_with("Too many failed logins"));
+    }
+}
@@ -0,0 +1,104 @@
+use warp::{Rejection, Reply};
+
+use crate::profanity::check_profanity;
+use crate::store::Store;
+use crate::types::{AccountId, ProfileUpdate, Session, UserPage, USER_PAGE_POST_LIMIT};
+
+/// A user's public page: their profile with their latest questions and
+/// answers. Leaves out the email, as anybody can look it up.
+pub async fn get_user<S: Store>(account_id: i32, store: S) -> Result<impl Reply, Rejection> {
+    let account_id = AccountId(account_id);
+    let profile = store
+        .get_profile(&account_id)
+        .await
+        .map_err(warp::reject::custom)?;
+    let questions = store
+        .get_account_questions(&account_id, USER_PAGE_POST_LIMIT)
+        .await
+        .map_err(warp::reject::custom)?;
+    let answers = store
+        .get_account_answers(&account_id, USER_PAGE_POST_LIMIT)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    Ok(warp::reply::json(&UserPage {
+        profile,
+        questions,
+        answers,
+    }))
+}
+
+/// Change the profile of the account making the request. The display
+/// name and bio are shown to everybody, so they are censored like posts.
+pub async fn update_profile<S: Store>(
+    session: Session,
+    store: S,
+    update: ProfileUpdate,
+) -> Result<impl Reply, Rejection> {
+    let mut update = update.normalize().map_err(warp::reject::custom)?;
+
+    for field in [&mut update.display_name, &mut update.bio] {
+        if let Some(value) = field.take() {
+            *field = Some(if value.is_empty() {
+                value
+            } else {
+                check_profanity(value).await.map_err(warp::reject::custom)?
+            });
+        }
+    }
+
+    store
+        .update_profile(&session.account_id, update)
+        .await
+        .map(|profile| warp::reply::json(&profile))
+        .map_err(warp::reject::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{AccountRepository, MemoryStore, QuestionRepository};
+    use crate::types::{Account, NewQuestion};
+    use warp::Filter;
+
+    #[tokio::test]
+    async fn user_pages_leave_out_the_email() {
+        let store = MemoryStore::new();
+        store
+            .add_account(Account {
+                id: None,
+                email: "ada@example.com".to_string(),
+                password: "password".to_string(),
+            })
+            .await
+            .unwrap();
+        let account_id = AccountId(1);
+        let update = ProfileUpdate {
+            display_name: Some("Ada".to_string()),
+            ..Default::default()
+        };
+        store.update_profile(&account_id, update).await.unwrap();
+
+        let new_question = NewQuestion {
+            title: "title".to_string(),
+            content: "content".to_string(),
+            tags: None,
+        };
+        store.add_question(new_question, account_id).await.unwrap();
+
+        let res = warp::test::request()
+            .path("/users/1")
+            .reply(
+                &warp::path!("users" / i32)
+                    .and(warp::any().map(move || store.clone()))
+                    .and_then(get_user::<MemoryStore>),
+            )
+            .await;
+        let page: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!("Ada", page["display_name"]);
+        assert!(page.get("email").is_none());
+        assert!(!String::from_utf8_lossy(res.body()).contains("ada@example.com"));
+        assert_eq!("title", page["questions"][0]["title"]);
+        assert_eq!(0, page["answers"].as_array().unwrap().len());
+    }
+}
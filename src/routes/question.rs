@@ -1,103 +1,456 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::Utc;
+
+use serde::Serialize;
 
 use crate::store::Store;
-use crate::types::{extract_pagination, NewQuestion, Pagination, Question, Session};
+use crate::types::{
+    extract_question_query, normalize_tags, Cursor, ETag, NewQuestion, Page, Question,
+    QuestionQuery, QuestionWithAnswers, Session,
+};
 
 use handle_errors::Error;
+use warp::http::header::{HeaderValue, ETAG, LINK};
 use warp::http::StatusCode;
 
+use crate::policy;
 use crate::profanity::check_profanity;
 use warp::{Rejection, Reply};
 
 use tracing::{event, info, instrument, Level};
 
 #[instrument]
-pub async fn get_questions(
+pub async fn get_questions<S: Store>(
     params: HashMap<String, String>,
-    store: Store,
+    store: S,
 ) -> Result<impl Reply, Rejection> {
     event!(target: env!("CARGO_CRATE_NAME"), Level::INFO, "querying questions");
 
-    let pagination = if !params.is_empty() {
-        event!(Level::INFO, pagination = true);
-        extract_pagination(&params)?
+    let query = if !params.is_empty() {
+        event!(Level::INFO, filtered = true);
+        extract_question_query(&params)?
     } else {
-        info!(pagination = false);
-        Pagination::default()
+        info!(filtered = false);
+        QuestionQuery::default()
     };
 
+    if let Some(terms) = query.search.clone() {
+        let results = store
+            .search_questions(terms, &query)
+            .await
+            .map_err(warp::reject::custom)?;
+
+        return Ok(page_reply(results, &params));
+    }
+
     let questions = store
-        .get_questions(pagination.limit, pagination.offset)
+        .get_questions(&query)
         .await
         .map_err(warp::reject::custom)?;
 
-    Ok(warp::reply::json(&questions))
+    Ok(page_reply(questions, &params))
 }
 
-pub async fn add_question(
+/// Reply with the items of a page, linking to the pages next to it
+/// in an RFC 8288 `Link` header and adding `X-Total-Count` if counted
+fn page_reply<T: Serialize>(
+    page: Page<T>,
+    params: &HashMap<String, String>,
+) -> warp::reply::Response {
+    let links: Vec<String> = [
+        ("next", "after", &page.next),
+        ("prev", "before", &page.prev),
+    ]
+    .into_iter()
+    .filter_map(|(rel, key, cursor)| {
+        cursor
+            .as_ref()
+            .map(|cursor| format!("<{}>; rel=\"{}\"", page_url(params, key, cursor), rel))
+    })
+    .collect();
+
+    let mut response = warp::reply::json(&page.items).into_response();
+    let headers = response.headers_mut();
+    if !links.is_empty() {
+        if let Ok(link) = HeaderValue::from_str(&links.join(", ")) {
+            headers.insert(LINK, link);
+        }
+    }
+    if let Some(total) = page.total {
+        headers.insert("x-total-count", HeaderValue::from(total));
+    }
+
+    response
+}
+
+/// The current query with its position replaced by a cursor
+fn page_url(params: &HashMap<String, String>, key: &str, cursor: &Cursor) -> String {
+    let mut params: BTreeMap<&str, String> = params
+        .iter()
+        .filter(|(k, _)| !matches!(k.as_str(), "offset" | "after" | "before"))
+        .map(|(k, v)| (k.as_str(), v.clone()))
+        .collect();
+    params.insert(key, cursor.encode());
+
+    let query = serde_urlencoded::to_string(params).unwrap_or_default();
+    format!("/questions?{}", query)
+}
+
+/// Replies `304 Not Modified` if the client's `If-None-Match` already
+/// has the question as it is now, answers and votes included
+pub async fn get_question<S: Store>(
+    id: i32,
+    if_none_match: Option<String>,
+    store: S,
+) -> Result<impl Reply, Rejection> {
+    let question = store.get_question(id).await?;
+    let answers = store.get_answers_for_question(id).await?;
+    let current = QuestionWithAnswers { question, answers };
+    let etag = ETag::of_representation(&current.question, &current);
+
+    if if_none_match.is_some_and(|header| etag.satisfies_if_none_match(&header)) {
+        let not_modified = warp::reply::with_status(warp::reply(), StatusCode::NOT_MODIFIED);
+        return Ok(with_etag(not_modified, &etag));
+    }
+
+    Ok(with_etag(warp::reply::json(&current), &etag))
+}
+
+/// Fails with `Error::PreconditionFailed` if the client sent an
+/// `If-Match` for another version than the current one. Votes and
+/// answers don't make a tag stale, only edits do.
+fn check_if_match(if_match: Option<&str>, current: &Question) -> Result<(), Error> {
+    match if_match {
+        Some(header) if !ETag::of(current).satisfies_if_match(header) => {
+            Err(Error::PreconditionFailed)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn with_etag(reply: impl Reply, etag: &ETag) -> warp::reply::Response {
+    let mut response = reply.into_response();
+    if let Ok(value) = HeaderValue::from_str(etag.as_str()) {
+        response.headers_mut().insert(ETAG, value);
+    }
+
+    response
+}
+
+pub async fn add_question<S: Store>(
     session: Session,
-    store: Store,
+    store: S,
     new_question: NewQuestion,
 ) -> Result<impl Reply, Rejection> {
-    let account_id = session.account_id;
+    let actor = policy::actor(&store, &session).await?;
+    policy::can_post(&actor)?;
+    let tags = normalize_tags(new_question.tags)?;
+
     let title = check_profanity(new_question.title);
     let content = check_profanity(new_question.content);
     let (title, content) = tokio::try_join!(title, content).map_err(warp::reject::custom)?;
     let new_question = NewQuestion {
         title,
         content,
-        ..new_question
+        tags,
     };
 
-    store
-        .add_question(new_question, account_id)
+    let question = store
+        .add_question(new_question, actor.account_id)
         .await
-        .map(|qustion| warp::reply::json(&qustion))
-        .map_err(warp::reject::custom)
+        .map_err(warp::reject::custom)?;
+
+    let etag = ETag::of(&question);
+    Ok(with_etag(warp::reply::json(&question), &etag))
 }
 
-pub async fn update_question(
+pub async fn update_question<S: Store>(
     id: i32,
     session: Session,
-    store: Store,
-    question: Question,
+    if_match: Option<String>,
+    store: S,
+    question: NewQuestion,
 ) -> Result<impl Reply, Rejection> {
-    let account_id = session.account_id;
-    if !store.is_question_owner(id, &account_id).await? {
-        return Err(warp::reject::custom(Error::Unauthorized));
-    }
+    let actor = policy::actor(&store, &session).await?;
+    let current = store.get_question(id).await?;
+    policy::can_modify(&actor, &current.account_id)?;
+    policy::check_unlocked(&actor, &current)?;
+    check_if_match(if_match.as_deref(), &current)?;
+    let tags = normalize_tags(question.tags)?;
 
     let title = check_profanity(question.title);
     let content = check_profanity(question.content);
     let (title, content) = tokio::try_join!(title, content).map_err(warp::reject::custom)?;
-    let question = Question {
+    let question = NewQuestion {
         title,
         content,
-        ..question
+        tags,
     };
 
-    store
-        .update_question(question, id, account_id)
+    let question = store
+        .update_question(question, id, actor.account_id, current.version)
         .await
-        .map(|question| warp::reply::json(&question))
-        .map_err(warp::reject::custom)
+        .map_err(warp::reject::custom)?;
+
+    let etag = ETag::of(&question);
+    Ok(with_etag(warp::reply::json(&question), &etag))
 }
 
-pub async fn delete_question(
+pub async fn delete_question<S: Store>(
     question_id: i32,
     session: Session,
-    store: Store,
+    if_match: Option<String>,
+    store: S,
 ) -> Result<impl Reply, Rejection> {
-    let account_id = session.account_id;
-    if !store.is_question_owner(question_id, &account_id).await? {
-        return Err(warp::reject::custom(Error::Unauthorized));
-    }
+    let actor = policy::actor(&store, &session).await?;
+    let current = store.get_question(question_id).await?;
+    policy::can_modify(&actor, &current.account_id)?;
+    policy::check_unlocked(&actor, &current)?;
+    check_if_match(if_match.as_deref(), &current)?;
 
     store
-        .delete_question(question_id, account_id)
+        .delete_question(question_id, current.version)
         .await
         .map(|_| {
             warp::reply::with_status(format!("Question {} deleted", question_id), StatusCode::OK)
         })
         .map_err(warp::reject::custom)
 }
+
+/// The owner or a moderator can bring a deleted question back,
+/// as long as it was deleted less than `retention` ago
+pub async fn restore_question<S: Store>(
+    question_id: i32,
+    session: Session,
+    retention: chrono::Duration,
+    store: S,
+) -> Result<impl Reply, Rejection> {
+    let actor = policy::actor(&store, &session).await?;
+    let deleted = store.get_deleted_question(question_id).await?;
+    policy::can_modify(&actor, &deleted.account_id)?;
+
+    let restorable_since = Utc::now().naive_utc() - retention;
+    if deleted
+        .deleted_at
+        .is_none_or(|deleted_at| deleted_at < restorable_since)
+    {
+        return Err(warp::reject::custom(Error::QuestionNotFound));
+    }
+
+    store
+        .restore_question(question_id)
+        .await
+        .map(|question| warp::reply::json(&question))
+        .map_err(warp::reject::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{AccountRepository, MemoryStore, QuestionRepository};
+    use crate::types::{Account, AccountId, NewAnswer, Role, Vote};
+    use warp::Filter;
+
+    fn new_question(title: &str) -> NewQuestion {
+        NewQuestion {
+            title: title.to_string(),
+            content: "content".to_string(),
+            tags: None,
+        }
+    }
+
+    fn get_question_filter(
+        store: MemoryStore,
+    ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+        warp::path!("questions" / i32)
+            .and(warp::header::optional::<String>("if-none-match"))
+            .and(warp::any().map(move || store.clone()))
+            .and_then(get_question::<MemoryStore>)
+            .recover(handle_errors::return_error)
+    }
+
+    #[tokio::test]
+    async fn gets_a_question_with_its_answers() {
+        let store = MemoryStore::new();
+        store
+            .add_question(new_question("title"), AccountId(1))
+            .await
+            .unwrap();
+        let new_answer = NewAnswer {
+            content: "answer".to_string(),
+        };
+        store.add_answer(1, new_answer, AccountId(2)).await.unwrap();
+        let filter = get_question_filter(store);
+
+        let res = warp::test::request()
+            .path("/questions/1")
+            .reply(&filter)
+            .await;
+        assert_eq!(200, res.status());
+        let body: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!("title", body["title"]);
+        assert_eq!(1, body["account_id"]);
+        assert_eq!(2, body["answers"][0]["account_id"]);
+
+        let res = warp::test::request()
+            .path("/questions/2")
+            .reply(&filter)
+            .await;
+        assert_eq!(404, res.status());
+        assert_eq!(Error::QuestionNotFound.to_string().as_bytes(), res.body());
+    }
+
+    #[tokio::test]
+    async fn etags_follow_answers_but_only_edits_fail_if_match() {
+        let store = MemoryStore::new();
+        store
+            .add_account(Account {
+                id: None,
+                email: "ada@example.com".to_string(),
+                password: "password".to_string(),
+            })
+            .await
+            .unwrap();
+        store
+            .add_question(new_question("title"), AccountId(1))
+            .await
+            .unwrap();
+        let session = Session {
+            exp: Utc::now() + chrono::Duration::minutes(5),
+            account_id: AccountId(1),
+            role: Role::User,
+            jti: None,
+            two_factor: false,
+            scopes: None,
+        };
+        let get_filter = get_question_filter(store.clone());
+        let delete_store = store.clone();
+        let delete_filter = warp::path!("questions" / i32)
+            .and(warp::any().map(move || session.clone()))
+            .and(warp::header::optional::<String>("if-match"))
+            .and(warp::any().map(move || delete_store.clone()))
+            .and_then(delete_question::<MemoryStore>)
+            .recover(handle_errors::return_error);
+
+        let res = warp::test::request()
+            .path("/questions/1")
+            .reply(&get_filter)
+            .await;
+        let etag = res.headers()["etag"].to_str().unwrap().to_string();
+
+        let res = warp::test::request()
+            .path("/questions/1")
+            .header("if-none-match", &etag)
+            .reply(&get_filter)
+            .await;
+        assert_eq!(304, res.status());
+        assert!(res.body().is_empty());
+
+        // A new answer or vote changes what clients polling get to see
+        let new_answer = NewAnswer {
+            content: "answer".to_string(),
+        };
+        store.add_answer(1, new_answer, AccountId(2)).await.unwrap();
+        let res = warp::test::request()
+            .path("/questions/1")
+            .header("if-none-match", &etag)
+            .reply(&get_filter)
+            .await;
+        assert_eq!(200, res.status());
+        let answered = res.headers()["etag"].to_str().unwrap().to_string();
+        assert_ne!(etag, answered);
+        store
+            .vote_on_question(1, AccountId(2), Some(Vote::Up))
+            .await
+            .unwrap();
+        let res = warp::test::request()
+            .path("/questions/1")
+            .header("if-none-match", &answered)
+            .reply(&get_filter)
+            .await;
+        assert_eq!(200, res.status());
+
+        // But only an edit turns an If-Match stale
+        store
+            .update_question(new_question("edited"), 1, AccountId(1), 1)
+            .await
+            .unwrap();
+        let res = warp::test::request()
+            .path("/questions/1")
+            .reply(&get_filter)
+            .await;
+        let current = res.headers()["etag"].to_str().unwrap().to_string();
+
+        let res = warp::test::request()
+            .path("/questions/1")
+            .header("if-match", &etag)
+            .reply(&delete_filter)
+            .await;
+        assert_eq!(412, res.status());
+        let res = warp::test::request()
+            .path("/questions/1")
+            .header("if-match", &current)
+            .reply(&delete_filter)
+            .await;
+        assert_eq!(200, res.status());
+    }
+
+    #[tokio::test]
+    async fn moderators_edit_other_questions_only_with_a_second_factor() {
+        let store = MemoryStore::new();
+        for email in ["ada@example.com", "mod@example.com"] {
+            store
+                .add_account(Account {
+                    id: None,
+                    email: email.to_string(),
+                    password: "password".to_string(),
+                })
+                .await
+                .unwrap();
+        }
+        store
+            .set_account_role(&AccountId(2), Role::Moderator)
+            .await
+            .unwrap();
+        store
+            .add_question(new_question("title"), AccountId(1))
+            .await
+            .unwrap();
+        let session = |two_factor| Session {
+            exp: Utc::now() + chrono::Duration::minutes(5),
+            account_id: AccountId(2),
+            role: Role::Moderator,
+            jti: None,
+            two_factor,
+            scopes: None,
+        };
+
+        let rejection = update_question(
+            1,
+            session(false),
+            None,
+            store.clone(),
+            new_question("edited"),
+        )
+        .await
+        .err()
+        .unwrap();
+        assert!(matches!(
+            rejection.find::<Error>(),
+            Some(Error::TwoFactorRequired)
+        ));
+        let rejection = delete_question(1, session(false), None, store.clone())
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            rejection.find::<Error>(),
+            Some(Error::TwoFactorRequired)
+        ));
+
+        assert!(delete_question(1, session(true), None, store.clone())
+            .await
+            .is_ok());
+    }
+}
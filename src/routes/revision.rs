@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+
+use crate::policy;
+use crate::store::Store;
+use crate::types::{extract_diff_range, normalize_tags, NewQuestion, RevisionDiff, Session};
+
+use warp::{Rejection, Reply};
+
+pub async fn get_revisions<S: Store>(question_id: i32, store: S) -> Result<impl Reply, Rejection> {
+    store
+        .get_revisions(question_id)
+        .await
+        .map(|revisions| warp::reply::json(&revisions))
+        .map_err(warp::reject::custom)
+}
+
+/// Compare two revisions of a question, or a revision
+/// with the current version if no `to` is given
+pub async fn get_revision_diff<S: Store>(
+    question_id: i32,
+    params: HashMap<String, String>,
+    store: S,
+) -> Result<impl Reply, Rejection> {
+    let (from, to) = extract_diff_range(&params)?;
+
+    let old = store.get_revision(question_id, from).await?.snapshot();
+    let new = match to {
+        Some(to) => store.get_revision(question_id, to).await?.snapshot(),
+        None => {
+            let question = store.get_question(question_id).await?;
+            NewQuestion {
+                title: question.title,
+                content: question.content,
+                tags: question.tags,
+            }
+        }
+    };
+
+    Ok(warp::reply::json(&RevisionDiff::new(
+        (from, &old),
+        (to, &new),
+    )))
+}
+
+/// Restore the values of an earlier revision. This is an edit like
+/// any other, so the values it replaces become a new revision.
+pub async fn rollback_question<S: Store>(
+    question_id: i32,
+    revision: i32,
+    session: Session,
+    store: S,
+) -> Result<impl Reply, Rejection> {
+    let actor = policy::actor(&store, &session).await?;
+    let current = store.get_question(question_id).await?;
+    policy::can_modify(&actor, &current.account_id)?;
+    policy::check_unlocked(&actor, &current)?;
+
+    let revision = store.get_revision(question_id, revision).await?;
+    // Revisions from before tags were normalized may hold raw tags
+    let mut snapshot = revision.snapshot();
+    snapshot.tags = normalize_tags(snapshot.tags)?;
+
+    store
+        .update_question(snapshot, question_id, actor.account_id, current.version)
+        .await
+        .map(|question| warp::reply::json(&question))
+        .map_err(warp::reject::custom)
+}
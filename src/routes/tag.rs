@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+use crate::policy;
+use crate::profanity::check_profanity;
+use crate::store::Store;
+use crate::types::{extract_tag_query, normalize_tag, NewSynonym, Session, TagUpdate};
+
+use warp::{Rejection, Reply};
+
+/// List canonical tags with their usage counts, most used first.
+/// With `prefix` this doubles as autocomplete.
+pub async fn get_tags<S: Store>(
+    params: HashMap<String, String>,
+    store: S,
+) -> Result<impl Reply, Rejection> {
+    let query = extract_tag_query(&params)?;
+
+    store
+        .get_tags(&query)
+        .await
+        .map(|tags| warp::reply::json(&tags))
+        .map_err(warp::reject::custom)
+}
+
+/// Looking up a synonym returns the tag it redirects to
+pub async fn get_tag<S: Store>(name: String, store: S) -> Result<impl Reply, Rejection> {
+    let name = normalize_tag(&name)?;
+
+    store
+        .get_tag(&name)
+        .await
+        .map(|tag| warp::reply::json(&tag))
+        .map_err(warp::reject::custom)
+}
+
+pub async fn update_tag<S: Store>(
+    name: String,
+    session: Session,
+    store: S,
+    tag: TagUpdate,
+) -> Result<impl Reply, Rejection> {
+    let actor = policy::actor(&store, &session).await?;
+    policy::can_moderate(&actor)?;
+    let name = normalize_tag(&name)?;
+    let mut tag = tag.normalize()?;
+
+    if let Some(description) = tag.description {
+        tag.description = Some(
+            check_profanity(description)
+                .await
+                .map_err(warp::reject::custom)?,
+        );
+    }
+
+    store
+        .update_tag(&name, tag)
+        .await
+        .map(|tag| warp::reply::json(&tag))
+        .map_err(warp::reject::custom)
+}
+
+pub async fn add_tag_synonym<S: Store>(
+    name: String,
+    session: Session,
+    store: S,
+    synonym: NewSynonym,
+) -> Result<impl Reply, Rejection> {
+    let actor = policy::actor(&store, &session).await?;
+    policy::can_moderate(&actor)?;
+    let name = normalize_tag(&name)?;
+    let synonym = normalize_tag(&synonym.name)?;
+
+    store
+        .add_tag_synonym(&name, &synonym)
+        .await
+        .map(|tag| warp::reply::json(&tag))
+        .map_err(warp::reject::custom)
+}
+
+pub async fn remove_tag_synonym<S: Store>(
+    name: String,
+    synonym: String,
+    session: Session,
+    store: S,
+) -> Result<impl Reply, Rejection> {
+    let actor = policy::actor(&store, &session).await?;
+    policy::can_moderate(&actor)?;
+    let name = normalize_tag(&name)?;
+    let synonym = normalize_tag(&synonym)?;
+
+    store
+        .remove_tag_synonym(&name, &synonym)
+        .await
+        .map(|tag| warp::reply::json(&tag))
+        .map_err(warp::reject::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{AccountRepository, MemoryStore, QuestionRepository};
+    use crate::types::{Account, AccountId, NewQuestion, Role};
+    use chrono::Utc;
+    use handle_errors::Error;
+
+    #[tokio::test]
+    async fn moderators_curate_tags_only_with_a_second_factor() {
+        let store = MemoryStore::new();
+        store
+            .add_account(Account {
+                id: None,
+                email: "mod@example.com".to_string(),
+                password: "password".to_string(),
+            })
+            .await
+            .unwrap();
+        store
+            .set_account_role(&AccountId(1), Role::Moderator)
+            .await
+            .unwrap();
+        let question = NewQuestion {
+            title: "title".to_string(),
+            content: "content".to_string(),
+            tags: Some(vec!["rust".to_string()]),
+        };
+        store.add_question(question, AccountId(1)).await.unwrap();
+        let session = |two_factor| Session {
+            exp: Utc::now() + chrono::Duration::minutes(5),
+            account_id: AccountId(1),
+            role: Role::Moderator,
+            jti: None,
+            two_factor,
+            scopes: None,
+        };
+
+        let update = TagUpdate {
+            description: Some("A systems language".to_string()),
+        };
+        let rejection = update_tag("rust".to_string(), session(false), store.clone(), update)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            rejection.find::<Error>(),
+            Some(Error::TwoFactorRequired)
+        ));
+
+        let synonym = || NewSynonym {
+            name: "rustlang".to_string(),
+        };
+        let rejection =
+            add_tag_synonym("rust".to_string(), session(false), store.clone(), synonym())
+                .await
+                .err()
+                .unwrap();
+        assert!(matches!(
+            rejection.find::<Error>(),
+            Some(Error::TwoFactorRequired)
+        ));
+        assert!(
+            add_tag_synonym("rust".to_string(), session(true), store.clone(), synonym())
+                .await
+                .is_ok()
+        );
+    }
+}
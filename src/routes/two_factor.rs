@@ -0,0 +1,473 @@
+use std::net::SocketAddr;
+
+use chrono::Utc;
+use rand::Rng;
+use totp_rs::{Algorithm, Secret, TOTP};
+use warp::http::StatusCode;
+use warp::{Rejection, Reply};
+
+use crate::store::Store;
+use crate::token_keys::SharedTokenKeys;
+use crate::types::{
+    AccountId, AccountToken, LoginChallenge, RecoveryCodes, Session, TwoFactor, TwoFactorCode,
+    TwoFactorEnrollment, TwoFactorLogin, RECOVERY_CODE_COUNT,
+};
+
+use handle_errors::Error;
+
+use super::authentication::{
+    check_login_lockout, hash_token, issue_tokens, login_keys, random_token, record_login_failure,
+    requested_scopes,
+};
+
+/// How long a login challenge can be answered with a code
+const CHALLENGE_TTL_MINUTES: i64 = 5;
+/// How long each TOTP code is valid, the period authenticator apps assume
+const TOTP_STEP_SECONDS: i64 = 30;
+/// Codes of this many steps before or after the current one are accepted
+/// as well, for clocks that are a little off
+const TOTP_SKEW_STEPS: i64 = 1;
+/// Characters of recovery codes, leaving out the ones easily mistaken
+/// for each other
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+/// Start turning on two-factor authentication with a new secret. It is
+/// not asked for at login until it is confirmed with a code.
+pub async fn enroll_two_factor<S: Store>(
+    session: Session,
+    store: S,
+) -> Result<impl Reply, Rejection> {
+    let account = store
+        .get_account_by_id(&session.account_id)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    let mut secret = vec![0u8; 20];
+    rand::thread_rng().fill(&mut secret[..]);
+    let totp = totp(secret, account.email);
+
+    store
+        .set_two_factor_secret(&session.account_id, &totp.get_secret_base32())
+        .await
+        .map_err(warp::reject::custom)?;
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&TwoFactorEnrollment {
+            secret: totp.get_secret_base32(),
+            otpauth_uri: totp.get_url(),
+        }),
+        StatusCode::CREATED,
+    ))
+}
+
+/// Turn on two-factor authentication with a code generated from the new
+/// secret, and hand out the recovery codes. Signs the account out
+/// everywhere, as none of its sessions passed a second factor.
+pub async fn confirm_two_factor<S: Store>(
+    session: Session,
+    store: S,
+    confirmation: TwoFactorCode,
+) -> Result<impl Reply, Rejection> {
+    let two_factor = match store.get_two_factor(&session.account_id).await {
+        Ok(Some(two_factor)) if two_factor.confirmed => Err(Error::TwoFactorAlreadyEnabled),
+        Ok(Some(two_factor)) => Ok(two_factor),
+        Ok(None) => Err(Error::TwoFactorNotEnabled),
+        Err(e) => Err(e),
+    }
+    .map_err(warp::reject::custom)?;
+
+    let step = matching_step(
+        &two_factor.secret,
+        &confirmation.code,
+        Utc::now().timestamp(),
+    )
+    .ok_or_else(|| warp::reject::custom(Error::InvalidTwoFactorCode))?;
+
+    let recovery_codes: Vec<String> = (0..RECOVERY_CODE_COUNT).map(|_| recovery_code()).collect();
+    let hashes = recovery_codes
+        .iter()
+        .map(|code| hash_token(&normalize_recovery_code(code)))
+        .collect();
+
+    store
+        .confirm_two_factor(&session.account_id, step, hashes)
+        .await
+        .map_err(warp::reject::custom)?;
+    store
+        .revoke_refresh_tokens(&session.account_id)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    Ok(warp::reply::json(&RecoveryCodes { recovery_codes }))
+}
+
+/// Turn off two-factor authentication, which takes a TOTP or recovery
+/// code so that a stolen session alone cannot. Wrong codes count as
+/// failed logins, so the code cannot be guessed either. Signs the
+/// account out everywhere, like turning it on does.
+pub async fn disable_two_factor<S: Store>(
+    session: Session,
+    store: S,
+    remote: Option<SocketAddr>,
+    confirmation: TwoFactorCode,
+) -> Result<impl Reply, Rejection> {
+    let account = store
+        .get_account_by_id(&session.account_id)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    let keys = login_keys(&account.email, remote);
+    check_login_lockout(&store, &keys)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    let two_factor = enabled_two_factor(&store, &session.account_id)
+        .await
+        .map_err(warp::reject::custom)?;
+    if !verify_second_factor(&store, &two_factor, &confirmation.code)
+        .await
+        .map_err(warp::reject::custom)?
+    {
+        record_login_failure(&store, &keys)
+            .await
+            .map_err(warp::reject::custom)?;
+        return Err(warp::reject::custom(Error::InvalidTwoFactorCode));
+    }
+
+    store
+        .clear_login_failures(&keys[0].0)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    store
+        .disable_two_factor(&session.account_id)
+        .await
+        .map_err(warp::reject::custom)?;
+    store
+        .revoke_refresh_tokens(&session.account_id)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    Ok(warp::reply::json(
+        &"Two-factor authentication disabled".to_string(),
+    ))
+}
+
+/// Second step of logging in to an account with two-factor
+/// authentication: trade the challenge from `POST /login` and a TOTP or
+/// recovery code for tokens. Wrong codes count as failed logins, and as
+/// a challenge is used up by any answer, each guess takes the password.
+pub async fn login_two_factor<S: Store>(
+    store: S,
+    token_keys: SharedTokenKeys,
+    remote: Option<SocketAddr>,
+    login: TwoFactorLogin,
+) -> Result<impl Reply, Rejection> {
+    let scopes = requested_scopes(None, login.scope.as_deref()).map_err(warp::reject::custom)?;
+
+    let account_id = store
+        .use_login_challenge(&hash_token(&login.challenge_token))
+        .await
+        .map_err(warp::reject::custom)?;
+    let account = store
+        .get_account_by_id(&account_id)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    let keys = login_keys(&account.email, remote);
+    check_login_lockout(&store, &keys)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    let two_factor = enabled_two_factor(&store, &account_id)
+        .await
+        .map_err(warp::reject::custom)?;
+    if !verify_second_factor(&store, &two_factor, &login.code)
+        .await
+        .map_err(warp::reject::custom)?
+    {
+        record_login_failure(&store, &keys)
+            .await
+            .map_err(warp::reject::custom)?;
+        return Err(warp::reject::custom(Error::InvalidTwoFactorCode));
+    }
+
+    store
+        .clear_login_failures(&keys[0].0)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    let actor = store
+        .get_actor(&account_id)
+        .await
+        .map_err(warp::reject::custom)?;
+    let tokens = issue_tokens(
+        &store,
+        &token_keys,
+        &actor,
+        random_token(16),
+        true,
+        scopes.clone(),
+        scopes,
+    )
+    .await
+    .map_err(warp::reject::custom)?;
+
+    Ok(warp::reply::json(&tokens))
+}
+
+/// Start the second step of logging in, for an account whose password
+/// was right and that has two-factor authentication on
+pub(super) async fn login_challenge<S: Store>(
+    store: &S,
+    account_id: AccountId,
+) -> Result<LoginChallenge, Error> {
+    let challenge_token = random_token(32);
+    store
+        .add_login_challenge(AccountToken {
+            token_hash: hash_token(&challenge_token),
+            account_id,
+            expires_on: Utc::now().naive_utc() + chrono::Duration::minutes(CHALLENGE_TTL_MINUTES),
+        })
+        .await?;
+
+    Ok(LoginChallenge {
+        challenge_token,
+        expires_in: CHALLENGE_TTL_MINUTES * 60,
+    })
+}
+
+/// Whether the account has confirmed two-factor authentication
+pub(super) async fn has_two_factor<S: Store>(
+    store: &S,
+    account_id: &AccountId,
+) -> Result<bool, Error> {
+    Ok(store
+        .get_two_factor(account_id)
+        .await?
+        .is_some_and(|two_factor| two_factor.confirmed))
+}
+
+async fn enabled_two_factor<S: Store>(
+    store: &S,
+    account_id: &AccountId,
+) -> Result<TwoFactor, Error> {
+    store
+        .get_two_factor(account_id)
+        .await?
+        .filter(|two_factor| two_factor.confirmed)
+        .ok_or(Error::TwoFactorNotEnabled)
+}
+
+/// Check a TOTP code, each of which is only accepted once, or else a
+/// recovery code, which is used up by it
+async fn verify_second_factor<S: Store>(
+    store: &S,
+    two_factor: &TwoFactor,
+    code: &str,
+) -> Result<bool, Error> {
+    match matching_step(&two_factor.secret, code, Utc::now().timestamp()) {
+        Some(step) => store.use_totp_step(&two_factor.account_id, step).await,
+        None => {
+            let code_hash = hash_token(&normalize_recovery_code(code));
+            store
+                .use_recovery_code(&two_factor.account_id, &code_hash)
+                .await
+        }
+    }
+}
+
+fn totp(secret: Vec<u8>, email: String) -> TOTP {
+    TOTP::new_unchecked(
+        Algorithm::SHA1,
+        6,
+        0,
+        TOTP_STEP_SECONDS as u64,
+        secret,
+        Some(env!("CARGO_PKG_NAME").to_string()),
+        email,
+    )
+}
+
+/// The time step, close to `now`, that the code was generated for
+fn matching_step(secret: &str, code: &str, now: i64) -> Option<i64> {
+    let secret = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .expect("TOTP secrets are stored base32 encoded");
+    let totp = totp(secret, String::new());
+    let code = code.trim();
+
+    let current = now / TOTP_STEP_SECONDS;
+    (current - TOTP_SKEW_STEPS..=current + TOTP_SKEW_STEPS)
+        .find(|step| totp.check(code, (step * TOTP_STEP_SECONDS) as u64))
+}
+
+/// A code like `abcd-efgh-jkmn`, good for 60 bits of randomness
+fn recovery_code() -> String {
+    let mut rng = rand::thread_rng();
+    let chars: Vec<char> = (0..12)
+        .map(|_| RECOVERY_CODE_ALPHABET[rng.gen_range(0..RECOVERY_CODE_ALPHABET.len())] as char)
+        .collect();
+
+    chars
+        .chunks(4)
+        .map(|chunk| chunk.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Recovery codes are accepted in any case and with or without dashes
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::store::{AccountRepository, MemoryStore, TwoFactorRepository};
+    use crate::token_keys::TokenKeys;
+    use crate::types::{Account, Role, TokenPair, ACCOUNT_FREE_FAILURES};
+
+    const SECRET: &str = "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP";
+
+    fn code_at(time: i64) -> String {
+        let secret = Secret::Encoded(SECRET.to_string()).to_bytes().unwrap();
+        totp(secret, String::new()).generate(time as u64)
+    }
+
+    #[test]
+    fn codes_are_accepted_one_step_off() {
+        let now = 1_700_000_000;
+        let step = now / TOTP_STEP_SECONDS;
+
+        assert_eq!(Some(step), matching_step(SECRET, &code_at(now), now));
+        assert_eq!(
+            Some(step - 1),
+            matching_step(SECRET, &code_at(now - 30), now)
+        );
+        assert_eq!(
+            Some(step + 1),
+            matching_step(SECRET, &code_at(now + 30), now)
+        );
+        assert_eq!(None, matching_step(SECRET, &code_at(now - 60), now));
+        assert_eq!(None, matching_step(SECRET, "not a code", now));
+    }
+
+    #[test]
+    fn recovery_codes_are_normalized() {
+        let code = recovery_code();
+        assert_eq!(14, code.len());
+        assert_eq!(
+            normalize_recovery_code(&code),
+            normalize_recovery_code(&code.to_uppercase().replace('-', " "))
+        );
+    }
+
+    #[tokio::test]
+    async fn codes_are_only_accepted_once() {
+        let token_keys = Arc::new(TokenKeys::generate());
+        let store = MemoryStore::new();
+        store
+            .add_account(Account {
+                id: None,
+                email: "a@b.c".to_string(),
+                password: "password".to_string(),
+            })
+            .await
+            .unwrap();
+        let account_id = AccountId(1);
+        store
+            .set_two_factor_secret(&account_id, SECRET)
+            .await
+            .unwrap();
+        store
+            .confirm_two_factor(&account_id, 0, vec![hash_token("abcdefghjkmn")])
+            .await
+            .unwrap();
+
+        let attempt = |code: String| {
+            let store = store.clone();
+            let token_keys = token_keys.clone();
+            async move {
+                let challenge = login_challenge(&store, account_id).await.unwrap();
+                let login = TwoFactorLogin {
+                    challenge_token: challenge.challenge_token,
+                    code,
+                    scope: None,
+                };
+                let reply = login_two_factor(store, token_keys, None, login)
+                    .await
+                    .ok()?;
+                let body = warp::hyper::body::to_bytes(reply.into_response().into_body())
+                    .await
+                    .unwrap();
+                Some(serde_json::from_slice::<TokenPair>(&body).unwrap())
+            }
+        };
+
+        let code = code_at(Utc::now().timestamp());
+        assert!(attempt(code.clone()).await.is_some());
+        assert!(attempt(code).await.is_none());
+
+        assert!(attempt("ABCD-EFGH-JKMN".to_string()).await.is_some());
+        assert!(attempt("abcd-efgh-jkmn".to_string()).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn disabling_takes_a_code_that_cannot_be_guessed() {
+        let store = MemoryStore::new();
+        store
+            .add_account(Account {
+                id: None,
+                email: "a@b.c".to_string(),
+                password: "password".to_string(),
+            })
+            .await
+            .unwrap();
+        let account_id = AccountId(1);
+        store
+            .set_two_factor_secret(&account_id, SECRET)
+            .await
+            .unwrap();
+        store
+            .confirm_two_factor(&account_id, 0, Vec::new())
+            .await
+            .unwrap();
+        let session = Session {
+            exp: Utc::now() + chrono::Duration::minutes(5),
+            account_id,
+            role: Role::User,
+            jti: None,
+            two_factor: true,
+            scopes: None,
+        };
+
+        let attempt = |code: String| {
+            let confirmation = TwoFactorCode { code };
+            let reply = disable_two_factor(session.clone(), store.clone(), None, confirmation);
+            async move {
+                match reply.await {
+                    Ok(_) => None,
+                    Err(rejection) => rejection.find::<Error>().map(|e| e.to_string()),
+                }
+            }
+        };
+
+        for _ in 0..ACCOUNT_FREE_FAILURES {
+            assert_eq!(
+                Some(Error::InvalidTwoFactorCode.to_string()),
+                attempt("not a code".to_string()).await
+            );
+        }
+        // One more wrong code locks out even the right one
+        attempt("not a code".to_string()).await;
+        let locked = attempt(code_at(Utc::now().timestamp())).await.unwrap();
+        assert!(locked.starts_with("Too many failed logins"));
+        assert!(store.get_two_factor(&account_id).await.unwrap().is_some());
+    }
+}
@@ -0,0 +1,88 @@
+use chrono::Utc;
+use warp::{Rejection, Reply};
+
+use crate::mailer::{Email, SharedMailer};
+use crate::store::Store;
+use crate::types::{AccountId, AccountToken, EmailVerification, Session};
+
+use handle_errors::Error;
+
+use super::authentication::{hash_token, random_token};
+
+/// How long an email verification token can be used
+const VERIFICATION_TOKEN_TTL_HOURS: i64 = 24;
+
+/// Mail a single-use token that proves the account owns `email`
+pub(super) async fn send_verification_email<S: Store>(
+    store: &S,
+    mailer: &SharedMailer,
+    account_id: AccountId,
+    email: String,
+) -> Result<(), Error> {
+    let token = random_token(32);
+    store
+        .add_email_verification(AccountToken {
+            token_hash: hash_token(&token),
+            account_id,
+            expires_on: Utc::now().naive_utc()
+                + chrono::Duration::hours(VERIFICATION_TOKEN_TTL_HOURS),
+        })
+        .await?;
+
+    mailer
+        .send(Email {
+            to: email,
+            subject: "Verify your email address".to_string(),
+            body: format!(
+                "Welcome! Use this token within {} hours to verify your \
+                email address:\n\n{}\n\nIf you did not create an account, \
+                you can ignore this email.\n",
+                VERIFICATION_TOKEN_TTL_HOURS, token
+            ),
+        })
+        .await
+}
+
+/// Send a new verification email to the account making the request,
+/// for when the first one expired or got lost
+pub async fn resend_verification_email<S: Store>(
+    session: Session,
+    store: S,
+    mailer: SharedMailer,
+) -> Result<impl Reply, Rejection> {
+    if store
+        .is_email_verified(&session.account_id)
+        .await
+        .map_err(warp::reject::custom)?
+    {
+        return Ok(warp::reply::json(&"Email already verified".to_string()));
+    }
+
+    let account = store
+        .get_account_by_id(&session.account_id)
+        .await
+        .map_err(warp::reject::custom)?;
+    send_verification_email(&store, &mailer, session.account_id, account.email)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    Ok(warp::reply::json(&"Verification email sent".to_string()))
+}
+
+/// Verify an email address with a token from `send_verification_email`
+pub async fn confirm_email<S: Store>(
+    store: S,
+    verification: EmailVerification,
+) -> Result<impl Reply, Rejection> {
+    let account_id = store
+        .use_email_verification(&hash_token(&verification.token))
+        .await
+        .map_err(warp::reject::custom)?;
+
+    store
+        .set_email_verified(&account_id)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    Ok(warp::reply::json(&"Email verified".to_string()))
+}
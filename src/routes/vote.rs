@@ -0,0 +1,79 @@
+use crate::policy;
+use crate::store::Store;
+use crate::types::{NewVote, Session, VoteSummary};
+
+use warp::{Rejection, Reply};
+
+pub async fn vote_on_question<S: Store>(
+    question_id: i32,
+    session: Session,
+    store: S,
+    new_vote: NewVote,
+) -> Result<impl Reply, Rejection> {
+    let actor = policy::actor(&store, &session).await?;
+    policy::can_post(&actor)?;
+    let question = store.get_question(question_id).await?;
+    policy::check_unlocked(&actor, &question)?;
+
+    let vote = Some(new_vote.value);
+    store
+        .vote_on_question(question_id, actor.account_id, vote)
+        .await
+        .map(|score| warp::reply::json(&VoteSummary { score, vote }))
+        .map_err(warp::reject::custom)
+}
+
+pub async fn retract_question_vote<S: Store>(
+    question_id: i32,
+    session: Session,
+    store: S,
+) -> Result<impl Reply, Rejection> {
+    let actor = policy::actor(&store, &session).await?;
+    policy::can_post(&actor)?;
+    let question = store.get_question(question_id).await?;
+    policy::check_unlocked(&actor, &question)?;
+
+    store
+        .vote_on_question(question_id, actor.account_id, None)
+        .await
+        .map(|score| warp::reply::json(&VoteSummary { score, vote: None }))
+        .map_err(warp::reject::custom)
+}
+
+pub async fn vote_on_answer<S: Store>(
+    question_id: i32,
+    answer_id: i32,
+    session: Session,
+    store: S,
+    new_vote: NewVote,
+) -> Result<impl Reply, Rejection> {
+    let actor = policy::actor(&store, &session).await?;
+    policy::can_post(&actor)?;
+    let question = store.get_question(question_id).await?;
+    policy::check_unlocked(&actor, &question)?;
+
+    let vote = Some(new_vote.value);
+    store
+        .vote_on_answer(question_id, answer_id, actor.account_id, vote)
+        .await
+        .map(|score| warp::reply::json(&VoteSummary { score, vote }))
+        .map_err(warp::reject::custom)
+}
+
+pub async fn retract_answer_vote<S: Store>(
+    question_id: i32,
+    answer_id: i32,
+    session: Session,
+    store: S,
+) -> Result<impl Reply, Rejection> {
+    let actor = policy::actor(&store, &session).await?;
+    policy::can_post(&actor)?;
+    let question = store.get_question(question_id).await?;
+    policy::check_unlocked(&actor, &question)?;
+
+    store
+        .vote_on_answer(question_id, answer_id, actor.account_id, None)
+        .await
+        .map(|score| warp::reply::json(&VoteSummary { score, vote: None }))
+        .map_err(warp::reject::custom)
+}
@@ -0,0 +1,2509 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use handle_errors::Error;
+
+use crate::types::{
+    Account, AccountId, AccountStatus, AccountToken, Actor, Answer, AnswerId, AnswerSnippet,
+    ApiKey, ApiKeyId, Comment, CommentId, CommentTarget, Cursor, CursorKey, LoginFailures,
+    NewAnswer, NewApiKey, NewComment, NewQuestion, OidcLogin, Page, PageCursor, Profile,
+    ProfileUpdate, Question, QuestionId, QuestionQuery, QuestionRevision, QuestionSearchResult,
+    QuestionSort, RefreshToken, Role, Tag, TagQuery, TagUpdate, TwoFactor, Vote,
+};
+
+use super::{
+    AccountRepository, ApiKeyRepository, CommentRepository, IdentityRepository,
+    LoginAttemptRepository, QuestionRepository, TagRepository, TokenRepository,
+    TwoFactorRepository,
+};
+
+/// Store that keeps everything in process memory, so the API can be run
+/// and tested without a database. Behaves like `PgStore`: ids start at 1,
+/// emails are unique, accounts vote once per post and purging a deleted
+/// question deletes its answers and comments.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    inner: Arc<RwLock<Inner>>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    questions: BTreeMap<i32, Question>,
+    /// Deleted questions waiting to be restored or purged, kept apart
+    /// so that every lookup in `questions` leaves them out
+    deleted_questions: BTreeMap<i32, Question>,
+    answers: BTreeMap<i32, Answer>,
+    comments: BTreeMap<i32, Comment>,
+    /// Revisions of each question, oldest first
+    revisions: BTreeMap<i32, Vec<QuestionRevision>>,
+    tags: BTreeMap<String, StoredTag>,
+    accounts: BTreeMap<i32, StoredAccount>,
+    votes: BTreeMap<(i32, VoteTarget), Vote>,
+    /// Refresh tokens by their hash
+    refresh_tokens: BTreeMap<String, StoredRefreshToken>,
+    /// Password reset tokens by their hash, and whether they were used
+    password_resets: BTreeMap<String, (AccountToken, bool)>,
+    /// Email verification tokens by their hash, and whether they were used
+    email_verifications: BTreeMap<String, (AccountToken, bool)>,
+    /// Login challenges by their hash, and whether they were used
+    login_challenges: BTreeMap<String, (AccountToken, bool)>,
+    /// Logins through the identity provider by the hash of their state,
+    /// and whether they were finished
+    oidc_logins: BTreeMap<String, (OidcLogin, bool)>,
+    /// Account ids by the issuer and subject of the identity linked to them
+    identities: BTreeMap<(String, String), i32>,
+    /// TOTP secrets by account id
+    two_factor: BTreeMap<i32, TwoFactor>,
+    /// Hashes of the recovery codes not used yet, with their account id
+    recovery_codes: BTreeSet<(i32, String)>,
+    api_keys: BTreeMap<i32, StoredApiKey>,
+    /// Failed logins by the key they are counted under
+    login_failures: BTreeMap<String, LoginFailures>,
+    /// When each revoked access token expires, by its jti
+    revoked_tokens: BTreeMap<String, NaiveDateTime>,
+    last_question_id: i32,
+    last_answer_id: i32,
+    last_comment_id: i32,
+    last_account_id: i32,
+    last_api_key_id: i32,
+}
+
+/// What a vote was cast on, keyed together with the voting account
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum VoteTarget {
+    Question(i32),
+    Answer(i32),
+}
+
+#[derive(Debug, Clone, Default)]
+struct StoredTag {
+    description: Option<String>,
+    /// The canonical tag this one redirects to
+    synonym_of: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct StoredAccount {
+    email: String,
+    password: String,
+    role: Role,
+    status: AccountStatus,
+    email_verified: bool,
+    display_name: Option<String>,
+    bio: Option<String>,
+    avatar_url: Option<String>,
+    created_on: NaiveDateTime,
+}
+
+#[derive(Debug, Clone)]
+struct StoredApiKey {
+    api_key: ApiKey,
+    key_hash: String,
+    revoked: bool,
+}
+
+#[derive(Debug, Clone)]
+struct StoredRefreshToken {
+    token: RefreshToken,
+    used: bool,
+    revoked: bool,
+}
+
+impl StoredAccount {
+    fn actor(&self, account_id: AccountId) -> Actor {
+        Actor {
+            account_id,
+            role: self.role,
+            status: self.status,
+            two_factor: false,
+        }
+    }
+
+    fn profile(&self, account_id: AccountId) -> Profile {
+        Profile {
+            id: account_id,
+            display_name: self.display_name.clone(),
+            bio: self.bio.clone(),
+            avatar_url: self.avatar_url.clone(),
+            created_on: self.created_on,
+        }
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, Inner> {
+        self.inner.read().expect("memory store lock poisoned")
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Inner> {
+        self.inner.write().expect("memory store lock poisoned")
+    }
+}
+
+#[async_trait]
+impl QuestionRepository for MemoryStore {
+    async fn get_questions(&self, query: &QuestionQuery) -> Result<Page<Question>, Error> {
+        let inner = self.read();
+        let sort = query.effective_sort();
+
+        let rows = inner
+            .questions
+            .values()
+            .filter(|question| inner.matches_filters(question, query))
+            .map(|question| (question.clone(), inner.cursor(question, sort, 0.0)))
+            .collect();
+
+        Ok(paginate(rows, query, sort))
+    }
+
+    async fn search_questions(
+        &self,
+        terms: String,
+        query: &QuestionQuery,
+    ) -> Result<Page<QuestionSearchResult>, Error> {
+        let terms = search_terms(&terms);
+        let inner = self.read();
+        let sort = query.sort.unwrap_or(QuestionSort::Relevance);
+
+        let rows = inner
+            .questions
+            .values()
+            .filter(|question| inner.matches_filters(question, query))
+            .filter_map(|question| {
+                let answers: Vec<&Answer> = inner
+                    .answers
+                    .values()
+                    .filter(|answer| answer.question_id == question.id)
+                    .filter(|answer| matches_all(&answer.content, &terms))
+                    .collect();
+
+                let question_matches =
+                    matches_all(&format!("{} {}", question.title, question.content), &terms);
+                if terms.is_empty() || (!question_matches && answers.is_empty()) {
+                    return None;
+                }
+
+                // Mirror the Postgres weights: title 1.0, content 0.4,
+                // and answers count for half as much as the question
+                let question_rank = if question_matches {
+                    hits(&question.title, &terms) as f32
+                        + 0.4 * hits(&question.content, &terms) as f32
+                } else {
+                    0.0
+                };
+                let rank = answers
+                    .iter()
+                    .map(|answer| 0.5 * hits(&answer.content, &terms) as f32)
+                    .fold(question_rank, f32::max);
+
+                let result = QuestionSearchResult {
+                    question: question.clone(),
+                    rank,
+                    title_snippet: highlight(&question.title, &terms),
+                    content_snippet: highlight(&question.content, &terms),
+                    answer_snippets: answers
+                        .iter()
+                        .map(|answer| AnswerSnippet {
+                            answer_id: answer.id.clone(),
+                            snippet: highlight(&answer.content, &terms),
+                        })
+                        .collect(),
+                };
+                Some((result, inner.cursor(question, sort, rank)))
+            })
+            .collect();
+
+        Ok(paginate(rows, query, sort))
+    }
+
+    async fn get_question(&self, question_id: i32) -> Result<Question, Error> {
+        self.read()
+            .questions
+            .get(&question_id)
+            .cloned()
+            .ok_or(Error::QuestionNotFound)
+    }
+
+    async fn get_account_questions(
+        &self,
+        account_id: &AccountId,
+        limit: i64,
+    ) -> Result<Vec<Question>, Error> {
+        let inner = self.read();
+        let mut questions: Vec<Question> = inner
+            .questions
+            .values()
+            .filter(|question| question.account_id == *account_id)
+            .cloned()
+            .collect();
+        questions.sort_by_key(|question| Reverse((question.created_on, question.id.0)));
+        questions.truncate(limit.max(0) as usize);
+
+        Ok(questions)
+    }
+
+    async fn add_question(
+        &self,
+        new_question: NewQuestion,
+        account_id: AccountId,
+    ) -> Result<Question, Error> {
+        let mut inner = self.write();
+        inner.last_question_id += 1;
+
+        let question = Question {
+            id: QuestionId(inner.last_question_id),
+            title: new_question.title,
+            content: new_question.content,
+            tags: inner.resolve_tags(new_question.tags),
+            account_id,
+            created_on: Utc::now().naive_utc(),
+            score: 0,
+            accepted_answer_id: None,
+            version: 1,
+            deleted_at: None,
+            locked: false,
+        };
+        inner.questions.insert(question.id.0, question.clone());
+
+        Ok(question)
+    }
+
+    async fn update_question(
+        &self,
+        question: NewQuestion,
+        question_id: i32,
+        editor_id: AccountId,
+        version: i32,
+    ) -> Result<Question, Error> {
+        let mut guard = self.write();
+        let inner = &mut *guard;
+        if inner
+            .questions
+            .get(&question_id)
+            .ok_or(Error::QuestionNotFound)?
+            .version
+            != version
+        {
+            return Err(Error::PreconditionFailed);
+        }
+
+        let tags = inner.resolve_tags(question.tags);
+        let stored = inner
+            .questions
+            .get_mut(&question_id)
+            .ok_or(Error::QuestionNotFound)?;
+        stored.version += 1;
+
+        let revisions = inner.revisions.entry(question_id).or_default();
+        revisions.push(QuestionRevision {
+            revision: revisions.len() as i32 + 1,
+            question_id: stored.id.clone(),
+            title: std::mem::replace(&mut stored.title, question.title),
+            content: std::mem::replace(&mut stored.content, question.content),
+            tags: std::mem::replace(&mut stored.tags, tags),
+            editor_id,
+            edited_on: Utc::now().naive_utc(),
+        });
+
+        Ok(stored.clone())
+    }
+
+    async fn get_revisions(&self, question_id: i32) -> Result<Vec<QuestionRevision>, Error> {
+        let inner = self.read();
+        if !inner.questions.contains_key(&question_id) {
+            return Err(Error::QuestionNotFound);
+        }
+
+        Ok(inner
+            .revisions
+            .get(&question_id)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn get_revision(
+        &self,
+        question_id: i32,
+        revision: i32,
+    ) -> Result<QuestionRevision, Error> {
+        self.read()
+            .revisions
+            .get(&question_id)
+            .and_then(|revisions| revisions.iter().find(|stored| stored.revision == revision))
+            .cloned()
+            .ok_or(Error::RevisionNotFound)
+    }
+
+    async fn delete_question(&self, question_id: i32, version: i32) -> Result<bool, Error> {
+        let mut inner = self.write();
+        match inner.questions.get(&question_id) {
+            Some(question) if question.version != version => return Err(Error::PreconditionFailed),
+            Some(_) => {}
+            None => return Ok(false),
+        }
+
+        if let Some(mut question) = inner.questions.remove(&question_id) {
+            question.deleted_at = Some(Utc::now().naive_utc());
+            inner.deleted_questions.insert(question_id, question);
+        }
+
+        Ok(true)
+    }
+
+    async fn get_deleted_question(&self, question_id: i32) -> Result<Question, Error> {
+        self.read()
+            .deleted_questions
+            .get(&question_id)
+            .cloned()
+            .ok_or(Error::QuestionNotFound)
+    }
+
+    async fn restore_question(&self, question_id: i32) -> Result<Question, Error> {
+        let mut inner = self.write();
+        let mut question = inner
+            .deleted_questions
+            .remove(&question_id)
+            .ok_or(Error::QuestionNotFound)?;
+        question.deleted_at = None;
+        inner.questions.insert(question_id, question.clone());
+
+        Ok(question)
+    }
+
+    async fn purge_deleted_questions(&self, deleted_before: NaiveDateTime) -> Result<u64, Error> {
+        let mut inner = self.write();
+        let expired: Vec<i32> = inner
+            .deleted_questions
+            .values()
+            .filter(|question| question.deleted_at.is_some_and(|at| at < deleted_before))
+            .map(|question| question.id.0)
+            .collect();
+
+        for question_id in &expired {
+            inner.purge_question(*question_id);
+        }
+
+        Ok(expired.len() as u64)
+    }
+
+    async fn set_question_locked(&self, question_id: i32, locked: bool) -> Result<Question, Error> {
+        let mut inner = self.write();
+        let question = inner
+            .questions
+            .get_mut(&question_id)
+            .ok_or(Error::QuestionNotFound)?;
+        question.locked = locked;
+
+        Ok(question.clone())
+    }
+
+    async fn get_question_owner(&self, question_id: i32) -> Result<AccountId, Error> {
+        self.read()
+            .questions
+            .get(&question_id)
+            .map(|question| question.account_id)
+            .ok_or(Error::QuestionNotFound)
+    }
+
+    async fn is_question_owner(
+        &self,
+        question_id: i32,
+        account_id: &AccountId,
+    ) -> Result<bool, Error> {
+        Ok(self
+            .read()
+            .questions
+            .get(&question_id)
+            .is_some_and(|question| question.account_id == *account_id))
+    }
+
+    async fn get_answers_for_question(&self, question_id: i32) -> Result<Vec<Answer>, Error> {
+        let inner = self.read();
+        let Some(question) = inner.questions.get(&question_id) else {
+            return Ok(Vec::new());
+        };
+        let accepted = question.accepted_answer_id.clone();
+
+        // Answers are keyed by id, which already follows creation order,
+        // and the sort is stable
+        let mut answers: Vec<Answer> = inner
+            .answers
+            .values()
+            .filter(|answer| answer.question_id.0 == question_id)
+            .cloned()
+            .collect();
+        answers.sort_by_key(|answer| Some(&answer.id) != accepted.as_ref());
+
+        Ok(answers)
+    }
+
+    async fn get_account_answers(
+        &self,
+        account_id: &AccountId,
+        limit: i64,
+    ) -> Result<Vec<Answer>, Error> {
+        let inner = self.read();
+        let mut answers: Vec<Answer> = inner
+            .answers
+            .values()
+            .filter(|answer| {
+                answer.account_id == *account_id
+                    && inner.questions.contains_key(&answer.question_id.0)
+            })
+            .cloned()
+            .collect();
+        answers.sort_by_key(|answer| Reverse((answer.created_on, answer.id.0)));
+        answers.truncate(limit.max(0) as usize);
+
+        Ok(answers)
+    }
+
+    async fn get_answer(&self, question_id: i32, answer_id: i32) -> Result<Answer, Error> {
+        let inner = self.read();
+        inner
+            .answers
+            .get(&answer_id)
+            .filter(|answer| answer.question_id.0 == question_id)
+            .filter(|_| inner.questions.contains_key(&question_id))
+            .cloned()
+            .ok_or(Error::AnswerNotFound)
+    }
+
+    async fn get_answer_question(&self, answer_id: i32) -> Result<Question, Error> {
+        let inner = self.read();
+        inner
+            .answers
+            .get(&answer_id)
+            .and_then(|answer| inner.questions.get(&answer.question_id.0))
+            .cloned()
+            .ok_or(Error::AnswerNotFound)
+    }
+
+    async fn add_answer(
+        &self,
+        question_id: i32,
+        new_answer: NewAnswer,
+        account_id: AccountId,
+    ) -> Result<Answer, Error> {
+        let mut inner = self.write();
+        if !inner.questions.contains_key(&question_id) {
+            return Err(Error::QuestionNotFound);
+        }
+        inner.last_answer_id += 1;
+
+        let answer = Answer {
+            id: AnswerId(inner.last_answer_id),
+            content: new_answer.content,
+            question_id: QuestionId(question_id),
+            account_id,
+            created_on: Utc::now().naive_utc(),
+            score: 0,
+        };
+        inner.answers.insert(answer.id.0, answer.clone());
+
+        Ok(answer)
+    }
+
+    async fn update_answer(
+        &self,
+        question_id: i32,
+        answer_id: i32,
+        answer: NewAnswer,
+    ) -> Result<Answer, Error> {
+        let mut inner = self.write();
+        let stored = inner
+            .answers
+            .get_mut(&answer_id)
+            .filter(|answer| answer.question_id.0 == question_id)
+            .ok_or(Error::AnswerNotFound)?;
+
+        stored.content = answer.content;
+
+        Ok(stored.clone())
+    }
+
+    async fn delete_answer(&self, question_id: i32, answer_id: i32) -> Result<bool, Error> {
+        let mut inner = self.write();
+        match inner.answers.get(&answer_id) {
+            Some(answer) if answer.question_id.0 == question_id => {
+                inner.answers.remove(&answer_id);
+                inner
+                    .comments
+                    .retain(|_, comment| comment.answer_id != Some(AnswerId(answer_id)));
+                if let Some(question) = inner.questions.get_mut(&question_id) {
+                    if question.accepted_answer_id == Some(AnswerId(answer_id)) {
+                        question.accepted_answer_id = None;
+                    }
+                }
+                inner
+                    .votes
+                    .retain(|(_, target), _| *target != VoteTarget::Answer(answer_id));
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn accept_answer(&self, question_id: i32, answer_id: i32) -> Result<Question, Error> {
+        let mut inner = self.write();
+        if inner
+            .answers
+            .get(&answer_id)
+            .is_none_or(|answer| answer.question_id.0 != question_id)
+        {
+            return Err(Error::AnswerNotFound);
+        }
+
+        let question = inner
+            .questions
+            .get_mut(&question_id)
+            .ok_or(Error::QuestionNotFound)?;
+        question.accepted_answer_id = Some(AnswerId(answer_id));
+
+        Ok(question.clone())
+    }
+
+    async fn unaccept_answer(&self, question_id: i32, answer_id: i32) -> Result<Question, Error> {
+        let mut inner = self.write();
+        let question = inner
+            .questions
+            .get_mut(&question_id)
+            .ok_or(Error::QuestionNotFound)?;
+        if question.accepted_answer_id == Some(AnswerId(answer_id)) {
+            question.accepted_answer_id = None;
+        }
+
+        Ok(question.clone())
+    }
+
+    async fn vote_on_question(
+        &self,
+        question_id: i32,
+        account_id: AccountId,
+        vote: Option<Vote>,
+    ) -> Result<i32, Error> {
+        let mut inner = self.write();
+        if !inner.questions.contains_key(&question_id) {
+            return Err(Error::QuestionNotFound);
+        }
+
+        let score = inner.cast_vote(account_id, VoteTarget::Question(question_id), vote);
+        if let Some(question) = inner.questions.get_mut(&question_id) {
+            question.score = score;
+        }
+
+        Ok(score)
+    }
+
+    async fn vote_on_answer(
+        &self,
+        question_id: i32,
+        answer_id: i32,
+        account_id: AccountId,
+        vote: Option<Vote>,
+    ) -> Result<i32, Error> {
+        let mut inner = self.write();
+        if !inner.questions.contains_key(&question_id)
+            || inner
+                .answers
+                .get(&answer_id)
+                .is_none_or(|answer| answer.question_id.0 != question_id)
+        {
+            return Err(Error::AnswerNotFound);
+        }
+
+        let score = inner.cast_vote(account_id, VoteTarget::Answer(answer_id), vote);
+        if let Some(answer) = inner.answers.get_mut(&answer_id) {
+            answer.score = score;
+        }
+
+        Ok(score)
+    }
+}
+
+#[async_trait]
+impl CommentRepository for MemoryStore {
+    async fn get_comments(&self, target: CommentTarget) -> Result<Vec<Comment>, Error> {
+        let inner = self.read();
+        inner.check_comment_target(target)?;
+
+        // Comments are keyed by id, which already follows creation order
+        Ok(inner
+            .comments
+            .values()
+            .filter(|comment| is_comment_on(comment, target))
+            .cloned()
+            .collect())
+    }
+
+    async fn get_comment(&self, target: CommentTarget, comment_id: i32) -> Result<Comment, Error> {
+        let inner = self.read();
+        inner.check_comment_target(target)?;
+
+        inner
+            .comments
+            .get(&comment_id)
+            .filter(|comment| is_comment_on(comment, target))
+            .cloned()
+            .ok_or(Error::CommentNotFound)
+    }
+
+    async fn add_comment(
+        &self,
+        target: CommentTarget,
+        new_comment: NewComment,
+        account_id: AccountId,
+    ) -> Result<Comment, Error> {
+        let mut inner = self.write();
+        inner.check_comment_target(target)?;
+        inner.last_comment_id += 1;
+
+        let (question_id, answer_id) = match target {
+            CommentTarget::Question(id) => (Some(QuestionId(id)), None),
+            CommentTarget::Answer(id) => (None, Some(AnswerId(id))),
+        };
+        let comment = Comment {
+            id: CommentId(inner.last_comment_id),
+            content: new_comment.content,
+            question_id,
+            answer_id,
+            account_id,
+            created_on: Utc::now().naive_utc(),
+        };
+        inner.comments.insert(comment.id.0, comment.clone());
+
+        Ok(comment)
+    }
+
+    async fn update_comment(
+        &self,
+        target: CommentTarget,
+        comment_id: i32,
+        comment: NewComment,
+    ) -> Result<Comment, Error> {
+        let mut inner = self.write();
+        inner.check_comment_target(target)?;
+        let stored = inner
+            .comments
+            .get_mut(&comment_id)
+            .filter(|comment| is_comment_on(comment, target))
+            .ok_or(Error::CommentNotFound)?;
+
+        stored.content = comment.content;
+
+        Ok(stored.clone())
+    }
+
+    async fn delete_comment(&self, target: CommentTarget, comment_id: i32) -> Result<bool, Error> {
+        let mut inner = self.write();
+        inner.check_comment_target(target)?;
+        match inner.comments.get(&comment_id) {
+            Some(comment) if is_comment_on(comment, target) => {
+                inner.comments.remove(&comment_id);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+#[async_trait]
+impl TagRepository for MemoryStore {
+    async fn get_tags(&self, query: &TagQuery) -> Result<Vec<Tag>, Error> {
+        let inner = self.read();
+        let matches = |name: &str| {
+            query
+                .prefix
+                .as_ref()
+                .is_none_or(|prefix| name.starts_with(prefix.as_str()))
+        };
+
+        let mut tags: Vec<Tag> = inner
+            .tags
+            .iter()
+            .filter(|(_, stored)| stored.synonym_of.is_none())
+            .map(|(name, _)| inner.tag(name))
+            .filter(|tag| matches(&tag.name) || tag.synonyms.iter().any(|name| matches(name)))
+            .collect();
+        tags.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+        tags.truncate(query.limit.max(0) as usize);
+
+        Ok(tags)
+    }
+
+    async fn get_tag(&self, name: &str) -> Result<Tag, Error> {
+        let inner = self.read();
+        let canonical = inner.canonical_tag(name).ok_or(Error::TagNotFound)?;
+
+        Ok(inner.tag(&canonical))
+    }
+
+    async fn update_tag(&self, name: &str, tag: TagUpdate) -> Result<Tag, Error> {
+        let mut inner = self.write();
+        let canonical = inner.canonical_tag(name).ok_or(Error::TagNotFound)?;
+        if let Some(stored) = inner.tags.get_mut(&canonical) {
+            stored.description = tag.description;
+        }
+
+        Ok(inner.tag(&canonical))
+    }
+
+    async fn add_tag_synonym(&self, name: &str, synonym: &str) -> Result<Tag, Error> {
+        let mut inner = self.write();
+        let canonical = inner.canonical_tag(name).ok_or(Error::TagNotFound)?;
+        if synonym == canonical {
+            return Err(Error::InvalidParameter(
+                "a tag cannot be a synonym of itself".to_string(),
+            ));
+        }
+
+        // The synonym and the synonyms it had all redirect to the canonical tag,
+        // and the questions filed under it move over as well
+        inner
+            .tags
+            .entry(synonym.to_string())
+            .or_default()
+            .synonym_of = Some(canonical.clone());
+        for stored in inner.tags.values_mut() {
+            if stored.synonym_of.as_deref() == Some(synonym) {
+                stored.synonym_of = Some(canonical.clone());
+            }
+        }
+        let inner = &mut *inner;
+        let questions = inner
+            .questions
+            .values_mut()
+            .chain(inner.deleted_questions.values_mut());
+        for question in questions {
+            if let Some(tags) = question.tags.as_mut() {
+                if tags.iter().any(|tag| tag == synonym) {
+                    let mut merged = Vec::with_capacity(tags.len());
+                    for tag in tags.drain(..) {
+                        let tag = if tag == synonym {
+                            canonical.clone()
+                        } else {
+                            tag
+                        };
+                        if !merged.contains(&tag) {
+                            merged.push(tag);
+                        }
+                    }
+                    *tags = merged;
+                }
+            }
+        }
+
+        Ok(inner.tag(&canonical))
+    }
+
+    async fn remove_tag_synonym(&self, name: &str, synonym: &str) -> Result<Tag, Error> {
+        let mut inner = self.write();
+        let canonical = inner.canonical_tag(name).ok_or(Error::TagNotFound)?;
+        let stored = inner
+            .tags
+            .get_mut(synonym)
+            .filter(|stored| stored.synonym_of.as_ref() == Some(&canonical))
+            .ok_or(Error::TagNotFound)?;
+        stored.synonym_of = None;
+
+        Ok(inner.tag(&canonical))
+    }
+}
+
+#[async_trait]
+impl AccountRepository for MemoryStore {
+    async fn add_account(&self, account: Account) -> Result<bool, Error> {
+        let mut inner = self.write();
+        if inner
+            .accounts
+            .values()
+            .any(|stored| stored.email == account.email)
+        {
+            return Err(Error::AccountAlreadyExists);
+        }
+        inner.last_account_id += 1;
+
+        let id = inner.last_account_id;
+        inner.accounts.insert(
+            id,
+            StoredAccount {
+                email: account.email,
+                password: account.password,
+                role: Role::User,
+                status: AccountStatus::Active,
+                email_verified: false,
+                display_name: None,
+                bio: None,
+                avatar_url: None,
+                created_on: Utc::now().naive_utc(),
+            },
+        );
+
+        Ok(true)
+    }
+
+    async fn get_account(&self, email: String) -> Result<Account, Error> {
+        self.read()
+            .accounts
+            .iter()
+            .find(|(_, stored)| stored.email == email)
+            .map(|(id, stored)| Account {
+                id: Some(AccountId(*id)),
+                email: stored.email.clone(),
+                password: stored.password.clone(),
+            })
+            .ok_or(Error::AccountNotFound)
+    }
+
+    async fn get_account_by_id(&self, account_id: &AccountId) -> Result<Account, Error> {
+        self.read()
+            .accounts
+            .get(&account_id.0)
+            .map(|stored| Account {
+                id: Some(*account_id),
+                email: stored.email.clone(),
+                password: stored.password.clone(),
+            })
+            .ok_or(Error::UnknownAccount)
+    }
+
+    async fn is_email_verified(&self, account_id: &AccountId) -> Result<bool, Error> {
+        self.read()
+            .accounts
+            .get(&account_id.0)
+            .map(|stored| stored.email_verified)
+            .ok_or(Error::UnknownAccount)
+    }
+
+    async fn set_email_verified(&self, account_id: &AccountId) -> Result<bool, Error> {
+        let mut inner = self.write();
+        let stored = inner
+            .accounts
+            .get_mut(&account_id.0)
+            .ok_or(Error::UnknownAccount)?;
+        stored.email_verified = true;
+
+        Ok(true)
+    }
+
+    async fn set_account_password(
+        &self,
+        account_id: &AccountId,
+        password: String,
+    ) -> Result<bool, Error> {
+        let mut inner = self.write();
+        let stored = inner
+            .accounts
+            .get_mut(&account_id.0)
+            .ok_or(Error::UnknownAccount)?;
+        stored.password = password;
+
+        Ok(true)
+    }
+
+    async fn get_actor(&self, account_id: &AccountId) -> Result<Actor, Error> {
+        self.read()
+            .accounts
+            .get(&account_id.0)
+            .map(|stored| stored.actor(*account_id))
+            .ok_or(Error::Unauthorized)
+    }
+
+    async fn set_account_role(&self, account_id: &AccountId, role: Role) -> Result<Actor, Error> {
+        let mut inner = self.write();
+        let stored = inner
+            .accounts
+            .get_mut(&account_id.0)
+            .ok_or(Error::UnknownAccount)?;
+        stored.role = role;
+
+        Ok(stored.actor(*account_id))
+    }
+
+    async fn set_account_status(
+        &self,
+        account_id: &AccountId,
+        status: AccountStatus,
+    ) -> Result<Actor, Error> {
+        let mut inner = self.write();
+        let stored = inner
+            .accounts
+            .get_mut(&account_id.0)
+            .ok_or(Error::UnknownAccount)?;
+        stored.status = status;
+
+        Ok(stored.actor(*account_id))
+    }
+
+    async fn get_profile(&self, account_id: &AccountId) -> Result<Profile, Error> {
+        self.read()
+            .accounts
+            .get(&account_id.0)
+            .map(|stored| stored.profile(*account_id))
+            .ok_or(Error::UnknownAccount)
+    }
+
+    async fn update_profile(
+        &self,
+        account_id: &AccountId,
+        update: ProfileUpdate,
+    ) -> Result<Profile, Error> {
+        let mut inner = self.write();
+        let stored = inner
+            .accounts
+            .get_mut(&account_id.0)
+            .ok_or(Error::UnknownAccount)?;
+
+        // Left out keeps a field, empty clears it
+        let apply = |field: &mut Option<String>, value: Option<String>| {
+            if let Some(value) = value {
+                *field = Some(value).filter(|value| !value.is_empty());
+            }
+        };
+        apply(&mut stored.display_name, update.display_name);
+        apply(&mut stored.bio, update.bio);
+        apply(&mut stored.avatar_url, update.avatar_url);
+
+        Ok(stored.profile(*account_id))
+    }
+}
+
+#[async_trait]
+impl TokenRepository for MemoryStore {
+    async fn add_refresh_token(&self, token: RefreshToken) -> Result<bool, Error> {
+        self.write().refresh_tokens.insert(
+            token.token_hash.clone(),
+            StoredRefreshToken {
+                token,
+                used: false,
+                revoked: false,
+            },
+        );
+
+        Ok(true)
+    }
+
+    async fn use_refresh_token(&self, token_hash: &str) -> Result<RefreshToken, Error> {
+        let mut inner = self.write();
+        let stored = inner
+            .refresh_tokens
+            .get_mut(token_hash)
+            .ok_or(Error::InvalidRefreshToken)?;
+
+        if stored.revoked || stored.token.expires_on <= Utc::now().naive_utc() {
+            return Err(Error::InvalidRefreshToken);
+        }
+        if stored.used {
+            let family = stored.token.family.clone();
+            inner
+                .refresh_tokens
+                .values_mut()
+                .filter(|stored| stored.token.family == family)
+                .for_each(|stored| stored.revoked = true);
+            return Err(Error::InvalidRefreshToken);
+        }
+
+        stored.used = true;
+        Ok(stored.token.clone())
+    }
+
+    async fn revoke_refresh_token_family(
+        &self,
+        token_hash: &str,
+        account_id: &AccountId,
+    ) -> Result<bool, Error> {
+        let mut inner = self.write();
+        let family = inner
+            .refresh_tokens
+            .get(token_hash)
+            .filter(|stored| stored.token.account_id == *account_id)
+            .map(|stored| stored.token.family.clone())
+            .ok_or(Error::InvalidRefreshToken)?;
+
+        inner
+            .refresh_tokens
+            .values_mut()
+            .filter(|stored| stored.token.family == family)
+            .for_each(|stored| stored.revoked = true);
+
+        Ok(true)
+    }
+
+    async fn revoke_refresh_tokens(&self, account_id: &AccountId) -> Result<u64, Error> {
+        let mut revoked = 0;
+        self.write()
+            .refresh_tokens
+            .values_mut()
+            .filter(|stored| stored.token.account_id == *account_id && !stored.revoked)
+            .for_each(|stored| {
+                stored.revoked = true;
+                revoked += 1;
+            });
+
+        Ok(revoked)
+    }
+
+    async fn add_password_reset(&self, token: AccountToken) -> Result<bool, Error> {
+        self.write()
+            .password_resets
+            .insert(token.token_hash.clone(), (token, false));
+
+        Ok(true)
+    }
+
+    async fn use_password_reset(&self, token_hash: &str) -> Result<AccountId, Error> {
+        use_account_token(&mut self.write().password_resets, token_hash)
+            .ok_or(Error::InvalidResetToken)
+    }
+
+    async fn add_email_verification(&self, token: AccountToken) -> Result<bool, Error> {
+        self.write()
+            .email_verifications
+            .insert(token.token_hash.clone(), (token, false));
+
+        Ok(true)
+    }
+
+    async fn use_email_verification(&self, token_hash: &str) -> Result<AccountId, Error> {
+        use_account_token(&mut self.write().email_verifications, token_hash)
+            .ok_or(Error::InvalidVerificationToken)
+    }
+
+    async fn add_login_challenge(&self, token: AccountToken) -> Result<bool, Error> {
+        self.write()
+            .login_challenges
+            .insert(token.token_hash.clone(), (token, false));
+
+        Ok(true)
+    }
+
+    async fn use_login_challenge(&self, token_hash: &str) -> Result<AccountId, Error> {
+        use_account_token(&mut self.write().login_challenges, token_hash)
+            .ok_or(Error::InvalidLoginChallenge)
+    }
+
+    async fn revoke_access_token(
+        &self,
+        jti: &str,
+        expires_on: NaiveDateTime,
+    ) -> Result<bool, Error> {
+        self.write()
+            .revoked_tokens
+            .insert(jti.to_string(), expires_on);
+
+        Ok(true)
+    }
+
+    async fn is_access_token_revoked(&self, jti: &str) -> Result<bool, Error> {
+        Ok(self.read().revoked_tokens.contains_key(jti))
+    }
+
+    async fn purge_expired_tokens(&self, now: NaiveDateTime) -> Result<u64, Error> {
+        let mut inner = self.write();
+        let count = |inner: &Inner| {
+            inner.refresh_tokens.len()
+                + inner.password_resets.len()
+                + inner.email_verifications.len()
+                + inner.login_challenges.len()
+                + inner.oidc_logins.len()
+                + inner.revoked_tokens.len()
+        };
+        let before = count(&inner);
+
+        inner
+            .refresh_tokens
+            .retain(|_, stored| stored.token.expires_on >= now);
+        inner
+            .password_resets
+            .retain(|_, (token, _)| token.expires_on >= now);
+        inner
+            .email_verifications
+            .retain(|_, (token, _)| token.expires_on >= now);
+        inner
+            .login_challenges
+            .retain(|_, (token, _)| token.expires_on >= now);
+        inner
+            .oidc_logins
+            .retain(|_, (login, _)| login.expires_on >= now);
+        inner
+            .revoked_tokens
+            .retain(|_, expires_on| *expires_on >= now);
+
+        Ok((before - count(&inner)) as u64)
+    }
+}
+
+#[async_trait]
+impl LoginAttemptRepository for MemoryStore {
+    async fn get_login_failures(&self, key: &str) -> Result<Option<LoginFailures>, Error> {
+        Ok(self.read().login_failures.get(key).copied())
+    }
+
+    async fn record_login_failure(
+        &self,
+        key: &str,
+        now: NaiveDateTime,
+        reset_before: NaiveDateTime,
+    ) -> Result<LoginFailures, Error> {
+        let mut inner = self.write();
+        let failures = inner
+            .login_failures
+            .entry(key.to_string())
+            .or_insert(LoginFailures {
+                failures: 0,
+                last_failure: now,
+            });
+
+        if failures.last_failure < reset_before {
+            failures.failures = 0;
+        }
+        failures.failures += 1;
+        failures.last_failure = now;
+
+        Ok(*failures)
+    }
+
+    async fn clear_login_failures(&self, key: &str) -> Result<bool, Error> {
+        Ok(self.write().login_failures.remove(key).is_some())
+    }
+
+    async fn purge_login_failures(&self, before: NaiveDateTime) -> Result<u64, Error> {
+        let mut inner = self.write();
+        let count = inner.login_failures.len();
+        inner
+            .login_failures
+            .retain(|_, failures| failures.last_failure >= before);
+
+        Ok((count - inner.login_failures.len()) as u64)
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepository for MemoryStore {
+    async fn add_api_key(
+        &self,
+        account_id: &AccountId,
+        key: NewApiKey,
+        prefix: &str,
+        key_hash: &str,
+    ) -> Result<ApiKey, Error> {
+        let mut inner = self.write();
+        inner.last_api_key_id += 1;
+        let api_key = ApiKey {
+            id: ApiKeyId(inner.last_api_key_id),
+            account_id: *account_id,
+            name: key.name,
+            prefix: prefix.to_string(),
+            scopes: key.scopes,
+            created_on: Utc::now().naive_utc(),
+            last_used_on: None,
+        };
+        inner.api_keys.insert(
+            api_key.id.0,
+            StoredApiKey {
+                api_key: api_key.clone(),
+                key_hash: key_hash.to_string(),
+                revoked: false,
+            },
+        );
+
+        Ok(api_key)
+    }
+
+    async fn get_api_keys(&self, account_id: &AccountId) -> Result<Vec<ApiKey>, Error> {
+        Ok(self
+            .read()
+            .api_keys
+            .values()
+            .filter(|stored| !stored.revoked && stored.api_key.account_id == *account_id)
+            .map(|stored| stored.api_key.clone())
+            .collect())
+    }
+
+    async fn revoke_api_key(
+        &self,
+        account_id: &AccountId,
+        api_key_id: ApiKeyId,
+    ) -> Result<bool, Error> {
+        match self.write().api_keys.get_mut(&api_key_id.0) {
+            Some(stored) if !stored.revoked && stored.api_key.account_id == *account_id => {
+                stored.revoked = true;
+                Ok(true)
+            }
+            _ => Err(Error::ApiKeyNotFound),
+        }
+    }
+
+    async fn use_api_key(&self, key_hash: &str, now: NaiveDateTime) -> Result<ApiKey, Error> {
+        let mut inner = self.write();
+        let stored = inner
+            .api_keys
+            .values_mut()
+            .find(|stored| !stored.revoked && stored.key_hash == key_hash)
+            .ok_or(Error::InvalidApiKey)?;
+        stored.api_key.last_used_on = Some(now);
+
+        Ok(stored.api_key.clone())
+    }
+}
+
+#[async_trait]
+impl TwoFactorRepository for MemoryStore {
+    async fn get_two_factor(&self, account_id: &AccountId) -> Result<Option<TwoFactor>, Error> {
+        Ok(self.read().two_factor.get(&account_id.0).cloned())
+    }
+
+    async fn set_two_factor_secret(
+        &self,
+        account_id: &AccountId,
+        secret: &str,
+    ) -> Result<bool, Error> {
+        let mut inner = self.write();
+        if let Some(TwoFactor {
+            confirmed: true, ..
+        }) = inner.two_factor.get(&account_id.0)
+        {
+            return Err(Error::TwoFactorAlreadyEnabled);
+        }
+
+        inner.two_factor.insert(
+            account_id.0,
+            TwoFactor {
+                account_id: *account_id,
+                secret: secret.to_string(),
+                confirmed: false,
+                last_used_step: 0,
+            },
+        );
+
+        Ok(true)
+    }
+
+    async fn confirm_two_factor(
+        &self,
+        account_id: &AccountId,
+        step: i64,
+        recovery_code_hashes: Vec<String>,
+    ) -> Result<bool, Error> {
+        let mut inner = self.write();
+        let two_factor = inner
+            .two_factor
+            .get_mut(&account_id.0)
+            .ok_or(Error::TwoFactorNotEnabled)?;
+        two_factor.confirmed = true;
+        two_factor.last_used_step = step;
+
+        inner.recovery_codes.retain(|(id, _)| *id != account_id.0);
+        inner.recovery_codes.extend(
+            recovery_code_hashes
+                .into_iter()
+                .map(|hash| (account_id.0, hash)),
+        );
+
+        Ok(true)
+    }
+
+    async fn disable_two_factor(&self, account_id: &AccountId) -> Result<bool, Error> {
+        let mut inner = self.write();
+        inner.recovery_codes.retain(|(id, _)| *id != account_id.0);
+
+        Ok(inner.two_factor.remove(&account_id.0).is_some())
+    }
+
+    async fn use_totp_step(&self, account_id: &AccountId, step: i64) -> Result<bool, Error> {
+        match self.write().two_factor.get_mut(&account_id.0) {
+            Some(two_factor) if two_factor.last_used_step < step => {
+                two_factor.last_used_step = step;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn use_recovery_code(
+        &self,
+        account_id: &AccountId,
+        code_hash: &str,
+    ) -> Result<bool, Error> {
+        Ok(self
+            .write()
+            .recovery_codes
+            .remove(&(account_id.0, code_hash.to_string())))
+    }
+}
+
+#[async_trait]
+impl IdentityRepository for MemoryStore {
+    async fn add_oidc_login(&self, login: OidcLogin) -> Result<bool, Error> {
+        self.write()
+            .oidc_logins
+            .insert(login.state_hash.clone(), (login, false));
+
+        Ok(true)
+    }
+
+    async fn use_oidc_login(&self, state_hash: &str) -> Result<OidcLogin, Error> {
+        match self.write().oidc_logins.get_mut(state_hash) {
+            Some((login, used)) if !*used && login.expires_on > Utc::now().naive_utc() => {
+                *used = true;
+                Ok(login.clone())
+            }
+            _ => Err(Error::InvalidOidcState),
+        }
+    }
+
+    async fn get_identity_account(
+        &self,
+        issuer: &str,
+        subject: &str,
+    ) -> Result<Option<AccountId>, Error> {
+        Ok(self
+            .read()
+            .identities
+            .get(&(issuer.to_string(), subject.to_string()))
+            .map(|id| AccountId(*id)))
+    }
+
+    async fn link_identity(
+        &self,
+        issuer: &str,
+        subject: &str,
+        account_id: &AccountId,
+    ) -> Result<bool, Error> {
+        let mut inner = self.write();
+        let identity = (issuer.to_string(), subject.to_string());
+        if inner.identities.contains_key(&identity) {
+            return Ok(false);
+        }
+        inner.identities.insert(identity, account_id.0);
+
+        Ok(true)
+    }
+}
+
+impl Inner {
+    fn check_comment_target(&self, target: CommentTarget) -> Result<(), Error> {
+        match target {
+            CommentTarget::Question(id) if !self.questions.contains_key(&id) => {
+                Err(Error::QuestionNotFound)
+            }
+            CommentTarget::Answer(id)
+                if self
+                    .answers
+                    .get(&id)
+                    .is_none_or(|answer| !self.questions.contains_key(&answer.question_id.0)) =>
+            {
+                Err(Error::AnswerNotFound)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Remove a deleted question for good, with everything posted on it
+    fn purge_question(&mut self, question_id: i32) {
+        self.deleted_questions.remove(&question_id);
+        self.revisions.remove(&question_id);
+        let answers = &self.answers;
+        self.votes.retain(|(_, target), _| match target {
+            VoteTarget::Question(id) => *id != question_id,
+            VoteTarget::Answer(id) => answers
+                .get(id)
+                .is_some_and(|answer| answer.question_id.0 != question_id),
+        });
+        self.comments.retain(|_, comment| {
+            comment.question_id.as_ref().map(|id| id.0) != Some(question_id)
+                && comment.answer_id.as_ref().is_none_or(|id| {
+                    answers
+                        .get(&id.0)
+                        .is_some_and(|answer| answer.question_id.0 != question_id)
+                })
+        });
+        self.answers
+            .retain(|_, answer| answer.question_id.0 != question_id);
+    }
+
+    /// Record or retract a vote and return the new score of its target
+    fn cast_vote(&mut self, account_id: AccountId, target: VoteTarget, vote: Option<Vote>) -> i32 {
+        match vote {
+            Some(vote) => self.votes.insert((account_id.0, target), vote),
+            None => self.votes.remove(&(account_id.0, target)),
+        };
+
+        self.votes
+            .iter()
+            .filter(|((_, voted_on), _)| *voted_on == target)
+            .map(|(_, vote)| i32::from(i16::from(*vote)))
+            .sum()
+    }
+
+    /// The tag `name` redirects to, or `name` itself if it isn't a synonym
+    fn canonical_tag(&self, name: &str) -> Option<String> {
+        self.tags.get(name).map(|stored| {
+            stored
+                .synonym_of
+                .clone()
+                .unwrap_or_else(|| name.to_string())
+        })
+    }
+
+    /// Replace every tag by its canonical tag, creating the ones that
+    /// don't exist yet, and drop the duplicates this leaves
+    fn resolve_tags(&mut self, tags: Option<Vec<String>>) -> Option<Vec<String>> {
+        let tags = tags?;
+
+        let mut resolved: Vec<String> = Vec::with_capacity(tags.len());
+        for name in tags {
+            let tag = self.tags.entry(name.clone()).or_default();
+            let canonical = tag.synonym_of.clone().unwrap_or(name);
+            if !resolved.contains(&canonical) {
+                resolved.push(canonical);
+            }
+        }
+
+        Some(resolved)
+    }
+
+    /// A canonical tag with its synonyms and the number of questions using it
+    fn tag(&self, name: &str) -> Tag {
+        Tag {
+            name: name.to_string(),
+            description: self
+                .tags
+                .get(name)
+                .and_then(|stored| stored.description.clone()),
+            synonyms: self
+                .tags
+                .iter()
+                .filter(|(_, stored)| stored.synonym_of.as_deref() == Some(name))
+                .map(|(synonym, _)| synonym.clone())
+                .collect(),
+            count: self
+                .questions
+                .values()
+                .filter(|question| {
+                    question
+                        .tags
+                        .as_ref()
+                        .is_some_and(|tags| tags.iter().any(|tag| tag == name))
+                })
+                .count() as i64,
+        }
+    }
+
+    fn matches_filters(&self, question: &Question, query: &QuestionQuery) -> bool {
+        if let Some(tag) = &query.tag {
+            let tag = self.canonical_tag(tag);
+            if !question
+                .tags
+                .as_ref()
+                .is_some_and(|tags| tag.is_some_and(|tag| tags.contains(&tag)))
+            {
+                return false;
+            }
+        }
+        if query
+            .author
+            .is_some_and(|author| author != question.account_id)
+        {
+            return false;
+        }
+        if query
+            .created_after
+            .is_some_and(|after| question.created_on <= after)
+        {
+            return false;
+        }
+        if query
+            .created_before
+            .is_some_and(|before| question.created_on >= before)
+        {
+            return false;
+        }
+        if query
+            .answered
+            .is_some_and(|answered| answered != question.accepted_answer_id.is_some())
+        {
+            return false;
+        }
+        if query.unanswered
+            && self
+                .answers
+                .values()
+                .any(|answer| answer.question_id == question.id)
+        {
+            return false;
+        }
+
+        true
+    }
+
+    /// When the question was asked or last answered
+    fn last_activity(&self, question: &Question) -> NaiveDateTime {
+        self.answers
+            .values()
+            .filter(|answer| answer.question_id == question.id)
+            .map(|answer| answer.created_on)
+            .fold(question.created_on, NaiveDateTime::max)
+    }
+
+    /// Where a question sits in a list sorted by `sort`, with the
+    /// search `rank` it was given if any
+    fn cursor(&self, question: &Question, sort: QuestionSort, rank: f32) -> Cursor {
+        let key = match sort {
+            QuestionSort::Newest | QuestionSort::Oldest => CursorKey::Created(question.created_on),
+            QuestionSort::Activity => CursorKey::Activity(self.last_activity(question)),
+            QuestionSort::Relevance => CursorKey::Rank(rank),
+            QuestionSort::Score => CursorKey::Score(question.score),
+        };
+
+        Cursor {
+            key,
+            id: question.id.0,
+        }
+    }
+}
+
+fn is_comment_on(comment: &Comment, target: CommentTarget) -> bool {
+    match target {
+        CommentTarget::Question(id) => comment.question_id == Some(QuestionId(id)),
+        CommentTarget::Answer(id) => comment.answer_id == Some(AnswerId(id)),
+    }
+}
+
+/// Sort, count and cut out the page a query asks for, the same way
+/// `PgStore` does with its keyset conditions
+fn paginate<T>(mut rows: Vec<(T, Cursor)>, query: &QuestionQuery, sort: QuestionSort) -> Page<T> {
+    let descending = sort.is_descending();
+    let list_order = |a: &Cursor, b: &Cursor| {
+        let ordering = compare_cursors(a, b);
+        if descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    };
+
+    rows.sort_by(|(_, a), (_, b)| list_order(a, b));
+    let total = query.count.then_some(rows.len() as i64);
+
+    match &query.cursor {
+        Some(PageCursor::After(cursor)) => {
+            rows.retain(|(_, row)| list_order(row, cursor) == Ordering::Greater);
+        }
+        Some(PageCursor::Before(cursor)) => {
+            rows.retain(|(_, row)| list_order(row, cursor) == Ordering::Less);
+            rows.reverse();
+        }
+        None => {}
+    }
+
+    let pagination = &query.pagination;
+    let rows = rows
+        .into_iter()
+        .skip(pagination.offset.max(0) as usize)
+        .take(
+            pagination
+                .fetch_limit()
+                .map_or(usize::MAX, |limit| limit.max(0) as usize),
+        )
+        .collect();
+
+    Page::from_rows(rows, pagination, query.cursor.as_ref(), total)
+}
+
+fn compare_cursors(a: &Cursor, b: &Cursor) -> Ordering {
+    let keys = match (&a.key, &b.key) {
+        (CursorKey::Created(a), CursorKey::Created(b))
+        | (CursorKey::Activity(a), CursorKey::Activity(b)) => a.cmp(b),
+        (CursorKey::Rank(a), CursorKey::Rank(b)) => a.total_cmp(b),
+        (CursorKey::Score(a), CursorKey::Score(b)) => a.cmp(b),
+        // Queries only accept cursors of their own sort order
+        _ => Ordering::Equal,
+    };
+
+    keys.then(a.id.cmp(&b.id))
+}
+
+/// Lowercased words of a search query, ignoring punctuation.
+/// Unlike Postgres there is no stemming, so only whole words match.
+fn search_terms(query: &str) -> Vec<String> {
+    words(query).map(|word| word.to_lowercase()).collect()
+}
+
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+}
+
+fn hits(text: &str, terms: &[String]) -> usize {
+    words(text)
+        .filter(|word| terms.contains(&word.to_lowercase()))
+        .count()
+}
+
+fn matches_all(text: &str, terms: &[String]) -> bool {
+    let words: Vec<String> = words(text).map(|word| word.to_lowercase()).collect();
+    terms.iter().all(|term| words.contains(term))
+}
+
+/// Mark a single-use token as used, unless it was used before or expired
+fn use_account_token(
+    tokens: &mut BTreeMap<String, (AccountToken, bool)>,
+    token_hash: &str,
+) -> Option<AccountId> {
+    match tokens.get_mut(token_hash) {
+        Some((token, used)) if !*used && token.expires_on > Utc::now().naive_utc() => {
+            *used = true;
+            Some(token.account_id)
+        }
+        _ => None,
+    }
+}
+
+/// Wrap every word matching a search term in `<b>` tags, the way
+/// `ts_headline` does. The text itself is HTML-escaped, so the only
+/// markup in a snippet is the highlighting.
+fn highlight(text: &str, terms: &[String]) -> String {
+    let mut highlighted = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find(char::is_alphanumeric) {
+        let (before, word_start) = rest.split_at(start);
+        let end = word_start
+            .find(|c: char| !c.is_alphanumeric())
+            .unwrap_or(word_start.len());
+        let (word, after) = word_start.split_at(end);
+
+        push_escaped_html(&mut highlighted, before);
+        if terms.contains(&word.to_lowercase()) {
+            highlighted.push_str("<b>");
+            push_escaped_html(&mut highlighted, word);
+            highlighted.push_str("</b>");
+        } else {
+            push_escaped_html(&mut highlighted, word);
+        }
+        rest = after;
+    }
+    push_escaped_html(&mut highlighted, rest);
+
+    highlighted
+}
+
+fn push_escaped_html(out: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Pagination;
+
+    fn new_question(title: &str) -> NewQuestion {
+        NewQuestion {
+            title: title.to_string(),
+            content: "content".to_string(),
+            tags: None,
+        }
+    }
+
+    fn account(email: &str) -> Account {
+        Account {
+            id: None,
+            email: email.to_string(),
+            password: "password".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn paginates_questions_in_insertion_order() {
+        let store = MemoryStore::new();
+        for title in ["one", "two", "three"] {
+            store
+                .add_question(new_question(title), AccountId(1))
+                .await
+                .unwrap();
+        }
+
+        let query = QuestionQuery {
+            pagination: Pagination {
+                limit: Some(1),
+                offset: 1,
+            },
+            ..Default::default()
+        };
+        let page = store.get_questions(&query).await.unwrap();
+        assert_eq!(vec!["two"], titles(&page.items));
+
+        let all = store
+            .get_questions(&QuestionQuery::default())
+            .await
+            .unwrap();
+        assert_eq!(3, all.items.len());
+    }
+
+    #[tokio::test]
+    async fn pages_through_questions_with_cursors() {
+        let store = MemoryStore::new();
+        for title in ["one", "two", "three", "four", "five"] {
+            store
+                .add_question(new_question(title), AccountId(1))
+                .await
+                .unwrap();
+        }
+
+        let mut query = QuestionQuery {
+            sort: Some(QuestionSort::Newest),
+            pagination: Pagination {
+                limit: Some(2),
+                offset: 0,
+            },
+            count: true,
+            ..Default::default()
+        };
+        let first = store.get_questions(&query).await.unwrap();
+        assert_eq!(vec!["five", "four"], titles(&first.items));
+        assert_eq!(Some(5), first.total);
+        assert!(first.prev.is_none());
+
+        query.cursor = first.next.map(PageCursor::After);
+        let second = store.get_questions(&query).await.unwrap();
+        assert_eq!(vec!["three", "two"], titles(&second.items));
+
+        query.cursor = second.next.map(PageCursor::After);
+        let last = store.get_questions(&query).await.unwrap();
+        assert_eq!(vec!["one"], titles(&last.items));
+        assert!(last.next.is_none());
+
+        query.cursor = last.prev.map(PageCursor::Before);
+        let back = store.get_questions(&query).await.unwrap();
+        assert_eq!(vec!["three", "two"], titles(&back.items));
+        assert!(back.prev.is_some());
+    }
+
+    #[tokio::test]
+    async fn filters_and_sorts_questions() {
+        let store = MemoryStore::new();
+        let mut tagged = new_question("tagged");
+        tagged.tags = Some(vec!["rust".to_string()]);
+        store.add_question(tagged, AccountId(1)).await.unwrap();
+        store
+            .add_question(new_question("answered"), AccountId(2))
+            .await
+            .unwrap();
+        let answer = NewAnswer {
+            content: "a".to_string(),
+        };
+        store.add_answer(2, answer, AccountId(1)).await.unwrap();
+
+        let query = QuestionQuery {
+            tag: Some("rust".to_string()),
+            ..Default::default()
+        };
+        let questions = store.get_questions(&query).await.unwrap();
+        assert_eq!(vec!["tagged"], titles(&questions.items));
+
+        let query = QuestionQuery {
+            author: Some(AccountId(2)),
+            ..Default::default()
+        };
+        let questions = store.get_questions(&query).await.unwrap();
+        assert_eq!(vec!["answered"], titles(&questions.items));
+
+        let query = QuestionQuery {
+            unanswered: true,
+            ..Default::default()
+        };
+        let questions = store.get_questions(&query).await.unwrap();
+        assert_eq!(vec!["tagged"], titles(&questions.items));
+
+        let query = QuestionQuery {
+            sort: Some(QuestionSort::Newest),
+            ..Default::default()
+        };
+        let questions = store.get_questions(&query).await.unwrap();
+        assert_eq!(vec!["answered", "tagged"], titles(&questions.items));
+    }
+
+    fn titles(questions: &[Question]) -> Vec<&str> {
+        questions.iter().map(|q| q.title.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn tracks_question_ownership() {
+        let store = MemoryStore::new();
+        let question = store
+            .add_question(new_question("mine"), AccountId(1))
+            .await
+            .unwrap();
+
+        assert!(store
+            .is_question_owner(question.id.0, &AccountId(1))
+            .await
+            .unwrap());
+        assert!(!store
+            .is_question_owner(question.id.0, &AccountId(2))
+            .await
+            .unwrap());
+        assert!(matches!(
+            store.get_question_owner(42).await,
+            Err(Error::QuestionNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn deleted_questions_can_be_restored_until_purged() {
+        let store = MemoryStore::new();
+        let question = store
+            .add_question(new_question("q"), AccountId(1))
+            .await
+            .unwrap();
+        let answer = NewAnswer {
+            content: "a".to_string(),
+        };
+        store
+            .add_answer(question.id.0, answer, AccountId(2))
+            .await
+            .unwrap();
+
+        assert!(store.delete_question(question.id.0, 1).await.unwrap());
+        assert!(!store.delete_question(question.id.0, 1).await.unwrap());
+        assert!(matches!(
+            store.get_question(question.id.0).await,
+            Err(Error::QuestionNotFound)
+        ));
+        assert!(matches!(
+            store.get_answer(question.id.0, 1).await,
+            Err(Error::AnswerNotFound)
+        ));
+        let questions = store
+            .get_questions(&QuestionQuery::default())
+            .await
+            .unwrap();
+        assert!(questions.items.is_empty());
+
+        let deleted = store.get_deleted_question(question.id.0).await.unwrap();
+        assert!(deleted.deleted_at.is_some());
+        let restored = store.restore_question(question.id.0).await.unwrap();
+        assert_eq!(None, restored.deleted_at);
+        assert!(store.get_answer(question.id.0, 1).await.is_ok());
+        assert!(matches!(
+            store.restore_question(question.id.0).await,
+            Err(Error::QuestionNotFound)
+        ));
+
+        // Only questions deleted before the cutoff are purged
+        store.delete_question(question.id.0, 1).await.unwrap();
+        let cutoff = deleted.deleted_at.unwrap();
+        assert_eq!(0, store.purge_deleted_questions(cutoff).await.unwrap());
+        let cutoff = Utc::now().naive_utc() + chrono::Duration::seconds(1);
+        assert_eq!(1, store.purge_deleted_questions(cutoff).await.unwrap());
+        assert!(store.read().answers.is_empty());
+        assert!(matches!(
+            store.restore_question(question.id.0).await,
+            Err(Error::QuestionNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn searches_questions_and_answers() {
+        let store = MemoryStore::new();
+        let rust = store
+            .add_question(new_question("Rust lifetimes"), AccountId(1))
+            .await
+            .unwrap();
+        let other = store
+            .add_question(new_question("Unrelated"), AccountId(1))
+            .await
+            .unwrap();
+        let answer = NewAnswer {
+            content: "Lifetimes are explained in the book".to_string(),
+        };
+        store
+            .add_answer(other.id.0, answer, AccountId(2))
+            .await
+            .unwrap();
+
+        let results = store
+            .search_questions("lifetimes".to_string(), &QuestionQuery::default())
+            .await
+            .unwrap()
+            .items;
+
+        assert_eq!(2, results.len());
+        assert_eq!(rust.id, results[0].question.id);
+        assert_eq!("Rust <b>lifetimes</b>", results[0].title_snippet);
+        assert_eq!(other.id, results[1].question.id);
+        assert_eq!(
+            "<b>Lifetimes</b> are explained in the book",
+            results[1].answer_snippets[0].snippet
+        );
+    }
+
+    #[tokio::test]
+    async fn snippets_escape_html() {
+        let store = MemoryStore::new();
+        let question = NewQuestion {
+            title: "Why does <script> run?".to_string(),
+            content: "<script>alert('x')</script> & more".to_string(),
+            tags: None,
+        };
+        store.add_question(question, AccountId(1)).await.unwrap();
+
+        let results = store
+            .search_questions("script".to_string(), &QuestionQuery::default())
+            .await
+            .unwrap()
+            .items;
+
+        assert_eq!(
+            "Why does &lt;<b>script</b>&gt; run?",
+            results[0].title_snippet
+        );
+        assert_eq!(
+            "&lt;<b>script</b>&gt;alert(&#39;x&#39;)&lt;/<b>script</b>&gt; &amp; more",
+            results[0].content_snippet
+        );
+    }
+
+    #[tokio::test]
+    async fn accepted_answer_comes_first() {
+        let store = MemoryStore::new();
+        let question = store
+            .add_question(new_question("q"), AccountId(1))
+            .await
+            .unwrap();
+        for content in ["first", "second"] {
+            let answer = NewAnswer {
+                content: content.to_string(),
+            };
+            store
+                .add_answer(question.id.0, answer, AccountId(2))
+                .await
+                .unwrap();
+        }
+
+        let accepted = store.accept_answer(question.id.0, 2).await.unwrap();
+        assert_eq!(Some(AnswerId(2)), accepted.accepted_answer_id);
+
+        let answers = store.get_answers_for_question(question.id.0).await.unwrap();
+        let ids: Vec<i32> = answers.iter().map(|answer| answer.id.0).collect();
+        assert_eq!(vec![2, 1], ids);
+
+        let query = QuestionQuery {
+            answered: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(1, store.get_questions(&query).await.unwrap().items.len());
+
+        // Un-accepting another answer leaves the accepted one alone
+        let question = store.unaccept_answer(question.id.0, 1).await.unwrap();
+        assert_eq!(Some(AnswerId(2)), question.accepted_answer_id);
+
+        store.delete_answer(question.id.0, 2).await.unwrap();
+        let question = store.get_question(question.id.0).await.unwrap();
+        assert_eq!(None, question.accepted_answer_id);
+        assert!(matches!(
+            store.accept_answer(question.id.0, 2).await,
+            Err(Error::AnswerNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn comments_go_away_with_their_target() {
+        let store = MemoryStore::new();
+        let question = store
+            .add_question(new_question("q"), AccountId(1))
+            .await
+            .unwrap();
+        let answer = NewAnswer {
+            content: "a".to_string(),
+        };
+        let answer = store
+            .add_answer(question.id.0, answer, AccountId(2))
+            .await
+            .unwrap();
+
+        let comment = |content: &str| NewComment {
+            content: content.to_string(),
+        };
+        let on_question = CommentTarget::Question(question.id.0);
+        let on_answer = CommentTarget::Answer(answer.id.0);
+        store
+            .add_comment(on_question, comment("which version?"), AccountId(2))
+            .await
+            .unwrap();
+        let reply = store
+            .add_comment(on_answer, comment("thanks"), AccountId(1))
+            .await
+            .unwrap();
+
+        assert_eq!(1, store.get_comments(on_answer).await.unwrap().len());
+        assert!(matches!(
+            store.get_comment(on_question, reply.id.0).await,
+            Err(Error::CommentNotFound)
+        ));
+
+        store.delete_question(question.id.0, 1).await.unwrap();
+        assert!(matches!(
+            store.get_comments(on_answer).await,
+            Err(Error::AnswerNotFound)
+        ));
+
+        let cutoff = Utc::now().naive_utc() + chrono::Duration::seconds(1);
+        store.purge_deleted_questions(cutoff).await.unwrap();
+        assert!(store.read().comments.is_empty());
+    }
+
+    #[tokio::test]
+    async fn keeps_a_revision_per_edit() {
+        let store = MemoryStore::new();
+        let question = store
+            .add_question(new_question("first"), AccountId(1))
+            .await
+            .unwrap();
+        store
+            .update_question(new_question("second"), question.id.0, AccountId(1), 1)
+            .await
+            .unwrap();
+        let current = store
+            .update_question(new_question("third"), question.id.0, AccountId(2), 2)
+            .await
+            .unwrap();
+        assert_eq!("third", current.title);
+        assert_eq!(3, current.version);
+
+        // An edit based on an outdated version is refused
+        assert!(matches!(
+            store
+                .update_question(new_question("stale"), question.id.0, AccountId(1), 2)
+                .await,
+            Err(Error::PreconditionFailed)
+        ));
+        assert!(matches!(
+            store.delete_question(question.id.0, 1).await,
+            Err(Error::PreconditionFailed)
+        ));
+
+        let revisions = store.get_revisions(question.id.0).await.unwrap();
+        let titles: Vec<(i32, &str, AccountId)> = revisions
+            .iter()
+            .map(|revision| {
+                (
+                    revision.revision,
+                    revision.title.as_str(),
+                    revision.editor_id,
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![(1, "first", AccountId(1)), (2, "second", AccountId(2))],
+            titles
+        );
+        assert!(matches!(
+            store.get_revision(question.id.0, 3).await,
+            Err(Error::RevisionNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn counts_one_vote_per_account() {
+        let store = MemoryStore::new();
+        let first = store
+            .add_question(new_question("first"), AccountId(1))
+            .await
+            .unwrap();
+        let second = store
+            .add_question(new_question("second"), AccountId(1))
+            .await
+            .unwrap();
+
+        let vote = |account, vote| store.vote_on_question(second.id.0, AccountId(account), vote);
+        assert_eq!(1, vote(2, Some(Vote::Up)).await.unwrap());
+        assert_eq!(1, vote(2, Some(Vote::Up)).await.unwrap());
+        assert_eq!(2, vote(3, Some(Vote::Up)).await.unwrap());
+        assert_eq!(0, vote(2, Some(Vote::Down)).await.unwrap());
+        assert_eq!(1, vote(2, None).await.unwrap());
+
+        store
+            .vote_on_question(first.id.0, AccountId(2), Some(Vote::Down))
+            .await
+            .unwrap();
+        let query = QuestionQuery {
+            sort: Some(QuestionSort::Score),
+            ..Default::default()
+        };
+        let questions = store.get_questions(&query).await.unwrap();
+        assert_eq!(vec!["second", "first"], titles(&questions.items));
+        assert_eq!(1, questions.items[0].score);
+
+        assert!(matches!(
+            store
+                .vote_on_answer(second.id.0, 1, AccountId(2), None)
+                .await,
+            Err(Error::AnswerNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn synonyms_merge_into_their_canonical_tag() {
+        let store = MemoryStore::new();
+        let tagged = |title: &str, tags: &[&str]| {
+            let mut question = new_question(title);
+            question.tags = Some(tags.iter().map(|tag| tag.to_string()).collect());
+            question
+        };
+        store
+            .add_question(tagged("both", &["rust", "rustlang"]), AccountId(1))
+            .await
+            .unwrap();
+        store
+            .add_question(tagged("old", &["rustlang", "web"]), AccountId(1))
+            .await
+            .unwrap();
+
+        let tag = store.add_tag_synonym("rust", "rustlang").await.unwrap();
+        assert_eq!(vec!["rustlang"], tag.synonyms);
+        assert_eq!(2, tag.count);
+
+        let questions = store
+            .get_questions(&QuestionQuery::default())
+            .await
+            .unwrap();
+        let tags: Vec<_> = questions.items.iter().map(|q| q.tags.clone()).collect();
+        assert_eq!(
+            vec![
+                Some(vec!["rust".to_string()]),
+                Some(vec!["rust".to_string(), "web".to_string()]),
+            ],
+            tags
+        );
+
+        // New questions and filters go through the synonym as well
+        let question = store
+            .add_question(tagged("new", &["rustlang"]), AccountId(1))
+            .await
+            .unwrap();
+        assert_eq!(Some(vec!["rust".to_string()]), question.tags);
+        let query = QuestionQuery {
+            tag: Some("rustlang".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(3, store.get_questions(&query).await.unwrap().items.len());
+
+        let query = TagQuery {
+            prefix: Some("rustl".to_string()),
+            ..Default::default()
+        };
+        let tags = store.get_tags(&query).await.unwrap();
+        assert_eq!(
+            vec!["rust"],
+            tags.iter().map(|t| &t.name).collect::<Vec<_>>()
+        );
+
+        let tags = store.get_tags(&TagQuery::default()).await.unwrap();
+        let counts: Vec<_> = tags.iter().map(|t| (t.name.as_str(), t.count)).collect();
+        assert_eq!(vec![("rust", 3), ("web", 1)], counts);
+
+        assert!(matches!(
+            store.add_tag_synonym("rustlang", "rust").await,
+            Err(Error::InvalidParameter(_))
+        ));
+        assert!(matches!(
+            store.remove_tag_synonym("web", "rustlang").await,
+            Err(Error::TagNotFound)
+        ));
+        let tag = store.remove_tag_synonym("rust", "rustlang").await.unwrap();
+        assert!(tag.synonyms.is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejects_duplicate_emails() {
+        let store = MemoryStore::new();
+        store.add_account(account("a@b.c")).await.unwrap();
+
+        let err = store.add_account(account("a@b.c")).await.unwrap_err();
+        assert!(matches!(err, Error::AccountAlreadyExists));
+
+        let stored = store.get_account("a@b.c".to_string()).await.unwrap();
+        assert_eq!(Some(AccountId(1)), stored.id);
+    }
+
+    #[tokio::test]
+    async fn sets_roles_statuses_and_locks() {
+        let store = MemoryStore::new();
+        store.add_account(account("a@b.c")).await.unwrap();
+        let id = AccountId(1);
+
+        let actor = store.set_account_role(&id, Role::Moderator).await.unwrap();
+        assert_eq!(Role::Moderator, actor.role);
+        let actor = store
+            .set_account_status(&id, AccountStatus::Suspended)
+            .await
+            .unwrap();
+        assert_eq!(actor, store.get_actor(&id).await.unwrap());
+        assert!(matches!(
+            store.set_account_role(&AccountId(2), Role::Admin).await,
+            Err(Error::UnknownAccount)
+        ));
+
+        let question = store
+            .add_question(new_question("q"), AccountId(1))
+            .await
+            .unwrap();
+        assert!(!question.locked);
+        assert!(
+            store
+                .set_question_locked(question.id.0, true)
+                .await
+                .unwrap()
+                .locked
+        );
+        assert!(store.get_question(question.id.0).await.unwrap().locked);
+    }
+
+    fn refresh_token(hash: &str, family: &str) -> RefreshToken {
+        RefreshToken {
+            token_hash: hash.to_string(),
+            family: family.to_string(),
+            account_id: AccountId(1),
+            expires_on: Utc::now().naive_utc() + chrono::Duration::days(1),
+            two_factor: false,
+            scopes: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn reusing_a_refresh_token_revokes_its_family() {
+        let store = MemoryStore::new();
+        store
+            .add_refresh_token(refresh_token("one", "f"))
+            .await
+            .unwrap();
+        store
+            .add_refresh_token(refresh_token("other", "g"))
+            .await
+            .unwrap();
+
+        let token = store.use_refresh_token("one").await.unwrap();
+        assert_eq!("f", token.family);
+        store
+            .add_refresh_token(refresh_token("two", "f"))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            store.use_refresh_token("one").await,
+            Err(Error::InvalidRefreshToken)
+        ));
+        assert!(matches!(
+            store.use_refresh_token("two").await,
+            Err(Error::InvalidRefreshToken)
+        ));
+        assert!(store.use_refresh_token("other").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn revokes_and_purges_tokens() {
+        let store = MemoryStore::new();
+        store
+            .add_refresh_token(refresh_token("one", "f"))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            store
+                .revoke_refresh_token_family("one", &AccountId(2))
+                .await,
+            Err(Error::InvalidRefreshToken)
+        ));
+        store
+            .revoke_refresh_token_family("one", &AccountId(1))
+            .await
+            .unwrap();
+        assert!(store.use_refresh_token("one").await.is_err());
+
+        let expires_on = Utc::now().naive_utc() + chrono::Duration::minutes(5);
+        store.revoke_access_token("jti", expires_on).await.unwrap();
+        assert!(store.is_access_token_revoked("jti").await.unwrap());
+        assert!(!store.is_access_token_revoked("other").await.unwrap());
+
+        let later = expires_on + chrono::Duration::days(2);
+        assert_eq!(2, store.purge_expired_tokens(later).await.unwrap());
+        assert!(!store.is_access_token_revoked("jti").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn password_reset_tokens_are_single_use() {
+        let store = MemoryStore::new();
+        let now = Utc::now().naive_utc();
+        for (hash, expires_on) in [
+            ("fresh", now + chrono::Duration::hours(1)),
+            ("stale", now - chrono::Duration::hours(1)),
+        ] {
+            store
+                .add_password_reset(AccountToken {
+                    token_hash: hash.to_string(),
+                    account_id: AccountId(1),
+                    expires_on,
+                })
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            AccountId(1),
+            store.use_password_reset("fresh").await.unwrap()
+        );
+        for hash in ["fresh", "stale", "unknown"] {
+            assert!(matches!(
+                store.use_password_reset(hash).await,
+                Err(Error::InvalidResetToken)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn verifies_emails_once() {
+        let store = MemoryStore::new();
+        store.add_account(account("a@b.c")).await.unwrap();
+        let id = AccountId(1);
+        assert!(!store.is_email_verified(&id).await.unwrap());
+
+        store
+            .add_email_verification(AccountToken {
+                token_hash: "hash".to_string(),
+                account_id: id,
+                expires_on: Utc::now().naive_utc() + chrono::Duration::hours(1),
+            })
+            .await
+            .unwrap();
+        assert_eq!(id, store.use_email_verification("hash").await.unwrap());
+        assert!(matches!(
+            store.use_email_verification("hash").await,
+            Err(Error::InvalidVerificationToken)
+        ));
+
+        store.set_email_verified(&id).await.unwrap();
+        assert!(store.is_email_verified(&id).await.unwrap());
+        assert!(matches!(
+            store.is_email_verified(&AccountId(2)).await,
+            Err(Error::UnknownAccount)
+        ));
+    }
+
+    #[tokio::test]
+    async fn confirmed_two_factor_cannot_be_replaced() {
+        let store = MemoryStore::new();
+        let account_id = AccountId(1);
+        store.set_two_factor_secret(&account_id, "A").await.unwrap();
+        store.set_two_factor_secret(&account_id, "B").await.unwrap();
+        store
+            .confirm_two_factor(&account_id, 10, vec!["code".to_string()])
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            store.set_two_factor_secret(&account_id, "C").await,
+            Err(Error::TwoFactorAlreadyEnabled)
+        ));
+        let two_factor = store.get_two_factor(&account_id).await.unwrap().unwrap();
+        assert_eq!("B", two_factor.secret);
+
+        assert!(!store.use_totp_step(&account_id, 10).await.unwrap());
+        assert!(store.use_totp_step(&account_id, 11).await.unwrap());
+        assert!(store.use_recovery_code(&account_id, "code").await.unwrap());
+        assert!(!store.use_recovery_code(&account_id, "code").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn keeps_profiles_and_lists_live_posts_newest_first() {
+        let store = MemoryStore::new();
+        store.add_account(account("a@b.c")).await.unwrap();
+        let account_id = AccountId(1);
+
+        let update = ProfileUpdate {
+            display_name: Some("Ada".to_string()),
+            bio: Some("Counts things".to_string()),
+            avatar_url: None,
+        };
+        store.update_profile(&account_id, update).await.unwrap();
+        let update = ProfileUpdate {
+            bio: Some(String::new()),
+            ..Default::default()
+        };
+        let profile = store.update_profile(&account_id, update).await.unwrap();
+        assert_eq!(Some("Ada".to_string()), profile.display_name);
+        assert_eq!(None, profile.bio);
+        assert_eq!(profile, store.get_profile(&account_id).await.unwrap());
+        assert!(matches!(
+            store.get_profile(&AccountId(2)).await,
+            Err(Error::UnknownAccount)
+        ));
+
+        let first = store
+            .add_question(new_question("first"), account_id)
+            .await
+            .unwrap();
+        let second = store
+            .add_question(new_question("second"), account_id)
+            .await
+            .unwrap();
+        for question in [&first, &second] {
+            let answer = NewAnswer {
+                content: "a".to_string(),
+            };
+            store
+                .add_answer(question.id.0, answer, account_id)
+                .await
+                .unwrap();
+        }
+        let questions = store.get_account_questions(&account_id, 5).await.unwrap();
+        assert_eq!(
+            vec![second.id.clone(), first.id.clone()],
+            questions.iter().map(|q| q.id.clone()).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            1,
+            store
+                .get_account_questions(&account_id, 1)
+                .await
+                .unwrap()
+                .len()
+        );
+
+        // Posts under a deleted question go with it
+        store.delete_question(first.id.0, 1).await.unwrap();
+        assert_eq!(
+            1,
+            store
+                .get_account_questions(&account_id, 5)
+                .await
+                .unwrap()
+                .len()
+        );
+        let answers = store.get_account_answers(&account_id, 5).await.unwrap();
+        assert_eq!(
+            vec![second.id],
+            answers
+                .into_iter()
+                .map(|a| a.question_id)
+                .collect::<Vec<_>>()
+        );
+    }
+}
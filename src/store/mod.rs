@@ -0,0 +1,479 @@
+use std::fmt::Debug;
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use handle_errors::Error;
+
+use crate::types::{
+    Account, AccountId, AccountStatus, AccountToken, Actor, Answer, ApiKey, ApiKeyId, Comment,
+    CommentTarget, LoginFailures, NewAnswer, NewApiKey, NewComment, NewQuestion, OidcLogin, Page,
+    Profile, ProfileUpdate, Question, QuestionQuery, QuestionRevision, QuestionSearchResult,
+    RefreshToken, Role, Tag, TagQuery, TagUpdate, TwoFactor, Vote,
+};
+
+mod memory;
+mod postgres;
+
+pub use memory::MemoryStore;
+pub use postgres::PgStore;
+
+/// Storage for questions and the answers posted to them. Deleted
+/// questions are kept until purged but behave as if they were gone,
+/// except for the methods dealing with deleted questions.
+#[async_trait]
+pub trait QuestionRepository {
+    async fn get_questions(&self, query: &QuestionQuery) -> Result<Page<Question>, Error>;
+
+    /// Full-text search over question titles, contents and answers,
+    /// best matches first unless the query asks for another order
+    async fn search_questions(
+        &self,
+        terms: String,
+        query: &QuestionQuery,
+    ) -> Result<Page<QuestionSearchResult>, Error>;
+
+    /// Fails with `Error::QuestionNotFound` if there is no such question
+    async fn get_question(&self, question_id: i32) -> Result<Question, Error>;
+
+    /// Tags are expected to be normalized already. Synonyms are replaced
+    /// by their canonical tag and tags that don't exist yet are created.
+    async fn add_question(
+        &self,
+        new_question: NewQuestion,
+        account_id: AccountId,
+    ) -> Result<Question, Error>;
+
+    /// Resolves tags like `add_question` and keeps the values being
+    /// replaced as a new revision, recording the editor's account.
+    /// Fails with `Error::PreconditionFailed` if the question is no
+    /// longer at `version`, the one the edit is based on.
+    async fn update_question(
+        &self,
+        question: NewQuestion,
+        question_id: i32,
+        editor_id: AccountId,
+        version: i32,
+    ) -> Result<Question, Error>;
+
+    /// Revisions are returned oldest first
+    async fn get_revisions(&self, question_id: i32) -> Result<Vec<QuestionRevision>, Error>;
+
+    /// Fails with `Error::RevisionNotFound` if the question has no such revision
+    async fn get_revision(
+        &self,
+        question_id: i32,
+        revision: i32,
+    ) -> Result<QuestionRevision, Error>;
+
+    /// Marks the question as deleted, failing with
+    /// `Error::PreconditionFailed` if it is no longer at `version`
+    async fn delete_question(&self, question_id: i32, version: i32) -> Result<bool, Error>;
+
+    /// Fails with `Error::QuestionNotFound` unless the question
+    /// is deleted and not purged yet
+    async fn get_deleted_question(&self, question_id: i32) -> Result<Question, Error>;
+
+    /// Undo `delete_question`, failing with `Error::QuestionNotFound`
+    /// unless the question is deleted and not purged yet
+    async fn restore_question(&self, question_id: i32) -> Result<Question, Error>;
+
+    /// Remove the questions deleted before `deleted_before` for good, along
+    /// with their answers, and return how many there were
+    async fn purge_deleted_questions(&self, deleted_before: NaiveDateTime) -> Result<u64, Error>;
+
+    /// Lock or unlock a question, see `policy::check_unlocked`
+    async fn set_question_locked(&self, question_id: i32, locked: bool) -> Result<Question, Error>;
+
+    async fn get_question_owner(&self, question_id: i32) -> Result<AccountId, Error>;
+
+    async fn is_question_owner(
+        &self,
+        question_id: i32,
+        account_id: &AccountId,
+    ) -> Result<bool, Error>;
+
+    /// The latest `limit` questions the account asked, newest first
+    async fn get_account_questions(
+        &self,
+        account_id: &AccountId,
+        limit: i64,
+    ) -> Result<Vec<Question>, Error>;
+
+    /// Answers are returned accepted answer first, then oldest first
+    async fn get_answers_for_question(&self, question_id: i32) -> Result<Vec<Answer>, Error>;
+
+    /// The latest `limit` answers the account posted, newest first
+    async fn get_account_answers(
+        &self,
+        account_id: &AccountId,
+        limit: i64,
+    ) -> Result<Vec<Answer>, Error>;
+
+    /// Fails with `Error::AnswerNotFound` if there is no such answer
+    /// for this question
+    async fn get_answer(&self, question_id: i32, answer_id: i32) -> Result<Answer, Error>;
+
+    /// The question an answer was posted to, failing with
+    /// `Error::AnswerNotFound` if there is no such answer
+    async fn get_answer_question(&self, answer_id: i32) -> Result<Question, Error>;
+
+    async fn add_answer(
+        &self,
+        question_id: i32,
+        new_answer: NewAnswer,
+        account_id: AccountId,
+    ) -> Result<Answer, Error>;
+
+    async fn update_answer(
+        &self,
+        question_id: i32,
+        answer_id: i32,
+        answer: NewAnswer,
+    ) -> Result<Answer, Error>;
+
+    async fn delete_answer(&self, question_id: i32, answer_id: i32) -> Result<bool, Error>;
+
+    /// Mark an answer as the accepted one, replacing any answer accepted
+    /// before. Fails with `Error::AnswerNotFound` if there is no such
+    /// answer for this question.
+    async fn accept_answer(&self, question_id: i32, answer_id: i32) -> Result<Question, Error>;
+
+    /// Clear the accepted answer if it is this one
+    async fn unaccept_answer(&self, question_id: i32, answer_id: i32) -> Result<Question, Error>;
+
+    /// Cast, change or, with `None`, retract the vote of an account on a
+    /// question and return the question's new score
+    async fn vote_on_question(
+        &self,
+        question_id: i32,
+        account_id: AccountId,
+        vote: Option<Vote>,
+    ) -> Result<i32, Error>;
+
+    /// Like `vote_on_question`, fails with `Error::AnswerNotFound` if there
+    /// is no such answer for this question
+    async fn vote_on_answer(
+        &self,
+        question_id: i32,
+        answer_id: i32,
+        account_id: AccountId,
+        vote: Option<Vote>,
+    ) -> Result<i32, Error>;
+}
+
+/// Storage for the comments posted on questions and answers. Every method
+/// fails with `Error::QuestionNotFound` or `Error::AnswerNotFound` if the
+/// target doesn't exist.
+#[async_trait]
+pub trait CommentRepository {
+    /// Comments are returned oldest first
+    async fn get_comments(&self, target: CommentTarget) -> Result<Vec<Comment>, Error>;
+
+    /// Fails with `Error::CommentNotFound` if there is no such comment
+    /// on this target
+    async fn get_comment(&self, target: CommentTarget, comment_id: i32) -> Result<Comment, Error>;
+
+    async fn add_comment(
+        &self,
+        target: CommentTarget,
+        new_comment: NewComment,
+        account_id: AccountId,
+    ) -> Result<Comment, Error>;
+
+    async fn update_comment(
+        &self,
+        target: CommentTarget,
+        comment_id: i32,
+        comment: NewComment,
+    ) -> Result<Comment, Error>;
+
+    async fn delete_comment(&self, target: CommentTarget, comment_id: i32) -> Result<bool, Error>;
+}
+
+/// Storage for the tags questions are filed under. Names are expected
+/// to be normalized already; every method fails with
+/// `Error::TagNotFound` if a tag it is given doesn't exist.
+#[async_trait]
+pub trait TagRepository {
+    /// Canonical tags matching the query, most used first
+    async fn get_tags(&self, query: &TagQuery) -> Result<Vec<Tag>, Error>;
+
+    /// Looking up a synonym returns its canonical tag
+    async fn get_tag(&self, name: &str) -> Result<Tag, Error>;
+
+    async fn update_tag(&self, name: &str, tag: TagUpdate) -> Result<Tag, Error>;
+
+    /// Make `synonym` redirect to the canonical tag of `name`, creating
+    /// it if needed. Questions and synonyms filed under `synonym` move
+    /// over to the canonical tag.
+    async fn add_tag_synonym(&self, name: &str, synonym: &str) -> Result<Tag, Error>;
+
+    /// Turn a synonym of `name` back into a tag of its own
+    async fn remove_tag_synonym(&self, name: &str, synonym: &str) -> Result<Tag, Error>;
+}
+
+/// Storage for user accounts
+#[async_trait]
+pub trait AccountRepository {
+    /// Fails with `Error::AccountAlreadyExists` if the email is taken
+    async fn add_account(&self, account: Account) -> Result<bool, Error>;
+
+    /// Fails with `Error::AccountNotFound` if no account uses this email
+    async fn get_account(&self, email: String) -> Result<Account, Error>;
+
+    /// Fails with `Error::UnknownAccount` if there is no such account
+    async fn get_account_by_id(&self, account_id: &AccountId) -> Result<Account, Error>;
+
+    async fn get_actor(&self, account_id: &AccountId) -> Result<Actor, Error>;
+
+    /// Fails with `Error::UnknownAccount` if there is no such account
+    async fn is_email_verified(&self, account_id: &AccountId) -> Result<bool, Error>;
+
+    /// Record that the account proved it owns its email address. Fails
+    /// with `Error::UnknownAccount` if there is no such account.
+    async fn set_email_verified(&self, account_id: &AccountId) -> Result<bool, Error>;
+
+    /// Replace the password hash of an account. Fails with
+    /// `Error::UnknownAccount` if there is no such account.
+    async fn set_account_password(
+        &self,
+        account_id: &AccountId,
+        password: String,
+    ) -> Result<bool, Error>;
+
+    /// Fails with `Error::UnknownAccount` if there is no such account
+    async fn set_account_role(&self, account_id: &AccountId, role: Role) -> Result<Actor, Error>;
+
+    /// Fails with `Error::UnknownAccount` if there is no such account
+    async fn set_account_status(
+        &self,
+        account_id: &AccountId,
+        status: AccountStatus,
+    ) -> Result<Actor, Error>;
+
+    /// Fails with `Error::UnknownAccount` if there is no such account
+    async fn get_profile(&self, account_id: &AccountId) -> Result<Profile, Error>;
+
+    /// Set the fields of a normalized update, clearing the empty ones.
+    /// Fails with `Error::UnknownAccount` if there is no such account.
+    async fn update_profile(
+        &self,
+        account_id: &AccountId,
+        update: ProfileUpdate,
+    ) -> Result<Profile, Error>;
+}
+
+/// Storage for refresh tokens and the deny-list of revoked access
+/// tokens. Refresh tokens are only ever seen by their hash.
+#[async_trait]
+pub trait TokenRepository {
+    async fn add_refresh_token(&self, token: RefreshToken) -> Result<bool, Error>;
+
+    /// Mark a refresh token as used so it cannot be used again. Fails with
+    /// `Error::InvalidRefreshToken` if it is unknown, expired or revoked.
+    /// A token that was already used revokes its whole family, since
+    /// either the client or someone who stole the token is replaying it.
+    async fn use_refresh_token(&self, token_hash: &str) -> Result<RefreshToken, Error>;
+
+    /// Revoke the family of a refresh token issued to `account_id`. Fails
+    /// with `Error::InvalidRefreshToken` if there is no such token.
+    async fn revoke_refresh_token_family(
+        &self,
+        token_hash: &str,
+        account_id: &AccountId,
+    ) -> Result<bool, Error>;
+
+    /// Revoke every refresh token of an account, signing it out everywhere
+    async fn revoke_refresh_tokens(&self, account_id: &AccountId) -> Result<u64, Error>;
+
+    async fn add_password_reset(&self, token: AccountToken) -> Result<bool, Error>;
+
+    /// Mark a password reset token as used and return the account it
+    /// resets. Fails with `Error::InvalidResetToken` if it is unknown,
+    /// expired or was used before.
+    async fn use_password_reset(&self, token_hash: &str) -> Result<AccountId, Error>;
+
+    async fn add_email_verification(&self, token: AccountToken) -> Result<bool, Error>;
+
+    /// Mark an email verification token as used and return the account
+    /// it verifies. Fails with `Error::InvalidVerificationToken` if it is
+    /// unknown, expired or was used before.
+    async fn use_email_verification(&self, token_hash: &str) -> Result<AccountId, Error>;
+
+    async fn add_login_challenge(&self, token: AccountToken) -> Result<bool, Error>;
+
+    /// Mark a login challenge as used and return the account logging in.
+    /// Fails with `Error::InvalidLoginChallenge` if it is unknown, expired
+    /// or was used before.
+    async fn use_login_challenge(&self, token_hash: &str) -> Result<AccountId, Error>;
+
+    /// Put an access token on the deny-list until it expires anyway
+    async fn revoke_access_token(
+        &self,
+        jti: &str,
+        expires_on: NaiveDateTime,
+    ) -> Result<bool, Error>;
+
+    async fn is_access_token_revoked(&self, jti: &str) -> Result<bool, Error>;
+
+    /// Forget refresh tokens, password reset and email verification
+    /// tokens, login challenges, logins through the identity provider
+    /// and deny-list entries that expired before `now`
+    async fn purge_expired_tokens(&self, now: NaiveDateTime) -> Result<u64, Error>;
+}
+
+/// Failed logins, counted under a key per email address and per client
+/// IP. Keys are opaque to the store.
+#[async_trait]
+pub trait LoginAttemptRepository {
+    async fn get_login_failures(&self, key: &str) -> Result<Option<LoginFailures>, Error>;
+
+    /// Count one more failure at `now`, starting the count over if the
+    /// last failure was before `reset_before`
+    async fn record_login_failure(
+        &self,
+        key: &str,
+        now: NaiveDateTime,
+        reset_before: NaiveDateTime,
+    ) -> Result<LoginFailures, Error>;
+
+    async fn clear_login_failures(&self, key: &str) -> Result<bool, Error>;
+
+    /// Forget failures whose last one was before `before`
+    async fn purge_login_failures(&self, before: NaiveDateTime) -> Result<u64, Error>;
+}
+
+/// Storage for TOTP secrets and the recovery codes standing in for them.
+/// Recovery codes are only ever seen by their hash.
+#[async_trait]
+pub trait TwoFactorRepository {
+    async fn get_two_factor(&self, account_id: &AccountId) -> Result<Option<TwoFactor>, Error>;
+
+    /// Start enrolling with a new, unconfirmed secret, replacing any
+    /// unconfirmed one. Fails with `Error::TwoFactorAlreadyEnabled` if
+    /// the account has a confirmed secret.
+    async fn set_two_factor_secret(
+        &self,
+        account_id: &AccountId,
+        secret: &str,
+    ) -> Result<bool, Error>;
+
+    /// Confirm the secret, record `step` as used and replace the
+    /// recovery codes of the account. Fails with
+    /// `Error::TwoFactorNotEnabled` if there is no secret to confirm.
+    async fn confirm_two_factor(
+        &self,
+        account_id: &AccountId,
+        step: i64,
+        recovery_code_hashes: Vec<String>,
+    ) -> Result<bool, Error>;
+
+    /// Forget the secret and recovery codes of an account
+    async fn disable_two_factor(&self, account_id: &AccountId) -> Result<bool, Error>;
+
+    /// Record `step` as used, returning false if it or a later step was
+    /// used before, meaning the code is being replayed
+    async fn use_totp_step(&self, account_id: &AccountId, step: i64) -> Result<bool, Error>;
+
+    /// Strike out a recovery code, returning false if the account has
+    /// no such code or it was used before
+    async fn use_recovery_code(
+        &self,
+        account_id: &AccountId,
+        code_hash: &str,
+    ) -> Result<bool, Error>;
+}
+
+/// Storage for the API keys of accounts. Keys are only ever seen by
+/// their hash.
+#[async_trait]
+pub trait ApiKeyRepository {
+    async fn add_api_key(
+        &self,
+        account_id: &AccountId,
+        key: NewApiKey,
+        prefix: &str,
+        key_hash: &str,
+    ) -> Result<ApiKey, Error>;
+
+    /// The keys of an account that were not revoked, oldest first
+    async fn get_api_keys(&self, account_id: &AccountId) -> Result<Vec<ApiKey>, Error>;
+
+    /// Fails with `Error::ApiKeyNotFound` unless the account has such a
+    /// key and it was not revoked yet
+    async fn revoke_api_key(
+        &self,
+        account_id: &AccountId,
+        api_key_id: ApiKeyId,
+    ) -> Result<bool, Error>;
+
+    /// Record that a key was used at `now` and return it. Fails with
+    /// `Error::InvalidApiKey` if it is unknown or revoked.
+    async fn use_api_key(&self, key_hash: &str, now: NaiveDateTime) -> Result<ApiKey, Error>;
+}
+
+/// Storage for logins through the OpenID Connect provider: the ones in
+/// flight, by the hash of their state, and the identities at providers
+/// that are linked to accounts
+#[async_trait]
+pub trait IdentityRepository {
+    async fn add_oidc_login(&self, login: OidcLogin) -> Result<bool, Error>;
+
+    /// Mark a login as finished and return it. Fails with
+    /// `Error::InvalidOidcState` if it is unknown, expired or was
+    /// finished before.
+    async fn use_oidc_login(&self, state_hash: &str) -> Result<OidcLogin, Error>;
+
+    /// The account the identity `subject` at `issuer` is linked to
+    async fn get_identity_account(
+        &self,
+        issuer: &str,
+        subject: &str,
+    ) -> Result<Option<AccountId>, Error>;
+
+    /// Link an identity to an account, keeping the account it is linked
+    /// to already if there is one
+    async fn link_identity(
+        &self,
+        issuer: &str,
+        subject: &str,
+        account_id: &AccountId,
+    ) -> Result<bool, Error>;
+}
+
+/// Everything the routes need from a storage backend
+pub trait Store:
+    QuestionRepository
+    + CommentRepository
+    + TagRepository
+    + AccountRepository
+    + TokenRepository
+    + LoginAttemptRepository
+    + TwoFactorRepository
+    + ApiKeyRepository
+    + IdentityRepository
+    + Debug
+    + Clone
+    + Send
+    + Sync
+    + 'static
+{
+}
+
+impl<T> Store for T where
+    T: QuestionRepository
+        + CommentRepository
+        + TagRepository
+        + AccountRepository
+        + TokenRepository
+        + LoginAttemptRepository
+        + TwoFactorRepository
+        + ApiKeyRepository
+        + IdentityRepository
+        + Debug
+        + Clone
+        + Send
+        + Sync
+        + 'static
+{
+}
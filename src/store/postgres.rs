@@ -0,0 +1,2754 @@
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use handle_errors::Error;
+
+use sqlx::postgres::{PgConnection, PgPool, PgPoolOptions, PgRow};
+use sqlx::{Executor, Postgres, QueryBuilder, Row};
+
+use crate::types::{
+    Account, AccountId, AccountStatus, AccountToken, Actor, Answer, AnswerId, AnswerSnippet,
+    ApiKey, ApiKeyId, Comment, CommentId, CommentTarget, Cursor, CursorKey, LoginFailures,
+    NewAnswer, NewApiKey, NewComment, NewQuestion, OidcLogin, Page, PageCursor, Profile,
+    ProfileUpdate, Question, QuestionId, QuestionQuery, QuestionRevision, QuestionSearchResult,
+    QuestionSort, RefreshToken, Role, Scope, Tag, TagQuery, TagUpdate, TwoFactor, Vote,
+};
+
+use tracing::{event, Level};
+
+use super::{
+    AccountRepository, ApiKeyRepository, CommentRepository, IdentityRepository,
+    LoginAttemptRepository, QuestionRepository, TagRepository, TokenRepository,
+    TwoFactorRepository,
+};
+
+/// Columns of a canonical tag, with its synonyms and usage count
+const TAG_SELECT: &str = "SELECT tags.name, tags.description,
+        ARRAY(
+            SELECT synonym.name FROM tags synonym
+            WHERE synonym.synonym_of = tags.id ORDER BY synonym.name
+        ) AS synonyms,
+        (
+            SELECT count(*) FROM question_tags
+            JOIN questions ON questions.id = question_tags.question_id
+            WHERE question_tags.tag_id = tags.id AND questions.deleted_at IS NULL
+        ) AS count
+    FROM tags";
+
+/// Postgres-backed store used in production
+#[derive(Debug, Clone)]
+pub struct PgStore {
+    pub connection: PgPool,
+}
+
+impl PgStore {
+    pub async fn new(db_url: &str) -> Result<Self, sqlx::Error> {
+        tracing::warn!("{}", db_url);
+
+        let db_pool = PgPoolOptions::new()
+            .max_connections(5)
+            .connect(db_url)
+            .await?;
+
+        Ok(Self {
+            connection: db_pool,
+        })
+    }
+}
+
+impl PgStore {
+    async fn fetch_count(&self, mut builder: QueryBuilder<'_, Postgres>) -> Result<i64, Error> {
+        builder
+            .build_query_scalar()
+            .fetch_one(&self.connection)
+            .await
+            .map_err(|err| {
+                event!(Level::ERROR, "{:?}", err);
+                Error::DatabaseQueryError(err)
+            })
+    }
+}
+
+#[async_trait]
+impl QuestionRepository for PgStore {
+    async fn get_questions(&self, query: &QuestionQuery) -> Result<Page<Question>, Error> {
+        let sort = query.effective_sort();
+
+        let mut builder = QueryBuilder::new(
+            "SELECT questions.id, title, content, tags, account_id, created_on, score,
+                accepted_answer_id, version, deleted_at, locked, ",
+        );
+        builder.push(sort_key(sort));
+        builder.push(" AS sort_key FROM questions WHERE TRUE");
+        push_question_filters(&mut builder, query);
+        push_page(&mut builder, query, sort);
+
+        let rows = builder
+            .build()
+            .map(|row: PgRow| {
+                let cursor = cursor_from_row(sort, &row);
+                (question_from_row(row), cursor)
+            })
+            .fetch_all(&self.connection)
+            .await
+            .map_err(|err| {
+                event!(Level::ERROR, "{:?}", err);
+                Error::DatabaseQueryError(err)
+            })?;
+
+        let total = if query.count {
+            let mut builder = QueryBuilder::new("SELECT count(*) FROM questions WHERE TRUE");
+            push_question_filters(&mut builder, query);
+            Some(self.fetch_count(builder).await?)
+        } else {
+            None
+        };
+
+        Ok(Page::from_rows(
+            rows,
+            &query.pagination,
+            query.cursor.as_ref(),
+            total,
+        ))
+    }
+
+    async fn search_questions(
+        &self,
+        terms: String,
+        query: &QuestionQuery,
+    ) -> Result<Page<QuestionSearchResult>, Error> {
+        let sort = query.sort.unwrap_or(QuestionSort::Relevance);
+
+        let mut builder = QueryBuilder::new("");
+        push_search_matches(&mut builder, &terms);
+        builder.push(format!(
+            " SELECT questions.id, title, content, tags, account_id, created_on, score,
+                accepted_answer_id, version, deleted_at, locked, ranked.rank,
+                ts_headline('english', {}, query.q, 'HighlightAll=true') AS title_snippet,
+                ts_headline('english', {}, query.q) AS content_snippet, ",
+            escaped_html("title"),
+            escaped_html("content"),
+        ));
+        builder.push(sort_key(sort));
+        builder.push(
+            " AS sort_key FROM ranked
+            JOIN questions ON questions.id = ranked.id, query
+            WHERE TRUE",
+        );
+        push_question_filters(&mut builder, query);
+        push_page(&mut builder, query, sort);
+
+        let rows = builder
+            .build()
+            .map(|row: PgRow| {
+                let cursor = cursor_from_row(sort, &row);
+                let result = QuestionSearchResult {
+                    rank: row.get("rank"),
+                    title_snippet: row.get("title_snippet"),
+                    content_snippet: row.get("content_snippet"),
+                    answer_snippets: Vec::new(),
+                    question: question_from_row(row),
+                };
+                (result, cursor)
+            })
+            .fetch_all(&self.connection)
+            .await
+            .map_err(|err| {
+                event!(Level::ERROR, "{:?}", err);
+                Error::DatabaseQueryError(err)
+            })?;
+
+        let total = if query.count {
+            let mut builder = QueryBuilder::new("");
+            push_search_matches(&mut builder, &terms);
+            builder.push(
+                " SELECT count(*) FROM ranked
+                JOIN questions ON questions.id = ranked.id
+                WHERE TRUE",
+            );
+            push_question_filters(&mut builder, query);
+            Some(self.fetch_count(builder).await?)
+        } else {
+            None
+        };
+
+        let mut page = Page::from_rows(rows, &query.pagination, query.cursor.as_ref(), total);
+        let results = &mut page.items;
+
+        let question_ids: Vec<i32> = results.iter().map(|r| r.question.id.0).collect();
+        let sql = format!(
+            "WITH query AS (SELECT websearch_to_tsquery('english', $1) AS q)
+            SELECT answers.id, answers.question_id,
+                ts_headline('english', {}, query.q) AS snippet
+            FROM answers, query
+            WHERE answers.question_id = ANY($2) AND answers.search @@ query.q
+            ORDER BY ts_rank(answers.search, query.q) DESC, answers.id",
+            escaped_html("answers.content"),
+        );
+        let snippets = sqlx::query(&sql)
+            .bind(&terms)
+            .bind(&question_ids)
+            .map(|row: PgRow| {
+                (
+                    row.get::<i32, _>("question_id"),
+                    AnswerSnippet {
+                        answer_id: AnswerId(row.get("id")),
+                        snippet: row.get("snippet"),
+                    },
+                )
+            })
+            .fetch_all(&self.connection)
+            .await
+            .map_err(|err| {
+                event!(Level::ERROR, "{:?}", err);
+                Error::DatabaseQueryError(err)
+            })?;
+
+        for (question_id, snippet) in snippets {
+            if let Some(result) = results.iter_mut().find(|r| r.question.id.0 == question_id) {
+                result.answer_snippets.push(snippet);
+            }
+        }
+
+        Ok(page)
+    }
+
+    async fn get_question(&self, question_id: i32) -> Result<Question, Error> {
+        sqlx::query(
+            "SELECT id, title, content, tags, account_id, created_on, score,
+                accepted_answer_id, version, deleted_at, locked
+                FROM questions WHERE id = $1 AND deleted_at IS NULL",
+        )
+        .bind(question_id)
+        .map(question_from_row)
+        .fetch_optional(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?
+        .ok_or(Error::QuestionNotFound)
+    }
+
+    async fn get_account_questions(
+        &self,
+        account_id: &AccountId,
+        limit: i64,
+    ) -> Result<Vec<Question>, Error> {
+        sqlx::query(
+            "SELECT id, title, content, tags, account_id, created_on, score,
+                accepted_answer_id, version, deleted_at, locked
+            FROM questions WHERE account_id = $1 AND deleted_at IS NULL
+            ORDER BY created_on DESC, id DESC
+            LIMIT $2",
+        )
+        .bind(account_id.0)
+        .bind(limit)
+        .map(question_from_row)
+        .fetch_all(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })
+    }
+
+    async fn add_question(
+        &self,
+        new_question: NewQuestion,
+        account_id: AccountId,
+    ) -> Result<Question, Error> {
+        let NewQuestion {
+            title,
+            content,
+            tags,
+        } = new_question;
+
+        let mut tx = self.connection.begin().await.map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        let tags = resolve_tags(&mut tx, tags).await?;
+
+        let question = sqlx::query(
+            "INSERT INTO questions (title, content, tags, account_id)
+                VALUES ($1, $2, $3, $4)
+                RETURNING id, title, content, tags, account_id, created_on, score,
+                    accepted_answer_id, version, deleted_at, locked",
+        )
+        .bind(title)
+        .bind(content)
+        .bind(tags.as_ref().map(|tags| tag_names(tags)))
+        .bind(account_id.0)
+        .map(question_from_row)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        set_question_tags(&mut tx, question.id.0, tags.as_deref().unwrap_or_default()).await?;
+
+        tx.commit().await.map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        Ok(question)
+    }
+
+    async fn update_question(
+        &self,
+        question: NewQuestion,
+        question_id: i32,
+        editor_id: AccountId,
+        version: i32,
+    ) -> Result<Question, Error> {
+        let NewQuestion {
+            title,
+            content,
+            tags,
+        } = question;
+
+        let mut tx = self.connection.begin().await.map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        // Lock the question so concurrent edits get consecutive revisions
+        let current: i32 = sqlx::query_scalar(
+            "SELECT version FROM questions WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+        )
+        .bind(question_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?
+        .ok_or(Error::QuestionNotFound)?;
+        if current != version {
+            return Err(Error::PreconditionFailed);
+        }
+
+        let tags = resolve_tags(&mut tx, tags).await?;
+
+        sqlx::query(
+            "INSERT INTO question_revisions
+                (question_id, revision, title, content, tags, account_id)
+            SELECT id, COALESCE(
+                    (SELECT max(revision) FROM question_revisions WHERE question_id = $1), 0
+                ) + 1,
+                title, content, tags, $2
+            FROM questions WHERE id = $1",
+        )
+        .bind(question_id)
+        .bind(editor_id.0)
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        let question = sqlx::query(
+            "UPDATE questions
+        SET title = $1, content = $2, tags = $3, version = version + 1
+        WHERE id = $4
+        RETURNING id, title, content, tags, account_id, created_on, score,
+            accepted_answer_id, version, deleted_at, locked
+        ",
+        )
+        .bind(title)
+        .bind(content)
+        .bind(tags.as_ref().map(|tags| tag_names(tags)))
+        .bind(question_id)
+        .map(question_from_row)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        set_question_tags(&mut tx, question_id, tags.as_deref().unwrap_or_default()).await?;
+
+        tx.commit().await.map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        Ok(question)
+    }
+
+    async fn get_revisions(&self, question_id: i32) -> Result<Vec<QuestionRevision>, Error> {
+        self.get_question_owner(question_id).await?;
+
+        sqlx::query(
+            "SELECT revision, question_id, title, content, tags, account_id, created_on
+                FROM question_revisions WHERE question_id = $1
+                ORDER BY revision",
+        )
+        .bind(question_id)
+        .map(revision_from_row)
+        .fetch_all(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })
+    }
+
+    async fn get_revision(
+        &self,
+        question_id: i32,
+        revision: i32,
+    ) -> Result<QuestionRevision, Error> {
+        sqlx::query(
+            "SELECT revision, question_id, title, content, tags, account_id, created_on
+                FROM question_revisions WHERE question_id = $1 AND revision = $2",
+        )
+        .bind(question_id)
+        .bind(revision)
+        .map(revision_from_row)
+        .fetch_optional(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?
+        .ok_or(Error::RevisionNotFound)
+    }
+
+    async fn delete_question(&self, question_id: i32, version: i32) -> Result<bool, Error> {
+        let deleted = sqlx::query(
+            "UPDATE questions SET deleted_at = NOW()
+            WHERE id = $1 AND version = $2 AND deleted_at IS NULL",
+        )
+        .bind(question_id)
+        .bind(version)
+        .execute(&self.connection)
+        .await
+        .map(|res| res.rows_affected() > 0)
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        match deleted {
+            true => Ok(true),
+            // Still there means it was edited in the meantime
+            false => match self.get_question(question_id).await {
+                Ok(_) => Err(Error::PreconditionFailed),
+                Err(Error::QuestionNotFound) => Ok(false),
+                Err(err) => Err(err),
+            },
+        }
+    }
+
+    async fn get_deleted_question(&self, question_id: i32) -> Result<Question, Error> {
+        sqlx::query(
+            "SELECT id, title, content, tags, account_id, created_on, score,
+                accepted_answer_id, version, deleted_at, locked
+                FROM questions WHERE id = $1 AND deleted_at IS NOT NULL",
+        )
+        .bind(question_id)
+        .map(question_from_row)
+        .fetch_optional(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?
+        .ok_or(Error::QuestionNotFound)
+    }
+
+    async fn restore_question(&self, question_id: i32) -> Result<Question, Error> {
+        sqlx::query(
+            "UPDATE questions SET deleted_at = NULL
+            WHERE id = $1 AND deleted_at IS NOT NULL
+            RETURNING id, title, content, tags, account_id, created_on, score,
+                accepted_answer_id, version, deleted_at, locked",
+        )
+        .bind(question_id)
+        .map(question_from_row)
+        .fetch_optional(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?
+        .ok_or(Error::QuestionNotFound)
+    }
+
+    async fn purge_deleted_questions(&self, deleted_before: NaiveDateTime) -> Result<u64, Error> {
+        // Answers, comments, votes, revisions and tag links go with
+        // their question through `ON DELETE CASCADE`
+        sqlx::query("DELETE FROM questions WHERE deleted_at < $1")
+            .bind(deleted_before)
+            .execute(&self.connection)
+            .await
+            .map(|res| res.rows_affected())
+            .map_err(|err| {
+                event!(Level::ERROR, "{:?}", err);
+                Error::DatabaseQueryError(err)
+            })
+    }
+
+    async fn get_answers_for_question(&self, question_id: i32) -> Result<Vec<Answer>, Error> {
+        sqlx::query(
+            "SELECT answers.id, answers.content, answers.question_id, answers.account_id,
+                answers.created_on, answers.score
+            FROM answers JOIN questions ON questions.id = answers.question_id
+            WHERE answers.question_id = $1 AND questions.deleted_at IS NULL
+            ORDER BY answers.id IS NOT DISTINCT FROM questions.accepted_answer_id DESC,
+                answers.created_on, answers.id",
+        )
+        .bind(question_id)
+        .map(answer_from_row)
+        .fetch_all(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })
+    }
+
+    async fn get_account_answers(
+        &self,
+        account_id: &AccountId,
+        limit: i64,
+    ) -> Result<Vec<Answer>, Error> {
+        sqlx::query(
+            "SELECT answers.id, answers.content, answers.question_id, answers.account_id,
+                answers.created_on, answers.score
+            FROM answers JOIN questions ON questions.id = answers.question_id
+            WHERE answers.account_id = $1 AND questions.deleted_at IS NULL
+            ORDER BY answers.created_on DESC, answers.id DESC
+            LIMIT $2",
+        )
+        .bind(account_id.0)
+        .bind(limit)
+        .map(answer_from_row)
+        .fetch_all(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })
+    }
+
+    async fn get_answer(&self, question_id: i32, answer_id: i32) -> Result<Answer, Error> {
+        sqlx::query(
+            "SELECT answers.id, answers.content, answers.question_id, answers.account_id,
+                answers.created_on, answers.score
+            FROM answers JOIN questions ON questions.id = answers.question_id
+            WHERE answers.id = $1 AND answers.question_id = $2
+                AND questions.deleted_at IS NULL",
+        )
+        .bind(answer_id)
+        .bind(question_id)
+        .map(answer_from_row)
+        .fetch_optional(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?
+        .ok_or(Error::AnswerNotFound)
+    }
+
+    async fn get_answer_question(&self, answer_id: i32) -> Result<Question, Error> {
+        sqlx::query(
+            "SELECT questions.id, questions.title, questions.content, questions.tags,
+                questions.account_id, questions.created_on, questions.score,
+                questions.accepted_answer_id, questions.version, questions.deleted_at,
+                questions.locked
+            FROM answers JOIN questions ON questions.id = answers.question_id
+            WHERE answers.id = $1 AND questions.deleted_at IS NULL",
+        )
+        .bind(answer_id)
+        .map(question_from_row)
+        .fetch_optional(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?
+        .ok_or(Error::AnswerNotFound)
+    }
+
+    async fn add_answer(
+        &self,
+        question_id: i32,
+        new_answer: NewAnswer,
+        account_id: AccountId,
+    ) -> Result<Answer, Error> {
+        let NewAnswer { content } = new_answer;
+
+        sqlx::query(
+            "INSERT INTO answers (content, question_id, account_id)
+                VALUES ($1, $2, $3)
+                RETURNING id, content, question_id, account_id, created_on, score",
+        )
+        .bind(content)
+        .bind(question_id)
+        .bind(account_id.0)
+        .map(answer_from_row)
+        .fetch_one(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })
+    }
+
+    async fn update_answer(
+        &self,
+        question_id: i32,
+        answer_id: i32,
+        answer: NewAnswer,
+    ) -> Result<Answer, Error> {
+        let NewAnswer { content } = answer;
+
+        sqlx::query(
+            "UPDATE answers
+        SET content = $1
+        WHERE id = $2 AND question_id = $3
+        RETURNING id, content, question_id, account_id, created_on, score
+        ",
+        )
+        .bind(content)
+        .bind(answer_id)
+        .bind(question_id)
+        .map(answer_from_row)
+        .fetch_optional(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?
+        .ok_or(Error::AnswerNotFound)
+    }
+
+    async fn delete_answer(&self, question_id: i32, answer_id: i32) -> Result<bool, Error> {
+        sqlx::query("DELETE FROM answers WHERE id = $1 AND question_id = $2")
+            .bind(answer_id)
+            .bind(question_id)
+            .execute(&self.connection)
+            .await
+            .map(|res| res.rows_affected() > 0)
+            .map_err(|err| {
+                event!(Level::ERROR, "{:?}", err);
+                Error::DatabaseQueryError(err)
+            })
+    }
+
+    async fn vote_on_question(
+        &self,
+        question_id: i32,
+        account_id: AccountId,
+        vote: Option<Vote>,
+    ) -> Result<i32, Error> {
+        let mut tx = self.connection.begin().await.map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        // Lock the question so concurrent votes recount one after the other
+        sqlx::query("SELECT 1 FROM questions WHERE id = $1 AND deleted_at IS NULL FOR UPDATE")
+            .bind(question_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|err| {
+                event!(Level::ERROR, "{:?}", err);
+                Error::DatabaseQueryError(err)
+            })?
+            .ok_or(Error::QuestionNotFound)?;
+
+        match vote {
+            Some(vote) => sqlx::query(
+                "INSERT INTO votes (account_id, question_id, value) VALUES ($1, $2, $3)
+                    ON CONFLICT (account_id, question_id) DO UPDATE SET value = EXCLUDED.value",
+            )
+            .bind(account_id.0)
+            .bind(question_id)
+            .bind(i16::from(vote)),
+            None => sqlx::query("DELETE FROM votes WHERE account_id = $1 AND question_id = $2")
+                .bind(account_id.0)
+                .bind(question_id),
+        }
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        let score = sqlx::query_scalar(
+            "UPDATE questions SET score = (
+                SELECT COALESCE(sum(value), 0) FROM votes WHERE question_id = $1
+            ) WHERE id = $1
+            RETURNING score",
+        )
+        .bind(question_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        tx.commit().await.map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        Ok(score)
+    }
+
+    async fn vote_on_answer(
+        &self,
+        question_id: i32,
+        answer_id: i32,
+        account_id: AccountId,
+        vote: Option<Vote>,
+    ) -> Result<i32, Error> {
+        let mut tx = self.connection.begin().await.map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        // Lock the answer so concurrent votes recount one after the other
+        sqlx::query(
+            "SELECT 1 FROM answers JOIN questions ON questions.id = answers.question_id
+            WHERE answers.id = $1 AND answers.question_id = $2 AND questions.deleted_at IS NULL
+            FOR UPDATE OF answers",
+        )
+        .bind(answer_id)
+        .bind(question_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?
+        .ok_or(Error::AnswerNotFound)?;
+
+        match vote {
+            Some(vote) => sqlx::query(
+                "INSERT INTO votes (account_id, answer_id, value) VALUES ($1, $2, $3)
+                    ON CONFLICT (account_id, answer_id) DO UPDATE SET value = EXCLUDED.value",
+            )
+            .bind(account_id.0)
+            .bind(answer_id)
+            .bind(i16::from(vote)),
+            None => sqlx::query("DELETE FROM votes WHERE account_id = $1 AND answer_id = $2")
+                .bind(account_id.0)
+                .bind(answer_id),
+        }
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        let score = sqlx::query_scalar(
+            "UPDATE answers SET score = (
+                SELECT COALESCE(sum(value), 0) FROM votes WHERE answer_id = $1
+            ) WHERE id = $1
+            RETURNING score",
+        )
+        .bind(answer_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        tx.commit().await.map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        Ok(score)
+    }
+
+    async fn accept_answer(&self, question_id: i32, answer_id: i32) -> Result<Question, Error> {
+        self.get_answer(question_id, answer_id).await?;
+
+        sqlx::query(
+            "UPDATE questions SET accepted_answer_id = $1
+            WHERE id = $2 AND deleted_at IS NULL
+            RETURNING id, title, content, tags, account_id, created_on, score,
+                accepted_answer_id, version, deleted_at, locked",
+        )
+        .bind(answer_id)
+        .bind(question_id)
+        .map(question_from_row)
+        .fetch_optional(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?
+        .ok_or(Error::QuestionNotFound)
+    }
+
+    async fn unaccept_answer(&self, question_id: i32, answer_id: i32) -> Result<Question, Error> {
+        sqlx::query(
+            "UPDATE questions SET accepted_answer_id = NULL
+            WHERE id = $1 AND accepted_answer_id = $2",
+        )
+        .bind(question_id)
+        .bind(answer_id)
+        .execute(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        self.get_question(question_id).await
+    }
+
+    async fn set_question_locked(&self, question_id: i32, locked: bool) -> Result<Question, Error> {
+        sqlx::query(
+            "UPDATE questions SET locked = $1
+            WHERE id = $2 AND deleted_at IS NULL
+            RETURNING id, title, content, tags, account_id, created_on, score,
+                accepted_answer_id, version, deleted_at, locked",
+        )
+        .bind(locked)
+        .bind(question_id)
+        .map(question_from_row)
+        .fetch_optional(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?
+        .ok_or(Error::QuestionNotFound)
+    }
+
+    async fn get_question_owner(&self, question_id: i32) -> Result<AccountId, Error> {
+        sqlx::query("SELECT account_id FROM questions WHERE id = $1 AND deleted_at IS NULL")
+            .bind(question_id)
+            .map(|row: PgRow| AccountId(row.get("account_id")))
+            .fetch_optional(&self.connection)
+            .await
+            .map_err(|e| {
+                event!(Level::ERROR, "{:?}", e);
+                Error::DatabaseQueryError(e)
+            })?
+            .ok_or(Error::QuestionNotFound)
+    }
+
+    async fn is_question_owner(
+        &self,
+        question_id: i32,
+        account_id: &AccountId,
+    ) -> Result<bool, Error> {
+        sqlx::query(
+            "SELECT 1 FROM questions
+            WHERE id = $1 and account_id = $2 AND deleted_at IS NULL",
+        )
+        .bind(question_id)
+        .bind(account_id.0)
+        .fetch_optional(&self.connection)
+        .await
+        .map(|question| question.is_some())
+        .map_err(|e| {
+            event!(Level::ERROR, "{:?}", e);
+            Error::DatabaseQueryError(e)
+        })
+    }
+}
+
+impl PgStore {
+    /// Make sure the question or answer a comment is posted on exists
+    async fn check_comment_target(&self, target: CommentTarget) -> Result<(), Error> {
+        let (query, id, not_found) = match target {
+            CommentTarget::Question(id) => (
+                "SELECT 1 FROM questions WHERE id = $1 AND deleted_at IS NULL",
+                id,
+                Error::QuestionNotFound,
+            ),
+            CommentTarget::Answer(id) => (
+                "SELECT 1 FROM answers JOIN questions ON questions.id = answers.question_id
+                WHERE answers.id = $1 AND questions.deleted_at IS NULL",
+                id,
+                Error::AnswerNotFound,
+            ),
+        };
+
+        sqlx::query(query)
+            .bind(id)
+            .fetch_optional(&self.connection)
+            .await
+            .map_err(|err| {
+                event!(Level::ERROR, "{:?}", err);
+                Error::DatabaseQueryError(err)
+            })?
+            .map(|_| ())
+            .ok_or(not_found)
+    }
+}
+
+#[async_trait]
+impl CommentRepository for PgStore {
+    async fn get_comments(&self, target: CommentTarget) -> Result<Vec<Comment>, Error> {
+        self.check_comment_target(target).await?;
+        let (column, id) = comment_target_column(target);
+
+        sqlx::query(&format!(
+            "SELECT id, content, question_id, answer_id, account_id, created_on
+                FROM comments WHERE {} = $1
+                ORDER BY created_on, id",
+            column
+        ))
+        .bind(id)
+        .map(comment_from_row)
+        .fetch_all(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })
+    }
+
+    async fn get_comment(&self, target: CommentTarget, comment_id: i32) -> Result<Comment, Error> {
+        self.check_comment_target(target).await?;
+        let (column, id) = comment_target_column(target);
+
+        sqlx::query(&format!(
+            "SELECT id, content, question_id, answer_id, account_id, created_on
+                FROM comments WHERE id = $1 AND {} = $2",
+            column
+        ))
+        .bind(comment_id)
+        .bind(id)
+        .map(comment_from_row)
+        .fetch_optional(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?
+        .ok_or(Error::CommentNotFound)
+    }
+
+    async fn add_comment(
+        &self,
+        target: CommentTarget,
+        new_comment: NewComment,
+        account_id: AccountId,
+    ) -> Result<Comment, Error> {
+        self.check_comment_target(target).await?;
+        let (column, id) = comment_target_column(target);
+        let NewComment { content } = new_comment;
+
+        sqlx::query(&format!(
+            "INSERT INTO comments (content, {}, account_id)
+                VALUES ($1, $2, $3)
+                RETURNING id, content, question_id, answer_id, account_id, created_on",
+            column
+        ))
+        .bind(content)
+        .bind(id)
+        .bind(account_id.0)
+        .map(comment_from_row)
+        .fetch_one(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })
+    }
+
+    async fn update_comment(
+        &self,
+        target: CommentTarget,
+        comment_id: i32,
+        comment: NewComment,
+    ) -> Result<Comment, Error> {
+        self.check_comment_target(target).await?;
+        let (column, id) = comment_target_column(target);
+        let NewComment { content } = comment;
+
+        sqlx::query(&format!(
+            "UPDATE comments
+        SET content = $1
+        WHERE id = $2 AND {} = $3
+        RETURNING id, content, question_id, answer_id, account_id, created_on",
+            column
+        ))
+        .bind(content)
+        .bind(comment_id)
+        .bind(id)
+        .map(comment_from_row)
+        .fetch_optional(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?
+        .ok_or(Error::CommentNotFound)
+    }
+
+    async fn delete_comment(&self, target: CommentTarget, comment_id: i32) -> Result<bool, Error> {
+        self.check_comment_target(target).await?;
+        let (column, id) = comment_target_column(target);
+
+        sqlx::query(&format!(
+            "DELETE FROM comments WHERE id = $1 AND {} = $2",
+            column
+        ))
+        .bind(comment_id)
+        .bind(id)
+        .execute(&self.connection)
+        .await
+        .map(|res| res.rows_affected() > 0)
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })
+    }
+}
+
+#[async_trait]
+impl TagRepository for PgStore {
+    async fn get_tags(&self, query: &TagQuery) -> Result<Vec<Tag>, Error> {
+        let mut builder = QueryBuilder::new(TAG_SELECT);
+        builder.push(" WHERE tags.synonym_of IS NULL");
+        if let Some(prefix) = &query.prefix {
+            builder.push(" AND (starts_with(tags.name, ");
+            builder.push_bind(prefix.clone());
+            builder.push(
+                ") OR EXISTS (SELECT 1 FROM tags synonym
+                    WHERE synonym.synonym_of = tags.id AND starts_with(synonym.name, ",
+            );
+            builder.push_bind(prefix.clone());
+            builder.push(")))");
+        }
+        builder.push(" ORDER BY count DESC, tags.name LIMIT ");
+        builder.push_bind(query.limit);
+
+        builder
+            .build()
+            .map(tag_from_row)
+            .fetch_all(&self.connection)
+            .await
+            .map_err(|err| {
+                event!(Level::ERROR, "{:?}", err);
+                Error::DatabaseQueryError(err)
+            })
+    }
+
+    async fn get_tag(&self, name: &str) -> Result<Tag, Error> {
+        fetch_tag(&self.connection, name).await
+    }
+
+    async fn update_tag(&self, name: &str, tag: TagUpdate) -> Result<Tag, Error> {
+        let TagUpdate { description } = tag;
+
+        let mut tx = self.connection.begin().await.map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        let (canonical_id, _) = canonical_tag(&mut tx, name).await?;
+        sqlx::query("UPDATE tags SET description = $1 WHERE id = $2")
+            .bind(description)
+            .bind(canonical_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|err| {
+                event!(Level::ERROR, "{:?}", err);
+                Error::DatabaseQueryError(err)
+            })?;
+
+        let tag = fetch_tag(&mut *tx, name).await?;
+        tx.commit().await.map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        Ok(tag)
+    }
+
+    async fn add_tag_synonym(&self, name: &str, synonym: &str) -> Result<Tag, Error> {
+        let mut tx = self.connection.begin().await.map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        let (canonical_id, canonical_name) = canonical_tag(&mut tx, name).await?;
+        if synonym == canonical_name {
+            return Err(Error::InvalidParameter(
+                "a tag cannot be a synonym of itself".to_string(),
+            ));
+        }
+
+        sqlx::query("INSERT INTO tags (name) VALUES ($1) ON CONFLICT (name) DO NOTHING")
+            .bind(synonym)
+            .execute(&mut *tx)
+            .await
+            .map_err(|err| {
+                event!(Level::ERROR, "{:?}", err);
+                Error::DatabaseQueryError(err)
+            })?;
+        let synonym_id: i32 = sqlx::query_scalar("SELECT id FROM tags WHERE name = $1 FOR UPDATE")
+            .bind(synonym)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|err| {
+                event!(Level::ERROR, "{:?}", err);
+                Error::DatabaseQueryError(err)
+            })?;
+
+        // The synonym and the synonyms it had all redirect to the canonical tag,
+        // and the questions filed under it move over as well
+        let statements = [
+            "UPDATE tags SET synonym_of = $1 WHERE id = $2 OR synonym_of = $2",
+            "INSERT INTO question_tags (question_id, tag_id)
+                SELECT question_id, $1 FROM question_tags WHERE tag_id = $2
+                ON CONFLICT DO NOTHING",
+        ];
+        for statement in statements {
+            sqlx::query(statement)
+                .bind(canonical_id)
+                .bind(synonym_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|err| {
+                    event!(Level::ERROR, "{:?}", err);
+                    Error::DatabaseQueryError(err)
+                })?;
+        }
+        sqlx::query("DELETE FROM question_tags WHERE tag_id = $1")
+            .bind(synonym_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|err| {
+                event!(Level::ERROR, "{:?}", err);
+                Error::DatabaseQueryError(err)
+            })?;
+
+        sqlx::query(
+            "UPDATE questions
+            SET tags = (
+                SELECT array_agg(name ORDER BY position)
+                FROM (
+                    SELECT DISTINCT ON (name) name, position
+                    FROM unnest(array_replace(questions.tags, $1, $2))
+                        WITH ORDINALITY AS tag (name, position)
+                    ORDER BY name, position
+                ) deduplicated
+            )
+            WHERE $1 = ANY(tags)",
+        )
+        .bind(synonym)
+        .bind(&canonical_name)
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        let tag = fetch_tag(&mut *tx, &canonical_name).await?;
+        tx.commit().await.map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        Ok(tag)
+    }
+
+    async fn remove_tag_synonym(&self, name: &str, synonym: &str) -> Result<Tag, Error> {
+        let mut tx = self.connection.begin().await.map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        let (canonical_id, canonical_name) = canonical_tag(&mut tx, name).await?;
+        let removed =
+            sqlx::query("UPDATE tags SET synonym_of = NULL WHERE name = $1 AND synonym_of = $2")
+                .bind(synonym)
+                .bind(canonical_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|err| {
+                    event!(Level::ERROR, "{:?}", err);
+                    Error::DatabaseQueryError(err)
+                })?;
+        if removed.rows_affected() == 0 {
+            return Err(Error::TagNotFound);
+        }
+
+        let tag = fetch_tag(&mut *tx, &canonical_name).await?;
+        tx.commit().await.map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        Ok(tag)
+    }
+}
+
+#[async_trait]
+impl AccountRepository for PgStore {
+    async fn add_account(&self, account: Account) -> Result<bool, Error> {
+        let Account {
+            email, password, ..
+        } = account;
+
+        sqlx::query(
+            "INSERT INTO accounts (email, password)
+        VALUES ($1, $2)",
+        )
+        .bind(email)
+        .bind(password)
+        .execute(&self.connection)
+        .await
+        .map(|_| true)
+        .map_err(|err| {
+            match err.as_database_error() {
+                Some(db_err) => {
+                    event!(
+                        Level::ERROR,
+                        code = ?db_err.code(),
+                        db_message = db_err.message(),
+                        constraint = ?db_err.constraint()
+                    );
+
+                    if db_err.is_unique_violation() {
+                        return Error::AccountAlreadyExists;
+                    }
+                }
+                None => event!(Level::ERROR, "{:?}", err),
+            }
+
+            Error::DatabaseQueryError(err)
+        })
+    }
+
+    async fn get_account(&self, email: String) -> Result<Account, Error> {
+        sqlx::query("SELECT id,email,password from accounts WHERE email = $1")
+            .bind(email)
+            .map(|row: PgRow| Account {
+                id: Some(AccountId(row.get("id"))),
+                email: row.get("email"),
+                password: row.get("password"),
+            })
+            .fetch_optional(&self.connection)
+            .await
+            .map_err(|err| {
+                event!(Level::ERROR, "{:?}", err);
+                Error::DatabaseQueryError(err)
+            })?
+            .ok_or(Error::AccountNotFound)
+    }
+
+    async fn get_account_by_id(&self, account_id: &AccountId) -> Result<Account, Error> {
+        sqlx::query("SELECT id, email, password FROM accounts WHERE id = $1")
+            .bind(account_id.0)
+            .map(|row: PgRow| Account {
+                id: Some(AccountId(row.get("id"))),
+                email: row.get("email"),
+                password: row.get("password"),
+            })
+            .fetch_optional(&self.connection)
+            .await
+            .map_err(|err| {
+                event!(Level::ERROR, "{:?}", err);
+                Error::DatabaseQueryError(err)
+            })?
+            .ok_or(Error::UnknownAccount)
+    }
+
+    async fn is_email_verified(&self, account_id: &AccountId) -> Result<bool, Error> {
+        sqlx::query_scalar("SELECT email_verified_at IS NOT NULL FROM accounts WHERE id = $1")
+            .bind(account_id.0)
+            .fetch_optional(&self.connection)
+            .await
+            .map_err(|err| {
+                event!(Level::ERROR, "{:?}", err);
+                Error::DatabaseQueryError(err)
+            })?
+            .ok_or(Error::UnknownAccount)
+    }
+
+    async fn set_email_verified(&self, account_id: &AccountId) -> Result<bool, Error> {
+        let updated = sqlx::query(
+            "UPDATE accounts SET email_verified_at = COALESCE(email_verified_at, NOW())
+            WHERE id = $1",
+        )
+        .bind(account_id.0)
+        .execute(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?
+        .rows_affected();
+
+        if updated == 0 {
+            return Err(Error::UnknownAccount);
+        }
+        Ok(true)
+    }
+
+    async fn set_account_password(
+        &self,
+        account_id: &AccountId,
+        password: String,
+    ) -> Result<bool, Error> {
+        let updated = sqlx::query("UPDATE accounts SET password = $1 WHERE id = $2")
+            .bind(password)
+            .bind(account_id.0)
+            .execute(&self.connection)
+            .await
+            .map_err(|err| {
+                event!(Level::ERROR, "{:?}", err);
+                Error::DatabaseQueryError(err)
+            })?
+            .rows_affected();
+
+        if updated == 0 {
+            return Err(Error::UnknownAccount);
+        }
+        Ok(true)
+    }
+
+    async fn get_actor(&self, account_id: &AccountId) -> Result<Actor, Error> {
+        sqlx::query("SELECT id, role, status FROM accounts WHERE id = $1")
+            .bind(account_id.0)
+            .map(actor_from_row)
+            .fetch_optional(&self.connection)
+            .await
+            .map_err(|e| {
+                event!(Level::ERROR, "{:?}", e);
+                Error::DatabaseQueryError(e)
+            })?
+            .ok_or(Error::Unauthorized)
+    }
+
+    async fn set_account_role(&self, account_id: &AccountId, role: Role) -> Result<Actor, Error> {
+        sqlx::query("UPDATE accounts SET role = $1 WHERE id = $2 RETURNING id, role, status")
+            .bind(role.as_db())
+            .bind(account_id.0)
+            .map(actor_from_row)
+            .fetch_optional(&self.connection)
+            .await
+            .map_err(|err| {
+                event!(Level::ERROR, "{:?}", err);
+                Error::DatabaseQueryError(err)
+            })?
+            .ok_or(Error::UnknownAccount)
+    }
+
+    async fn set_account_status(
+        &self,
+        account_id: &AccountId,
+        status: AccountStatus,
+    ) -> Result<Actor, Error> {
+        sqlx::query("UPDATE accounts SET status = $1 WHERE id = $2 RETURNING id, role, status")
+            .bind(status.as_db())
+            .bind(account_id.0)
+            .map(actor_from_row)
+            .fetch_optional(&self.connection)
+            .await
+            .map_err(|err| {
+                event!(Level::ERROR, "{:?}", err);
+                Error::DatabaseQueryError(err)
+            })?
+            .ok_or(Error::UnknownAccount)
+    }
+
+    async fn get_profile(&self, account_id: &AccountId) -> Result<Profile, Error> {
+        sqlx::query(
+            "SELECT id, display_name, bio, avatar_url, created_on
+            FROM accounts WHERE id = $1",
+        )
+        .bind(account_id.0)
+        .map(profile_from_row)
+        .fetch_optional(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?
+        .ok_or(Error::UnknownAccount)
+    }
+
+    async fn update_profile(
+        &self,
+        account_id: &AccountId,
+        update: ProfileUpdate,
+    ) -> Result<Profile, Error> {
+        let ProfileUpdate {
+            display_name,
+            bio,
+            avatar_url,
+        } = update;
+
+        // NULL keeps a field, an empty string clears it
+        sqlx::query(
+            "UPDATE accounts SET
+                display_name = CASE WHEN $1::text IS NULL THEN display_name
+                    ELSE NULLIF($1, '') END,
+                bio = CASE WHEN $2::text IS NULL THEN bio ELSE NULLIF($2, '') END,
+                avatar_url = CASE WHEN $3::text IS NULL THEN avatar_url
+                    ELSE NULLIF($3, '') END
+            WHERE id = $4
+            RETURNING id, display_name, bio, avatar_url, created_on",
+        )
+        .bind(display_name)
+        .bind(bio)
+        .bind(avatar_url)
+        .bind(account_id.0)
+        .map(profile_from_row)
+        .fetch_optional(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?
+        .ok_or(Error::UnknownAccount)
+    }
+}
+
+#[async_trait]
+impl TokenRepository for PgStore {
+    async fn add_refresh_token(&self, token: RefreshToken) -> Result<bool, Error> {
+        let scopes: Option<Vec<&str>> = token
+            .scopes
+            .as_ref()
+            .map(|scopes| scopes.iter().map(Scope::as_db).collect());
+        sqlx::query(
+            "INSERT INTO refresh_tokens
+                (token_hash, family, account_id, expires_on, two_factor, scopes)
+            VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(token.token_hash)
+        .bind(token.family)
+        .bind(token.account_id.0)
+        .bind(token.expires_on)
+        .bind(token.two_factor)
+        .bind(scopes)
+        .execute(&self.connection)
+        .await
+        .map(|_| true)
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })
+    }
+
+    async fn use_refresh_token(&self, token_hash: &str) -> Result<RefreshToken, Error> {
+        // Only one of two concurrent requests can flip `used_on`
+        let token = sqlx::query(
+            "UPDATE refresh_tokens SET used_on = $2
+            WHERE token_hash = $1 AND used_on IS NULL AND revoked_on IS NULL
+            AND expires_on > $2
+            RETURNING token_hash, family, account_id, expires_on, two_factor, scopes",
+        )
+        .bind(token_hash)
+        .bind(Utc::now().naive_utc())
+        .map(refresh_token_from_row)
+        .fetch_optional(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        if let Some(token) = token {
+            return Ok(token);
+        }
+
+        let revoked = sqlx::query(
+            "UPDATE refresh_tokens SET revoked_on = $2
+            WHERE revoked_on IS NULL AND family = (
+                SELECT family FROM refresh_tokens
+                WHERE token_hash = $1 AND used_on IS NOT NULL AND revoked_on IS NULL
+            )",
+        )
+        .bind(token_hash)
+        .bind(Utc::now().naive_utc())
+        .execute(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?
+        .rows_affected();
+
+        if revoked > 0 {
+            event!(
+                Level::WARN,
+                revoked,
+                "refresh token was reused, revoked its family"
+            );
+        }
+
+        Err(Error::InvalidRefreshToken)
+    }
+
+    async fn revoke_refresh_token_family(
+        &self,
+        token_hash: &str,
+        account_id: &AccountId,
+    ) -> Result<bool, Error> {
+        let family: Option<String> = sqlx::query_scalar(
+            "SELECT family FROM refresh_tokens WHERE token_hash = $1 AND account_id = $2",
+        )
+        .bind(token_hash)
+        .bind(account_id.0)
+        .fetch_optional(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+        let family = family.ok_or(Error::InvalidRefreshToken)?;
+
+        sqlx::query(
+            "UPDATE refresh_tokens SET revoked_on = $2
+            WHERE family = $1 AND revoked_on IS NULL",
+        )
+        .bind(family)
+        .bind(Utc::now().naive_utc())
+        .execute(&self.connection)
+        .await
+        .map(|_| true)
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })
+    }
+
+    async fn revoke_refresh_tokens(&self, account_id: &AccountId) -> Result<u64, Error> {
+        sqlx::query(
+            "UPDATE refresh_tokens SET revoked_on = $2
+            WHERE account_id = $1 AND revoked_on IS NULL",
+        )
+        .bind(account_id.0)
+        .bind(Utc::now().naive_utc())
+        .execute(&self.connection)
+        .await
+        .map(|result| result.rows_affected())
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })
+    }
+
+    async fn add_password_reset(&self, token: AccountToken) -> Result<bool, Error> {
+        sqlx::query(
+            "INSERT INTO password_resets (token_hash, account_id, expires_on)
+            VALUES ($1, $2, $3)",
+        )
+        .bind(token.token_hash)
+        .bind(token.account_id.0)
+        .bind(token.expires_on)
+        .execute(&self.connection)
+        .await
+        .map(|_| true)
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })
+    }
+
+    async fn use_password_reset(&self, token_hash: &str) -> Result<AccountId, Error> {
+        sqlx::query_scalar(
+            "UPDATE password_resets SET used_on = $2
+            WHERE token_hash = $1 AND used_on IS NULL AND expires_on > $2
+            RETURNING account_id",
+        )
+        .bind(token_hash)
+        .bind(Utc::now().naive_utc())
+        .fetch_optional(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?
+        .map(AccountId)
+        .ok_or(Error::InvalidResetToken)
+    }
+
+    async fn add_email_verification(&self, token: AccountToken) -> Result<bool, Error> {
+        sqlx::query(
+            "INSERT INTO email_verifications (token_hash, account_id, expires_on)
+            VALUES ($1, $2, $3)",
+        )
+        .bind(token.token_hash)
+        .bind(token.account_id.0)
+        .bind(token.expires_on)
+        .execute(&self.connection)
+        .await
+        .map(|_| true)
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })
+    }
+
+    async fn use_email_verification(&self, token_hash: &str) -> Result<AccountId, Error> {
+        sqlx::query_scalar(
+            "UPDATE email_verifications SET used_on = $2
+            WHERE token_hash = $1 AND used_on IS NULL AND expires_on > $2
+            RETURNING account_id",
+        )
+        .bind(token_hash)
+        .bind(Utc::now().naive_utc())
+        .fetch_optional(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?
+        .map(AccountId)
+        .ok_or(Error::InvalidVerificationToken)
+    }
+
+    async fn add_login_challenge(&self, token: AccountToken) -> Result<bool, Error> {
+        sqlx::query(
+            "INSERT INTO login_challenges (token_hash, account_id, expires_on)
+            VALUES ($1, $2, $3)",
+        )
+        .bind(token.token_hash)
+        .bind(token.account_id.0)
+        .bind(token.expires_on)
+        .execute(&self.connection)
+        .await
+        .map(|_| true)
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })
+    }
+
+    async fn use_login_challenge(&self, token_hash: &str) -> Result<AccountId, Error> {
+        sqlx::query_scalar(
+            "UPDATE login_challenges SET used_on = $2
+            WHERE token_hash = $1 AND used_on IS NULL AND expires_on > $2
+            RETURNING account_id",
+        )
+        .bind(token_hash)
+        .bind(Utc::now().naive_utc())
+        .fetch_optional(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?
+        .map(AccountId)
+        .ok_or(Error::InvalidLoginChallenge)
+    }
+
+    async fn revoke_access_token(
+        &self,
+        jti: &str,
+        expires_on: NaiveDateTime,
+    ) -> Result<bool, Error> {
+        sqlx::query(
+            "INSERT INTO revoked_tokens (jti, expires_on) VALUES ($1, $2)
+            ON CONFLICT (jti) DO NOTHING",
+        )
+        .bind(jti)
+        .bind(expires_on)
+        .execute(&self.connection)
+        .await
+        .map(|_| true)
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })
+    }
+
+    async fn is_access_token_revoked(&self, jti: &str) -> Result<bool, Error> {
+        sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM revoked_tokens WHERE jti = $1)")
+            .bind(jti)
+            .fetch_one(&self.connection)
+            .await
+            .map_err(|err| {
+                event!(Level::ERROR, "{:?}", err);
+                Error::DatabaseQueryError(err)
+            })
+    }
+
+    async fn purge_expired_tokens(&self, now: NaiveDateTime) -> Result<u64, Error> {
+        let mut tx = self.connection.begin().await.map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        let mut purged = 0;
+        for statement in [
+            "DELETE FROM refresh_tokens WHERE expires_on < $1",
+            "DELETE FROM password_resets WHERE expires_on < $1",
+            "DELETE FROM email_verifications WHERE expires_on < $1",
+            "DELETE FROM login_challenges WHERE expires_on < $1",
+            "DELETE FROM oidc_logins WHERE expires_on < $1",
+            "DELETE FROM revoked_tokens WHERE expires_on < $1",
+        ] {
+            purged += sqlx::query(statement)
+                .bind(now)
+                .execute(&mut *tx)
+                .await
+                .map_err(|err| {
+                    event!(Level::ERROR, "{:?}", err);
+                    Error::DatabaseQueryError(err)
+                })?
+                .rows_affected();
+        }
+
+        tx.commit().await.map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        Ok(purged)
+    }
+}
+
+#[async_trait]
+impl LoginAttemptRepository for PgStore {
+    async fn get_login_failures(&self, key: &str) -> Result<Option<LoginFailures>, Error> {
+        sqlx::query("SELECT failures, last_failure FROM login_failures WHERE key = $1")
+            .bind(key)
+            .map(login_failures_from_row)
+            .fetch_optional(&self.connection)
+            .await
+            .map_err(|err| {
+                event!(Level::ERROR, "{:?}", err);
+                Error::DatabaseQueryError(err)
+            })
+    }
+
+    async fn record_login_failure(
+        &self,
+        key: &str,
+        now: NaiveDateTime,
+        reset_before: NaiveDateTime,
+    ) -> Result<LoginFailures, Error> {
+        sqlx::query(
+            "INSERT INTO login_failures (key, failures, last_failure) VALUES ($1, 1, $2)
+            ON CONFLICT (key) DO UPDATE SET
+                failures = CASE WHEN login_failures.last_failure < $3 THEN 1
+                    ELSE login_failures.failures + 1 END,
+                last_failure = $2
+            RETURNING failures, last_failure",
+        )
+        .bind(key)
+        .bind(now)
+        .bind(reset_before)
+        .map(login_failures_from_row)
+        .fetch_one(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })
+    }
+
+    async fn clear_login_failures(&self, key: &str) -> Result<bool, Error> {
+        sqlx::query("DELETE FROM login_failures WHERE key = $1")
+            .bind(key)
+            .execute(&self.connection)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .map_err(|err| {
+                event!(Level::ERROR, "{:?}", err);
+                Error::DatabaseQueryError(err)
+            })
+    }
+
+    async fn purge_login_failures(&self, before: NaiveDateTime) -> Result<u64, Error> {
+        sqlx::query("DELETE FROM login_failures WHERE last_failure < $1")
+            .bind(before)
+            .execute(&self.connection)
+            .await
+            .map(|result| result.rows_affected())
+            .map_err(|err| {
+                event!(Level::ERROR, "{:?}", err);
+                Error::DatabaseQueryError(err)
+            })
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepository for PgStore {
+    async fn add_api_key(
+        &self,
+        account_id: &AccountId,
+        key: NewApiKey,
+        prefix: &str,
+        key_hash: &str,
+    ) -> Result<ApiKey, Error> {
+        let scopes: Vec<&str> = key.scopes.iter().map(Scope::as_db).collect();
+        sqlx::query(
+            "INSERT INTO api_keys (account_id, name, prefix, key_hash, scopes)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, account_id, name, prefix, scopes, created_on, last_used_on",
+        )
+        .bind(account_id.0)
+        .bind(key.name)
+        .bind(prefix)
+        .bind(key_hash)
+        .bind(scopes)
+        .map(api_key_from_row)
+        .fetch_one(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })
+    }
+
+    async fn get_api_keys(&self, account_id: &AccountId) -> Result<Vec<ApiKey>, Error> {
+        sqlx::query(
+            "SELECT id, account_id, name, prefix, scopes, created_on, last_used_on
+            FROM api_keys WHERE account_id = $1 AND revoked_on IS NULL
+            ORDER BY id",
+        )
+        .bind(account_id.0)
+        .map(api_key_from_row)
+        .fetch_all(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })
+    }
+
+    async fn revoke_api_key(
+        &self,
+        account_id: &AccountId,
+        api_key_id: ApiKeyId,
+    ) -> Result<bool, Error> {
+        let revoked = sqlx::query(
+            "UPDATE api_keys SET revoked_on = $3
+            WHERE id = $1 AND account_id = $2 AND revoked_on IS NULL",
+        )
+        .bind(api_key_id.0)
+        .bind(account_id.0)
+        .bind(Utc::now().naive_utc())
+        .execute(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?
+        .rows_affected();
+
+        if revoked == 0 {
+            return Err(Error::ApiKeyNotFound);
+        }
+
+        Ok(true)
+    }
+
+    async fn use_api_key(&self, key_hash: &str, now: NaiveDateTime) -> Result<ApiKey, Error> {
+        sqlx::query(
+            "UPDATE api_keys SET last_used_on = $2
+            WHERE key_hash = $1 AND revoked_on IS NULL
+            RETURNING id, account_id, name, prefix, scopes, created_on, last_used_on",
+        )
+        .bind(key_hash)
+        .bind(now)
+        .map(api_key_from_row)
+        .fetch_optional(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?
+        .ok_or(Error::InvalidApiKey)
+    }
+}
+
+#[async_trait]
+impl IdentityRepository for PgStore {
+    async fn add_oidc_login(&self, login: OidcLogin) -> Result<bool, Error> {
+        sqlx::query(
+            "INSERT INTO oidc_logins (state_hash, nonce, code_verifier, expires_on)
+            VALUES ($1, $2, $3, $4)",
+        )
+        .bind(login.state_hash)
+        .bind(login.nonce)
+        .bind(login.code_verifier)
+        .bind(login.expires_on)
+        .execute(&self.connection)
+        .await
+        .map(|_| true)
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })
+    }
+
+    async fn use_oidc_login(&self, state_hash: &str) -> Result<OidcLogin, Error> {
+        sqlx::query(
+            "UPDATE oidc_logins SET used_on = $2
+            WHERE state_hash = $1 AND used_on IS NULL AND expires_on > $2
+            RETURNING state_hash, nonce, code_verifier, expires_on",
+        )
+        .bind(state_hash)
+        .bind(Utc::now().naive_utc())
+        .map(|row: PgRow| OidcLogin {
+            state_hash: row.get("state_hash"),
+            nonce: row.get("nonce"),
+            code_verifier: row.get("code_verifier"),
+            expires_on: row.get("expires_on"),
+        })
+        .fetch_optional(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?
+        .ok_or(Error::InvalidOidcState)
+    }
+
+    async fn get_identity_account(
+        &self,
+        issuer: &str,
+        subject: &str,
+    ) -> Result<Option<AccountId>, Error> {
+        sqlx::query_scalar(
+            "SELECT account_id FROM external_identities WHERE issuer = $1 AND subject = $2",
+        )
+        .bind(issuer)
+        .bind(subject)
+        .fetch_optional(&self.connection)
+        .await
+        .map(|account_id| account_id.map(AccountId))
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })
+    }
+
+    async fn link_identity(
+        &self,
+        issuer: &str,
+        subject: &str,
+        account_id: &AccountId,
+    ) -> Result<bool, Error> {
+        sqlx::query(
+            "INSERT INTO external_identities (issuer, subject, account_id)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (issuer, subject) DO NOTHING",
+        )
+        .bind(issuer)
+        .bind(subject)
+        .bind(account_id.0)
+        .execute(&self.connection)
+        .await
+        .map(|result| result.rows_affected() > 0)
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })
+    }
+}
+
+#[async_trait]
+impl TwoFactorRepository for PgStore {
+    async fn get_two_factor(&self, account_id: &AccountId) -> Result<Option<TwoFactor>, Error> {
+        sqlx::query(
+            "SELECT account_id, secret, confirmed_on IS NOT NULL AS confirmed, last_used_step
+            FROM two_factor WHERE account_id = $1",
+        )
+        .bind(account_id.0)
+        .map(two_factor_from_row)
+        .fetch_optional(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })
+    }
+
+    async fn set_two_factor_secret(
+        &self,
+        account_id: &AccountId,
+        secret: &str,
+    ) -> Result<bool, Error> {
+        let replaced = sqlx::query(
+            "INSERT INTO two_factor (account_id, secret) VALUES ($1, $2)
+            ON CONFLICT (account_id) DO UPDATE
+            SET secret = EXCLUDED.secret, last_used_step = 0, created_on = NOW()
+            WHERE two_factor.confirmed_on IS NULL",
+        )
+        .bind(account_id.0)
+        .bind(secret)
+        .execute(&self.connection)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?
+        .rows_affected();
+
+        if replaced == 0 {
+            return Err(Error::TwoFactorAlreadyEnabled);
+        }
+
+        Ok(true)
+    }
+
+    async fn confirm_two_factor(
+        &self,
+        account_id: &AccountId,
+        step: i64,
+        recovery_code_hashes: Vec<String>,
+    ) -> Result<bool, Error> {
+        let mut tx = self.connection.begin().await.map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        let confirmed = sqlx::query(
+            "UPDATE two_factor SET confirmed_on = $2, last_used_step = $3
+            WHERE account_id = $1",
+        )
+        .bind(account_id.0)
+        .bind(Utc::now().naive_utc())
+        .bind(step)
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?
+        .rows_affected();
+
+        if confirmed == 0 {
+            return Err(Error::TwoFactorNotEnabled);
+        }
+
+        sqlx::query("DELETE FROM recovery_codes WHERE account_id = $1")
+            .bind(account_id.0)
+            .execute(&mut *tx)
+            .await
+            .map_err(|err| {
+                event!(Level::ERROR, "{:?}", err);
+                Error::DatabaseQueryError(err)
+            })?;
+
+        sqlx::query(
+            "INSERT INTO recovery_codes (account_id, code_hash)
+            SELECT $1, code_hash FROM UNNEST($2::text[]) AS code_hash",
+        )
+        .bind(account_id.0)
+        .bind(recovery_code_hashes)
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        tx.commit().await.map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        Ok(true)
+    }
+
+    async fn disable_two_factor(&self, account_id: &AccountId) -> Result<bool, Error> {
+        let mut tx = self.connection.begin().await.map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        sqlx::query("DELETE FROM recovery_codes WHERE account_id = $1")
+            .bind(account_id.0)
+            .execute(&mut *tx)
+            .await
+            .map_err(|err| {
+                event!(Level::ERROR, "{:?}", err);
+                Error::DatabaseQueryError(err)
+            })?;
+
+        let disabled = sqlx::query("DELETE FROM two_factor WHERE account_id = $1")
+            .bind(account_id.0)
+            .execute(&mut *tx)
+            .await
+            .map_err(|err| {
+                event!(Level::ERROR, "{:?}", err);
+                Error::DatabaseQueryError(err)
+            })?
+            .rows_affected();
+
+        tx.commit().await.map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+
+        Ok(disabled > 0)
+    }
+
+    async fn use_totp_step(&self, account_id: &AccountId, step: i64) -> Result<bool, Error> {
+        // Only one of two requests with the same code can move the step on
+        sqlx::query(
+            "UPDATE two_factor SET last_used_step = $2
+            WHERE account_id = $1 AND last_used_step < $2",
+        )
+        .bind(account_id.0)
+        .bind(step)
+        .execute(&self.connection)
+        .await
+        .map(|result| result.rows_affected() > 0)
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })
+    }
+
+    async fn use_recovery_code(
+        &self,
+        account_id: &AccountId,
+        code_hash: &str,
+    ) -> Result<bool, Error> {
+        sqlx::query("DELETE FROM recovery_codes WHERE account_id = $1 AND code_hash = $2")
+            .bind(account_id.0)
+            .bind(code_hash)
+            .execute(&self.connection)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .map_err(|err| {
+                event!(Level::ERROR, "{:?}", err);
+                Error::DatabaseQueryError(err)
+            })
+    }
+}
+
+/// Append the `WHERE` conditions of a questions list query,
+/// the builder has to end with a `WHERE` clause already.
+/// Deleted questions are always left out.
+fn push_question_filters(builder: &mut QueryBuilder<'_, Postgres>, query: &QuestionQuery) {
+    builder.push(" AND questions.deleted_at IS NULL");
+    if let Some(tag) = &query.tag {
+        builder.push(
+            " AND EXISTS (SELECT 1 FROM question_tags
+                WHERE question_tags.question_id = questions.id
+                AND question_tags.tag_id = (SELECT COALESCE(synonym_of, id) FROM tags WHERE name = ",
+        );
+        builder.push_bind(tag.clone());
+        builder.push("))");
+    }
+    if let Some(author) = query.author {
+        builder.push(" AND questions.account_id = ");
+        builder.push_bind(author.0);
+    }
+    if let Some(created_after) = query.created_after {
+        builder.push(" AND questions.created_on > ");
+        builder.push_bind(created_after);
+    }
+    if let Some(created_before) = query.created_before {
+        builder.push(" AND questions.created_on < ");
+        builder.push_bind(created_before);
+    }
+    match query.answered {
+        Some(true) => builder.push(" AND questions.accepted_answer_id IS NOT NULL"),
+        Some(false) => builder.push(" AND questions.accepted_answer_id IS NULL"),
+        None => builder,
+    };
+    if query.unanswered {
+        builder.push(
+            " AND NOT EXISTS (SELECT 1 FROM answers WHERE answers.question_id = questions.id)",
+        );
+    }
+}
+
+/// Start a search query with the `query` and `ranked` tables,
+/// answers count for half as much as the question itself
+fn push_search_matches(builder: &mut QueryBuilder<'_, Postgres>, terms: &str) {
+    builder.push("WITH query AS (SELECT websearch_to_tsquery('english', ");
+    builder.push_bind(terms.to_string());
+    builder.push(
+        ") AS q),
+        matches AS (
+            SELECT questions.id, ts_rank(questions.search, query.q) AS rank
+                FROM questions, query WHERE questions.search @@ query.q
+            UNION ALL
+            SELECT answers.question_id, (ts_rank(answers.search, query.q) * 0.5)::real
+                FROM answers, query WHERE answers.search @@ query.q
+        ),
+        ranked AS (SELECT id, max(rank) AS rank FROM matches GROUP BY id)",
+    );
+}
+
+/// `column` with the characters HTML gives a meaning to escaped, for
+/// `ts_headline` to work on, so the only markup in a snippet is its own
+fn escaped_html(column: &str) -> String {
+    format!(
+        "replace(replace(replace(replace(replace({}, '&', '&amp;'), '<', '&lt;'), '>', '&gt;'), \
+            '\"', '&quot;'), '''', '&#39;')",
+        column
+    )
+}
+
+/// The column a list is sorted by, ties are broken by the question id
+fn sort_key(sort: QuestionSort) -> &'static str {
+    match sort {
+        QuestionSort::Newest | QuestionSort::Oldest => "questions.created_on",
+        QuestionSort::Activity => {
+            "GREATEST(questions.created_on, (SELECT max(answers.created_on) FROM answers
+                WHERE answers.question_id = questions.id))"
+        }
+        QuestionSort::Relevance => "ranked.rank",
+        QuestionSort::Score => "questions.score",
+    }
+}
+
+fn cursor_from_row(sort: QuestionSort, row: &PgRow) -> Cursor {
+    let key = match sort {
+        QuestionSort::Newest | QuestionSort::Oldest => CursorKey::Created(row.get("sort_key")),
+        QuestionSort::Activity => CursorKey::Activity(row.get("sort_key")),
+        QuestionSort::Relevance => CursorKey::Rank(row.get("sort_key")),
+        QuestionSort::Score => CursorKey::Score(row.get("sort_key")),
+    };
+
+    Cursor {
+        key,
+        id: row.get("id"),
+    }
+}
+
+/// Append the keyset condition, `ORDER BY` and `LIMIT`/`OFFSET` of a
+/// questions list query. Pages before a cursor are fetched in reverse,
+/// nearest to the cursor first.
+fn push_page(builder: &mut QueryBuilder<'_, Postgres>, query: &QuestionQuery, sort: QuestionSort) {
+    let backwards = matches!(query.cursor, Some(PageCursor::Before(_)));
+    let descending = sort.is_descending() != backwards;
+
+    if let Some(PageCursor::After(cursor) | PageCursor::Before(cursor)) = &query.cursor {
+        builder.push(" AND (");
+        builder.push(sort_key(sort));
+        builder.push(", questions.id) ");
+        builder.push(if descending { "<" } else { ">" });
+        builder.push(" (");
+        match cursor.key {
+            CursorKey::Created(key) | CursorKey::Activity(key) => builder.push_bind(key),
+            CursorKey::Rank(key) => builder.push_bind(key),
+            CursorKey::Score(key) => builder.push_bind(key),
+        };
+        builder.push(", ");
+        builder.push_bind(cursor.id);
+        builder.push(")");
+    }
+
+    let direction = if descending { "DESC" } else { "ASC" };
+    builder.push(format!(
+        " ORDER BY {} {}, questions.id {}",
+        sort_key(sort),
+        direction,
+        direction
+    ));
+
+    builder.push(" LIMIT ");
+    builder.push_bind(query.pagination.fetch_limit());
+    builder.push(" OFFSET ");
+    builder.push_bind(query.pagination.offset);
+}
+
+/// Replace every tag by its canonical tag, creating the ones that don't
+/// exist yet, and return the ids and names without duplicates
+async fn resolve_tags(
+    conn: &mut PgConnection,
+    tags: Option<Vec<String>>,
+) -> Result<Option<Vec<(i32, String)>>, Error> {
+    let Some(tags) = tags else {
+        return Ok(None);
+    };
+
+    let mut resolved: Vec<(i32, String)> = Vec::with_capacity(tags.len());
+    for name in tags {
+        sqlx::query("INSERT INTO tags (name) VALUES ($1) ON CONFLICT (name) DO NOTHING")
+            .bind(&name)
+            .execute(&mut *conn)
+            .await
+            .map_err(|err| {
+                event!(Level::ERROR, "{:?}", err);
+                Error::DatabaseQueryError(err)
+            })?;
+
+        let tag = canonical_tag(&mut *conn, &name).await?;
+        if !resolved.contains(&tag) {
+            resolved.push(tag);
+        }
+    }
+
+    Ok(Some(resolved))
+}
+
+fn tag_names(tags: &[(i32, String)]) -> Vec<String> {
+    tags.iter().map(|(_, name)| name.clone()).collect()
+}
+
+/// Point the `question_tags` rows of a question at exactly these tags
+async fn set_question_tags(
+    conn: &mut PgConnection,
+    question_id: i32,
+    tags: &[(i32, String)],
+) -> Result<(), Error> {
+    let tag_ids: Vec<i32> = tags.iter().map(|(id, _)| *id).collect();
+
+    sqlx::query("DELETE FROM question_tags WHERE question_id = $1")
+        .bind(question_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, "{:?}", err);
+            Error::DatabaseQueryError(err)
+        })?;
+    sqlx::query(
+        "INSERT INTO question_tags (question_id, tag_id)
+            SELECT $1, unnest($2::integer[])",
+    )
+    .bind(question_id)
+    .bind(tag_ids)
+    .execute(&mut *conn)
+    .await
+    .map_err(|err| {
+        event!(Level::ERROR, "{:?}", err);
+        Error::DatabaseQueryError(err)
+    })?;
+
+    Ok(())
+}
+
+/// The id and name of the tag `name` redirects to, or of `name`
+/// itself if it isn't a synonym
+async fn canonical_tag(conn: &mut PgConnection, name: &str) -> Result<(i32, String), Error> {
+    sqlx::query_as(
+        "SELECT COALESCE(canonical.id, tags.id), COALESCE(canonical.name, tags.name)
+            FROM tags LEFT JOIN tags canonical ON canonical.id = tags.synonym_of
+            WHERE tags.name = $1",
+    )
+    .bind(name)
+    .fetch_optional(conn)
+    .await
+    .map_err(|err| {
+        event!(Level::ERROR, "{:?}", err);
+        Error::DatabaseQueryError(err)
+    })?
+    .ok_or(Error::TagNotFound)
+}
+
+/// Look up the canonical tag of `name`, along with its synonyms and count
+async fn fetch_tag<'e, E>(executor: E, name: &str) -> Result<Tag, Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query(&format!(
+        "{} WHERE tags.id = (SELECT COALESCE(synonym_of, id) FROM tags WHERE name = $1)",
+        TAG_SELECT
+    ))
+    .bind(name)
+    .map(tag_from_row)
+    .fetch_optional(executor)
+    .await
+    .map_err(|err| {
+        event!(Level::ERROR, "{:?}", err);
+        Error::DatabaseQueryError(err)
+    })?
+    .ok_or(Error::TagNotFound)
+}
+
+fn tag_from_row(row: PgRow) -> Tag {
+    Tag {
+        name: row.get("name"),
+        description: row.get("description"),
+        synonyms: row.get("synonyms"),
+        count: row.get("count"),
+    }
+}
+
+fn question_from_row(row: PgRow) -> Question {
+    Question {
+        id: QuestionId(row.get("id")),
+        title: row.get("title"),
+        content: row.get("content"),
+        tags: row.get("tags"),
+        account_id: AccountId(row.get("account_id")),
+        created_on: row.get("created_on"),
+        score: row.get("score"),
+        accepted_answer_id: row
+            .get::<Option<i32>, _>("accepted_answer_id")
+            .map(AnswerId),
+        version: row.get("version"),
+        deleted_at: row.get("deleted_at"),
+        locked: row.get("locked"),
+    }
+}
+
+fn profile_from_row(row: PgRow) -> Profile {
+    Profile {
+        id: AccountId(row.get("id")),
+        display_name: row.get("display_name"),
+        bio: row.get("bio"),
+        avatar_url: row.get("avatar_url"),
+        created_on: row.get("created_on"),
+    }
+}
+
+fn actor_from_row(row: PgRow) -> Actor {
+    Actor {
+        account_id: AccountId(row.get("id")),
+        role: Role::from_db(row.get("role")),
+        status: AccountStatus::from_db(row.get("status")),
+        two_factor: false,
+    }
+}
+
+fn login_failures_from_row(row: PgRow) -> LoginFailures {
+    LoginFailures {
+        failures: row.get("failures"),
+        last_failure: row.get("last_failure"),
+    }
+}
+
+fn refresh_token_from_row(row: PgRow) -> RefreshToken {
+    let scopes: Option<Vec<String>> = row.get("scopes");
+    RefreshToken {
+        token_hash: row.get("token_hash"),
+        family: row.get("family"),
+        account_id: AccountId(row.get("account_id")),
+        expires_on: row.get("expires_on"),
+        two_factor: row.get("two_factor"),
+        scopes: scopes.map(|scopes| {
+            scopes
+                .iter()
+                .filter_map(|scope| Scope::from_db(scope))
+                .collect()
+        }),
+    }
+}
+
+fn api_key_from_row(row: PgRow) -> ApiKey {
+    let scopes: Vec<String> = row.get("scopes");
+    ApiKey {
+        id: ApiKeyId(row.get("id")),
+        account_id: AccountId(row.get("account_id")),
+        name: row.get("name"),
+        prefix: row.get("prefix"),
+        scopes: scopes
+            .iter()
+            .filter_map(|scope| Scope::from_db(scope))
+            .collect(),
+        created_on: row.get("created_on"),
+        last_used_on: row.get("last_used_on"),
+    }
+}
+
+fn two_factor_from_row(row: PgRow) -> TwoFactor {
+    TwoFactor {
+        account_id: AccountId(row.get("account_id")),
+        secret: row.get("secret"),
+        confirmed: row.get("confirmed"),
+        last_used_step: row.get("last_used_step"),
+    }
+}
+
+fn revision_from_row(row: PgRow) -> QuestionRevision {
+    QuestionRevision {
+        revision: row.get("revision"),
+        question_id: QuestionId(row.get("question_id")),
+        title: row.get("title"),
+        content: row.get("content"),
+        tags: row.get("tags"),
+        editor_id: AccountId(row.get("account_id")),
+        edited_on: row.get("created_on"),
+    }
+}
+
+/// The column of `comments` pointing at a target, and the target's id
+fn comment_target_column(target: CommentTarget) -> (&'static str, i32) {
+    match target {
+        CommentTarget::Question(id) => ("question_id", id),
+        CommentTarget::Answer(id) => ("answer_id", id),
+    }
+}
+
+fn comment_from_row(row: PgRow) -> Comment {
+    Comment {
+        id: CommentId(row.get("id")),
+        content: row.get("content"),
+        question_id: row.get::<Option<i32>, _>("question_id").map(QuestionId),
+        answer_id: row.get::<Option<i32>, _>("answer_id").map(AnswerId),
+        account_id: AccountId(row.get("account_id")),
+        created_on: row.get("created_on"),
+    }
+}
+
+fn answer_from_row(row: PgRow) -> Answer {
+    Answer {
+        id: AnswerId(row.get("id")),
+        content: row.get("content"),
+        question_id: QuestionId(row.get("question_id")),
+        account_id: AccountId(row.get("account_id")),
+        created_on: row.get("created_on"),
+        score: row.get("score"),
+    }
+}
+
+/// These need a Postgres server and run with
+/// `DATABASE_URL=postgres://... cargo test -- --ignored`,
+/// each in a fresh database with the migrations applied
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Pagination;
+
+    /// A store whose database has accounts 1 to `accounts`, as the
+    /// posts and votes of the tests need to belong to real accounts
+    async fn store(pool: PgPool, accounts: i32) -> PgStore {
+        let store = PgStore { connection: pool };
+        for i in 1..=accounts {
+            store
+                .add_account(Account {
+                    id: None,
+                    email: format!("user{}@example.com", i),
+                    password: "password".to_string(),
+                })
+                .await
+                .unwrap();
+        }
+        store
+    }
+
+    fn new_question(title: &str) -> NewQuestion {
+        NewQuestion {
+            title: title.to_string(),
+            content: "content".to_string(),
+            tags: None,
+        }
+    }
+
+    fn titles(questions: &[Question]) -> Vec<&str> {
+        questions.iter().map(|q| q.title.as_str()).collect()
+    }
+
+    #[ignore = "needs DATABASE_URL"]
+    #[sqlx::test]
+    async fn updates_only_the_version_an_edit_is_based_on(pool: PgPool) {
+        let store = store(pool, 2).await;
+        let question = store
+            .add_question(new_question("first"), AccountId(1))
+            .await
+            .unwrap();
+        let current = store
+            .update_question(new_question("second"), question.id.0, AccountId(2), 1)
+            .await
+            .unwrap();
+        assert_eq!("second", current.title);
+        assert_eq!(2, current.version);
+
+        assert!(matches!(
+            store
+                .update_question(new_question("stale"), question.id.0, AccountId(1), 1)
+                .await,
+            Err(Error::PreconditionFailed)
+        ));
+        assert!(matches!(
+            store.delete_question(question.id.0, 1).await,
+            Err(Error::PreconditionFailed)
+        ));
+
+        let revisions = store.get_revisions(question.id.0).await.unwrap();
+        assert_eq!(1, revisions.len());
+        assert_eq!("first", revisions[0].title);
+        assert_eq!(AccountId(2), revisions[0].editor_id);
+        assert!(store.delete_question(question.id.0, 2).await.unwrap());
+    }
+
+    #[ignore = "needs DATABASE_URL"]
+    #[sqlx::test]
+    async fn counts_one_vote_per_account(pool: PgPool) {
+        let store = store(pool, 3).await;
+        let first = store
+            .add_question(new_question("first"), AccountId(1))
+            .await
+            .unwrap();
+        let second = store
+            .add_question(new_question("second"), AccountId(1))
+            .await
+            .unwrap();
+
+        let vote = |account, vote| store.vote_on_question(second.id.0, AccountId(account), vote);
+        assert_eq!(1, vote(2, Some(Vote::Up)).await.unwrap());
+        assert_eq!(1, vote(2, Some(Vote::Up)).await.unwrap());
+        assert_eq!(2, vote(3, Some(Vote::Up)).await.unwrap());
+        assert_eq!(0, vote(2, Some(Vote::Down)).await.unwrap());
+        assert_eq!(1, vote(2, None).await.unwrap());
+
+        store
+            .vote_on_question(first.id.0, AccountId(2), Some(Vote::Down))
+            .await
+            .unwrap();
+        let query = QuestionQuery {
+            sort: Some(QuestionSort::Score),
+            ..Default::default()
+        };
+        let questions = store.get_questions(&query).await.unwrap();
+        assert_eq!(vec!["second", "first"], titles(&questions.items));
+        assert_eq!(1, questions.items[0].score);
+
+        let answer = store
+            .add_answer(
+                second.id.0,
+                NewAnswer {
+                    content: "answer".to_string(),
+                },
+                AccountId(1),
+            )
+            .await
+            .unwrap();
+        let score = store
+            .vote_on_answer(second.id.0, answer.id.0, AccountId(2), Some(Vote::Down))
+            .await
+            .unwrap();
+        assert_eq!(-1, score);
+        assert!(matches!(
+            store
+                .vote_on_answer(first.id.0, answer.id.0, AccountId(2), None)
+                .await,
+            Err(Error::AnswerNotFound)
+        ));
+    }
+
+    #[ignore = "needs DATABASE_URL"]
+    #[sqlx::test]
+    async fn pages_through_questions_with_cursors(pool: PgPool) {
+        let store = store(pool, 1).await;
+        for title in ["one", "two", "three", "four", "five"] {
+            store
+                .add_question(new_question(title), AccountId(1))
+                .await
+                .unwrap();
+        }
+
+        let mut query = QuestionQuery {
+            sort: Some(QuestionSort::Newest),
+            pagination: Pagination {
+                limit: Some(2),
+                offset: 0,
+            },
+            count: true,
+            ..Default::default()
+        };
+        let first = store.get_questions(&query).await.unwrap();
+        assert_eq!(vec!["five", "four"], titles(&first.items));
+        assert_eq!(Some(5), first.total);
+        assert!(first.prev.is_none());
+
+        query.cursor = first.next.map(PageCursor::After);
+        let second = store.get_questions(&query).await.unwrap();
+        assert_eq!(vec!["three", "two"], titles(&second.items));
+
+        query.cursor = second.next.map(PageCursor::After);
+        let last = store.get_questions(&query).await.unwrap();
+        assert_eq!(vec!["one"], titles(&last.items));
+        assert!(last.next.is_none());
+
+        query.cursor = last.prev.map(PageCursor::Before);
+        let back = store.get_questions(&query).await.unwrap();
+        assert_eq!(vec!["three", "two"], titles(&back.items));
+        assert!(back.prev.is_some());
+    }
+
+    #[ignore = "needs DATABASE_URL"]
+    #[sqlx::test]
+    async fn synonyms_merge_into_their_canonical_tag(pool: PgPool) {
+        let store = store(pool, 1).await;
+        let tagged = |title: &str, tags: &[&str]| {
+            let mut question = new_question(title);
+            question.tags = Some(tags.iter().map(|tag| tag.to_string()).collect());
+            question
+        };
+        store
+            .add_question(tagged("both", &["rust", "rustlang"]), AccountId(1))
+            .await
+            .unwrap();
+        store
+            .add_question(tagged("old", &["rustlang", "web"]), AccountId(1))
+            .await
+            .unwrap();
+
+        let tag = store.add_tag_synonym("rust", "rustlang").await.unwrap();
+        assert_eq!(vec!["rustlang"], tag.synonyms);
+        assert_eq!(2, tag.count);
+
+        let question = store
+            .add_question(tagged("new", &["rustlang"]), AccountId(1))
+            .await
+            .unwrap();
+        assert_eq!(Some(vec!["rust".to_string()]), question.tags);
+        let query = QuestionQuery {
+            tag: Some("rustlang".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(3, store.get_questions(&query).await.unwrap().items.len());
+
+        let tags = store.get_tags(&TagQuery::default()).await.unwrap();
+        let counts: Vec<_> = tags.iter().map(|t| (t.name.as_str(), t.count)).collect();
+        assert_eq!(vec![("rust", 3), ("web", 1)], counts);
+
+        assert!(matches!(
+            store.add_tag_synonym("rustlang", "rust").await,
+            Err(Error::InvalidParameter(_))
+        ));
+        let tag = store.remove_tag_synonym("rust", "rustlang").await.unwrap();
+        assert!(tag.synonyms.is_empty());
+    }
+
+    #[ignore = "needs DATABASE_URL"]
+    #[sqlx::test]
+    async fn snippets_escape_html(pool: PgPool) {
+        let store = store(pool, 1).await;
+        let question = NewQuestion {
+            title: "Why does <script> run?".to_string(),
+            content: "<script>alert('x')</script> & more".to_string(),
+            tags: None,
+        };
+        let question = store.add_question(question, AccountId(1)).await.unwrap();
+        let answer = NewAnswer {
+            content: "<b>Never</b> trust a script".to_string(),
+        };
+        store
+            .add_answer(question.id.0, answer, AccountId(1))
+            .await
+            .unwrap();
+
+        let results = store
+            .search_questions("script".to_string(), &QuestionQuery::default())
+            .await
+            .unwrap()
+            .items;
+
+        assert_eq!(
+            "Why does &lt;<b>script</b>&gt; run?",
+            results[0].title_snippet
+        );
+        assert!(!results[0].content_snippet.contains("<script"));
+        assert!(results[0].content_snippet.contains("&lt;<b>script</b>&gt;"));
+        assert_eq!(
+            "&lt;b&gt;Never&lt;/b&gt; trust a <b>script</b>",
+            results[0].answer_snippets[0].snippet
+        );
+    }
+}
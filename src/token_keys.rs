@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use handle_errors::Error;
+use paseto::pae::pae;
+use paseto::tokens::{validate_potential_json_blob, TimeBackend};
+use ring::rand::SystemRandom;
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::Config;
+
+const LOCAL_HEADER: &str = "v2.local.";
+const PUBLIC_HEADER: &str = "v4.public.";
+/// Bytes of the nonce `v2.local` tokens start with
+const LOCAL_NONCE_LEN: usize = 24;
+/// Bytes of the Poly1305 tag `v2.local` tokens end with
+const LOCAL_TAG_LEN: usize = 16;
+/// Bytes of the Ed25519 signature `v4.public` tokens end with
+const SIGNATURE_LEN: usize = 64;
+
+/// A key tokens are issued or checked with
+#[derive(Debug)]
+enum Key {
+    /// Shared secret of `v2.local` tokens, which only this service can read
+    Local(Vec<u8>),
+    /// Ed25519 key pair signing `v4.public` tokens, which anyone with the
+    /// public key can check
+    Secret(Ed25519KeyPair),
+    /// Public key of a key pair, only good for checking tokens
+    Public(Vec<u8>),
+}
+
+/// Footer of the tokens, naming the key they were issued with
+#[derive(Debug, Serialize, Deserialize)]
+struct Footer {
+    kid: String,
+}
+
+/// A public key other services can check tokens with, as a PASERK
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PublicKey {
+    pub kid: String,
+    pub key: String,
+}
+
+/// The keys access tokens are issued and checked with. Tokens are issued
+/// with the current key and name it in their footer, and are accepted
+/// from every key kept for checking, so keys can be rotated while the
+/// tokens of the one before are still around.
+#[derive(Debug)]
+pub struct TokenKeys {
+    kid: String,
+    keys: HashMap<String, Key>,
+}
+
+/// The keys the routes are handed
+pub type SharedTokenKeys = Arc<TokenKeys>;
+
+/// Load the keys the config names. Fails with `Error::InvalidTokenKey` if
+/// one of them cannot be read.
+pub fn from_config(config: &Config) -> Result<SharedTokenKeys, Error> {
+    Ok(Arc::new(TokenKeys::new(
+        &config.paseto_key_id,
+        &config.paseto_key,
+        &config.paseto_verification_keys,
+    )?))
+}
+
+/// Read a key given as a PASERK (`k2.local.`, `k4.secret.` or
+/// `k4.public.`), or as the 32 characters `v2.local` keys used to be
+fn parse_key(key: &str) -> Result<Key, Error> {
+    let decode = |data: &str, len: usize| {
+        URL_SAFE_NO_PAD
+            .decode(data)
+            .ok()
+            .filter(|bytes| bytes.len() == len)
+            .ok_or_else(|| Error::InvalidTokenKey(format!("expected {} base64url bytes", len)))
+    };
+
+    if let Some(data) = key.strip_prefix("k2.local.") {
+        Ok(Key::Local(decode(data, 32)?))
+    } else if let Some(data) = key.strip_prefix("k4.secret.") {
+        let bytes = decode(data, 64)?;
+        Ed25519KeyPair::from_seed_and_public_key(&bytes[..32], &bytes[32..])
+            .map(Key::Secret)
+            .map_err(|err| Error::InvalidTokenKey(err.to_string()))
+    } else if let Some(data) = key.strip_prefix("k4.public.") {
+        Ok(Key::Public(decode(data, 32)?))
+    } else if key.len() == 32 {
+        Ok(Key::Local(key.as_bytes().to_vec()))
+    } else {
+        Err(Error::InvalidTokenKey(
+            "expected a k2.local, k4.secret or k4.public PASERK, or 32 characters".to_string(),
+        ))
+    }
+}
+
+impl TokenKeys {
+    /// Issue tokens with `key`, naming it `kid`, and also accept the ones
+    /// issued with `verification_keys`, each given as `<kid>=<key>`.
+    /// `key` has to be one that can sign, not a `k4.public` one.
+    pub fn new(kid: &str, key: &str, verification_keys: &[String]) -> Result<Self, Error> {
+        let mut keys = HashMap::new();
+        for entry in verification_keys {
+            let (kid, key) = entry.split_once('=').ok_or_else(|| {
+                Error::InvalidTokenKey(format!("`{}` is not in the form <kid>=<key>", entry))
+            })?;
+            keys.insert(kid.trim().to_string(), parse_key(key.trim())?);
+        }
+        let key = parse_key(key)?;
+        if let Key::Public(_) = key {
+            return Err(Error::InvalidTokenKey(
+                "tokens cannot be issued with a k4.public key".to_string(),
+            ));
+        }
+        keys.insert(kid.to_string(), key);
+
+        Ok(TokenKeys {
+            kid: kid.to_string(),
+            keys,
+        })
+    }
+
+    /// Keys made up on the spot, for a service whose tokens need not
+    /// outlive it
+    pub fn generate() -> Self {
+        let pkcs8 =
+            Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).expect("Cannot generate key pair");
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).expect("Invalid key pair");
+
+        TokenKeys {
+            kid: "generated".to_string(),
+            keys: HashMap::from([("generated".to_string(), Key::Secret(key_pair))]),
+        }
+    }
+
+    /// Turn `claims` into a token with the current key
+    pub fn issue(&self, claims: &Value) -> String {
+        let message = claims.to_string();
+        let footer = serde_json::to_string(&Footer {
+            kid: self.kid.clone(),
+        })
+        .expect("Cannot serialize footer");
+
+        match &self.keys[&self.kid] {
+            Key::Local(key) => paseto::v2::local_paseto(&message, Some(&footer), key)
+                .expect("Failed to construct paseto token"),
+            Key::Secret(key_pair) => {
+                let signature = key_pair.sign(&pae(&[
+                    PUBLIC_HEADER.as_bytes(),
+                    message.as_bytes(),
+                    footer.as_bytes(),
+                    b"",
+                ]));
+                let body = [message.as_bytes(), signature.as_ref()].concat();
+                format!(
+                    "{}{}.{}",
+                    PUBLIC_HEADER,
+                    URL_SAFE_NO_PAD.encode(body),
+                    URL_SAFE_NO_PAD.encode(footer)
+                )
+            }
+            Key::Public(_) => unreachable!("tokens are issued with a key that can sign"),
+        }
+    }
+
+    /// The claims of a token issued with one of the keys, if it has not
+    /// expired. Fails with `Error::CannotDecryptToken` otherwise.
+    pub fn verify(&self, token: &str) -> Result<Value, Error> {
+        let footer = match token.splitn(4, '.').nth(3) {
+            Some(footer) => Some(
+                URL_SAFE_NO_PAD
+                    .decode(footer)
+                    .ok()
+                    .and_then(|footer| String::from_utf8(footer).ok())
+                    .ok_or(Error::CannotDecryptToken)?,
+            ),
+            None => None,
+        };
+        // Tokens from before keys had ids are from the current key
+        let kid = match &footer {
+            Some(footer) => {
+                serde_json::from_str::<Footer>(footer)
+                    .map_err(|_| Error::CannotDecryptToken)?
+                    .kid
+            }
+            None => self.kid.clone(),
+        };
+
+        // The key decides the kind of token, not what the token claims to be
+        let message = match (self.keys.get(&kid), footer.as_deref()) {
+            (Some(Key::Local(key)), footer) => decrypt_local(token, footer, key),
+            (Some(Key::Secret(key_pair)), Some(footer)) => {
+                verify_public(token, footer, key_pair.public_key())
+            }
+            (Some(Key::Public(key)), Some(footer)) => verify_public(token, footer, key),
+            _ => None,
+        }
+        .ok_or(Error::CannotDecryptToken)?;
+
+        validate_potential_json_blob(&message, &TimeBackend::Chrono)
+            .map_err(|_| Error::CannotDecryptToken)
+    }
+
+    /// The keys `v4.public` tokens can be checked with
+    pub fn public_keys(&self) -> Vec<PublicKey> {
+        let mut public_keys: Vec<PublicKey> = self
+            .keys
+            .iter()
+            .filter_map(|(kid, key)| {
+                let public_key = match key {
+                    Key::Secret(key_pair) => key_pair.public_key().as_ref(),
+                    Key::Public(public_key) => public_key.as_slice(),
+                    Key::Local(_) => return None,
+                };
+                Some(PublicKey {
+                    kid: kid.clone(),
+                    key: format!("k4.public.{}", URL_SAFE_NO_PAD.encode(public_key)),
+                })
+            })
+            .collect();
+        public_keys.sort_by(|a, b| a.kid.cmp(&b.kid));
+        public_keys
+    }
+}
+
+/// The message of a `v2.local` token, if it was encrypted with `key`
+fn decrypt_local(token: &str, footer: Option<&str>, key: &[u8]) -> Option<String> {
+    // The paseto crate panics on bodies too short for a nonce and a tag
+    let body = token.strip_prefix(LOCAL_HEADER)?.split('.').next()?;
+    if URL_SAFE_NO_PAD.decode(body).ok()?.len() < LOCAL_NONCE_LEN + LOCAL_TAG_LEN {
+        return None;
+    }
+    paseto::v2::decrypt_paseto(token, footer, key).ok()
+}
+
+/// The message of a `v4.public` token, if `public_key` signed it
+fn verify_public(token: &str, footer: &str, public_key: impl AsRef<[u8]>) -> Option<String> {
+    let body = token.strip_prefix(PUBLIC_HEADER)?.split('.').next()?;
+    let body = URL_SAFE_NO_PAD.decode(body).ok()?;
+    let split = body.len().checked_sub(SIGNATURE_LEN)?;
+    let (message, signature) = body.split_at(split);
+
+    UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(
+            &pae(&[PUBLIC_HEADER.as_bytes(), message, footer.as_bytes(), b""]),
+            signature,
+        )
+        .ok()?;
+    String::from_utf8(message.to_vec()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn claims() -> Value {
+        json!({ "account_id": 3, "exp": chrono::Utc::now() + chrono::Duration::minutes(1) })
+    }
+
+    #[test]
+    fn rotated_keys_keep_checking_their_tokens() {
+        const LOCAL: &str = "RANDOM WORDS WINTER MACINTOSH PC";
+        let secret = format!("k4.secret.{}", URL_SAFE_NO_PAD.encode([7; 64]));
+        assert!(parse_key(&secret).is_err());
+
+        let old = TokenKeys::new("1", LOCAL, &[]).unwrap();
+        let local_token = old.issue(&claims());
+        assert!(local_token.starts_with(LOCAL_HEADER));
+        assert_eq!(json!(3), old.verify(&local_token).unwrap()["account_id"]);
+        let legacy = paseto::v2::local_paseto(&claims().to_string(), None, LOCAL.as_bytes());
+        assert!(old.verify(&legacy.unwrap()).is_ok());
+        let footer = URL_SAFE_NO_PAD.encode(r#"{"kid":"1"}"#);
+        assert!(old
+            .verify(&format!("{}AAAA.{}", LOCAL_HEADER, footer))
+            .is_err());
+
+        let generated = TokenKeys::generate();
+        let public_token = generated.issue(&claims());
+        assert!(public_token.starts_with(PUBLIC_HEADER));
+        let [public_key] = generated.public_keys().try_into().unwrap();
+
+        // A public key checks tokens but cannot issue them
+        assert!(matches!(
+            TokenKeys::new("3", &public_key.key, &[]),
+            Err(Error::InvalidTokenKey(_))
+        ));
+
+        // The new key signs, the ones before it still check their tokens
+        let retired = format!("generated={}", public_key.key);
+        let current = format!("k2.local.{}", URL_SAFE_NO_PAD.encode([1; 32]));
+        let new = TokenKeys::new("2", &current, &[format!("1={}", LOCAL), retired]).unwrap();
+        assert!(new.verify(&local_token).is_ok());
+        assert!(new.verify(&public_token).is_ok());
+        assert!(new.verify(&new.issue(&claims())).is_ok());
+        assert_eq!(vec![public_key], new.public_keys());
+
+        // Once a key is gone, so are its tokens
+        assert!(matches!(
+            TokenKeys::new("2", LOCAL, &[])
+                .unwrap()
+                .verify(&local_token),
+            Err(Error::CannotDecryptToken)
+        ));
+        let (header, body) = public_token.split_at(PUBLIC_HEADER.len());
+        let flipped = if body.starts_with('A') { 'B' } else { 'A' };
+        let tampered = format!("{}{}{}", header, flipped, &body[1..]);
+        assert!(generated.verify(&tampered).is_err());
+
+        let expired = json!({ "exp": chrono::Utc::now() - chrono::Duration::minutes(1) });
+        assert!(generated.verify(&generated.issue(&expired)).is_err());
+    }
+}
@@ -1,6 +1,12 @@
 use chrono::prelude::*;
+use handle_errors::Error;
 use serde::{Deserialize, Serialize};
 
+/// Shortest password accepted when changing or resetting one
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+/// Longest email address that can be delivered to, per RFC 5321
+pub const EMAIL_MAX_LENGTH: usize = 254;
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Account {
     pub id: Option<AccountId>,
@@ -15,4 +21,317 @@ pub struct AccountId(pub i32);
 pub struct Session {
     pub exp: DateTime<Utc>,
     pub account_id: AccountId,
+    /// The role the account had when the token was issued. Tokens
+    /// issued before roles were part of them count as `Role::User`.
+    #[serde(default)]
+    pub role: Role,
+    /// Identifies the token on the deny-list once it is revoked.
+    /// Tokens issued before logout existed have none.
+    #[serde(default)]
+    pub jti: Option<String>,
+    /// Whether the login the token goes back to passed a second factor
+    #[serde(default)]
+    pub two_factor: bool,
+    /// What the session is limited to, if it comes from an API key or
+    /// a token issued with a `scope`. Other sessions may do all the
+    /// account may.
+    #[serde(default)]
+    pub scopes: Option<Vec<Scope>>,
+}
+
+impl Session {
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.scopes
+            .as_ref()
+            .is_none_or(|scopes| scopes.contains(&scope))
+    }
+}
+
+/// Parts of the API a session can be limited to
+#[derive(Debug, Deserialize, Serialize, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+    /// Managing the account itself, like its password and API keys
+    #[serde(rename = "account")]
+    Account,
+    #[serde(rename = "questions:write")]
+    QuestionsWrite,
+    #[serde(rename = "answers:write")]
+    AnswersWrite,
+    #[serde(rename = "comments:write")]
+    CommentsWrite,
+    #[serde(rename = "votes:write")]
+    VotesWrite,
+    #[serde(rename = "tags:write")]
+    TagsWrite,
+    /// Moderating and administering, as far as the account's role allows
+    #[serde(rename = "admin")]
+    Admin,
+}
+
+impl Scope {
+    /// Unknown values are left out, so a bad row never grants access
+    pub fn from_db(value: &str) -> Option<Self> {
+        match value {
+            "account" => Some(Scope::Account),
+            "questions:write" => Some(Scope::QuestionsWrite),
+            "answers:write" => Some(Scope::AnswersWrite),
+            "comments:write" => Some(Scope::CommentsWrite),
+            "votes:write" => Some(Scope::VotesWrite),
+            "tags:write" => Some(Scope::TagsWrite),
+            "admin" => Some(Scope::Admin),
+            _ => None,
+        }
+    }
+
+    pub fn as_db(&self) -> &'static str {
+        match self {
+            Scope::Account => "account",
+            Scope::QuestionsWrite => "questions:write",
+            Scope::AnswersWrite => "answers:write",
+            Scope::CommentsWrite => "comments:write",
+            Scope::VotesWrite => "votes:write",
+            Scope::TagsWrite => "tags:write",
+            Scope::Admin => "admin",
+        }
+    }
+
+    /// Parse a space-separated `scope` parameter the way OAuth 2.0 has
+    /// them, sorted and without duplicates. Fails with
+    /// `Error::InvalidContent` for unknown or no scopes.
+    pub fn parse_list(value: &str) -> Result<Vec<Self>, Error> {
+        let mut scopes = value
+            .split_whitespace()
+            .map(|scope| {
+                Scope::from_db(scope)
+                    .ok_or_else(|| Error::InvalidContent(format!("unknown scope `{}`", scope)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        scopes.sort();
+        scopes.dedup();
+        if scopes.is_empty() {
+            return Err(Error::InvalidContent("scope is empty".to_string()));
+        }
+
+        Ok(scopes)
+    }
+
+    /// The inverse of `parse_list`
+    pub fn format_list(scopes: &[Self]) -> String {
+        scopes
+            .iter()
+            .map(Scope::as_db)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// What an account is allowed to do beyond managing its own posts.
+/// Roles are declared from least to most privileged, so a role
+/// compares greater than the roles it includes.
+#[derive(Debug, Default, Deserialize, Serialize, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    User,
+    Moderator,
+    Admin,
+}
+
+impl Role {
+    /// Unknown values fall back to the least privileged role
+    pub fn from_db(value: &str) -> Self {
+        match value {
+            "moderator" => Role::Moderator,
+            "admin" => Role::Admin,
+            _ => Role::User,
+        }
+    }
+
+    pub fn as_db(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountStatus {
+    Active,
+    Suspended,
+}
+
+impl AccountStatus {
+    /// Unknown values are treated as suspended so a bad row never grants access
+    pub fn from_db(value: &str) -> Self {
+        match value {
+            "active" => AccountStatus::Active,
+            _ => AccountStatus::Suspended,
+        }
+    }
+
+    pub fn as_db(&self) -> &'static str {
+        match self {
+            AccountStatus::Active => "active",
+            AccountStatus::Suspended => "suspended",
+        }
+    }
+}
+
+/// Body of `PUT /accounts/{id}/role`
+#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+pub struct RoleUpdate {
+    pub role: Role,
+}
+
+/// Body of `PUT /accounts/{id}/status`
+#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+pub struct StatusUpdate {
+    pub status: AccountStatus,
+}
+
+/// Body of `PUT /account/password`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct PasswordChange {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+/// Body of `POST /password-reset`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct PasswordResetRequest {
+    pub email: String,
+}
+
+/// Body of `POST /password-reset/confirm`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct PasswordResetConfirm {
+    pub token: String,
+    pub new_password: String,
+}
+
+/// Body of `POST /email-verification/confirm`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct EmailVerification {
+    pub token: String,
+}
+
+/// Trim and lowercase an email address so that it is registered and
+/// looked up the same way however it was typed. Fails with
+/// `Error::InvalidContent` unless it looks like `local@example.com`.
+pub fn normalize_email(raw: &str) -> Result<String, Error> {
+    let email = raw.trim().to_lowercase();
+    let invalid = || Error::InvalidContent(format!("`{}` is not a valid email address", raw));
+
+    if email.is_empty() || email.chars().count() > EMAIL_MAX_LENGTH {
+        return Err(invalid());
+    }
+
+    let (local, domain) = email.split_once('@').ok_or_else(invalid)?;
+    if local.is_empty()
+        || local
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || matches!(c, '@' | '<' | '>'))
+    {
+        return Err(invalid());
+    }
+
+    // At least two labels of letters, digits and dashes, like `example.com`
+    let labels: Vec<&str> = domain.split('.').collect();
+    if labels.len() < 2
+        || labels.iter().any(|label| {
+            label.is_empty()
+                || label.starts_with('-')
+                || label.ends_with('-')
+                || !label.chars().all(|c| c.is_alphanumeric() || c == '-')
+        })
+    {
+        return Err(invalid());
+    }
+
+    Ok(email)
+}
+
+/// Fails with `Error::InvalidContent` if a new password is shorter
+/// than `MIN_PASSWORD_LENGTH` characters
+pub fn validate_password(password: &str) -> Result<(), Error> {
+    let length = password.chars().count();
+    if length < MIN_PASSWORD_LENGTH {
+        return Err(Error::InvalidContent(format!(
+            "passwords must be at least {} characters, got {}",
+            MIN_PASSWORD_LENGTH, length
+        )));
+    }
+
+    Ok(())
+}
+
+/// The account behind a request, as seen by the authorization policy
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Actor {
+    pub account_id: AccountId,
+    pub role: Role,
+    pub status: AccountStatus,
+    /// Whether the request was made with a login that passed a second
+    /// factor. Stores know nothing of requests and leave it `false`.
+    #[serde(skip)]
+    pub two_factor: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_short_passwords() {
+        assert!(validate_password("correct horse").is_ok());
+        assert!(validate_password(&"x".repeat(MIN_PASSWORD_LENGTH)).is_ok());
+
+        let err = validate_password("short").unwrap_err();
+        assert!(matches!(err, Error::InvalidContent(_)));
+    }
+
+    #[test]
+    fn parses_scope_lists() {
+        let scopes = Scope::parse_list(" votes:write questions:write  votes:write").unwrap();
+        assert_eq!(vec![Scope::QuestionsWrite, Scope::VotesWrite], scopes);
+        assert_eq!("questions:write votes:write", Scope::format_list(&scopes));
+
+        assert!(Scope::parse_list("questions:write questions:read").is_err());
+        assert!(Scope::parse_list("  ").is_err());
+    }
+
+    #[test]
+    fn normalizes_emails() {
+        assert_eq!(
+            "jane.doe+qa@example.com",
+            normalize_email("  Jane.Doe+QA@Example.COM ").unwrap()
+        );
+        assert_eq!(
+            "a@mail.example.co.uk",
+            normalize_email("a@mail.example.co.uk").unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_emails() {
+        let too_long = format!("{}@example.com", "x".repeat(EMAIL_MAX_LENGTH));
+        for raw in [
+            "",
+            "plainaddress",
+            "@example.com",
+            "a@b@example.com",
+            "a b@example.com",
+            "a@localhost",
+            "a@example..com",
+            "a@-example.com",
+            "a@exa_mple.com",
+            &too_long,
+        ] {
+            let err = normalize_email(raw).unwrap_err();
+            assert!(matches!(err, Error::InvalidContent(_)), "{:?}", raw);
+        }
+    }
 }
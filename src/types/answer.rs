@@ -1,5 +1,6 @@
-use crate::types::QuestionId;
+use crate::types::{AccountId, QuestionId};
 
+use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Hash)]
@@ -10,10 +11,13 @@ pub struct Answer {
     pub id: AnswerId,
     pub content: String,
     pub question_id: QuestionId,
+    pub account_id: AccountId,
+    pub created_on: NaiveDateTime,
+    /// Sum of the up and down votes
+    pub score: i32,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct NewAnswer {
     pub content: String,
-    pub question_id: QuestionId,
 }
@@ -0,0 +1,108 @@
+use chrono::NaiveDateTime;
+use handle_errors::Error;
+use serde::{Deserialize, Serialize};
+
+use crate::types::{AccountId, Scope};
+
+/// Longest name an API key can be given
+pub const API_KEY_NAME_MAX_LENGTH: usize = 100;
+
+#[derive(Debug, Deserialize, Serialize, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ApiKeyId(pub i32);
+
+/// An API key as it is listed, without the key itself, which is only
+/// stored by its hash
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct ApiKey {
+    pub id: ApiKeyId,
+    #[serde(skip)]
+    pub account_id: AccountId,
+    pub name: String,
+    /// The start of the key, to tell keys apart by
+    pub prefix: String,
+    pub scopes: Vec<Scope>,
+    pub created_on: NaiveDateTime,
+    pub last_used_on: Option<NaiveDateTime>,
+}
+
+/// Body of `POST /account/api-keys`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct NewApiKey {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+}
+
+/// Answer to `POST /account/api-keys`, the only time the key is shown
+#[derive(Debug, Serialize, Clone)]
+pub struct CreatedApiKey {
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+    pub key: String,
+}
+
+impl NewApiKey {
+    /// Trim the name and sort out duplicate scopes. Fails with
+    /// `Error::InvalidContent` for an empty or overly long name, for no
+    /// scopes, and for the `account` and `admin` scopes, as an API key
+    /// must not be able to mint more of them, change the password or
+    /// stand in for a moderator.
+    pub fn validate(self) -> Result<Self, Error> {
+        let name = self.name.trim().to_string();
+        if name.is_empty() || name.chars().count() > API_KEY_NAME_MAX_LENGTH {
+            return Err(Error::InvalidContent(format!(
+                "API key names must be 1 to {} characters long",
+                API_KEY_NAME_MAX_LENGTH
+            )));
+        }
+
+        let mut scopes = self.scopes;
+        scopes.sort();
+        scopes.dedup();
+        if scopes.is_empty() {
+            return Err(Error::InvalidContent(
+                "API keys need at least one scope".to_string(),
+            ));
+        }
+        if let Some(scope) = scopes
+            .iter()
+            .find(|scope| matches!(scope, Scope::Account | Scope::Admin))
+        {
+            return Err(Error::InvalidContent(format!(
+                "API keys cannot have the `{}` scope",
+                scope.as_db()
+            )));
+        }
+
+        Ok(NewApiKey { name, scopes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_names_and_scopes() {
+        let key = NewApiKey {
+            name: " ci bot ".to_string(),
+            scopes: vec![Scope::VotesWrite, Scope::QuestionsWrite, Scope::VotesWrite],
+        }
+        .validate()
+        .unwrap();
+        assert_eq!("ci bot", key.name);
+        assert_eq!(vec![Scope::QuestionsWrite, Scope::VotesWrite], key.scopes);
+
+        for (name, scopes) in [
+            ("  ", vec![Scope::VotesWrite]),
+            ("ci bot", vec![]),
+            ("ci bot", vec![Scope::Account]),
+            ("ci bot", vec![Scope::VotesWrite, Scope::Admin]),
+        ] {
+            let key = NewApiKey {
+                name: name.to_string(),
+                scopes,
+            };
+            assert!(key.validate().is_err());
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use crate::types::{AccountId, AnswerId, QuestionId};
+
+use chrono::NaiveDateTime;
+use handle_errors::Error;
+use serde::{Deserialize, Serialize};
+
+/// Comments are meant for short clarifications, longer
+/// replies belong in an answer
+pub const COMMENT_MIN_LENGTH: usize = 2;
+pub const COMMENT_MAX_LENGTH: usize = 600;
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Hash)]
+pub struct CommentId(pub i32);
+
+/// A comment belongs to either a question or an answer,
+/// the other id is `null`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Comment {
+    pub id: CommentId,
+    pub content: String,
+    pub question_id: Option<QuestionId>,
+    pub answer_id: Option<AnswerId>,
+    pub account_id: AccountId,
+    pub created_on: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct NewComment {
+    pub content: String,
+}
+
+impl NewComment {
+    /// Fails with `Error::InvalidContent` unless the trimmed content is
+    /// between `COMMENT_MIN_LENGTH` and `COMMENT_MAX_LENGTH` characters
+    pub fn validate(&self) -> Result<(), Error> {
+        let length = self.content.trim().chars().count();
+        if (COMMENT_MIN_LENGTH..=COMMENT_MAX_LENGTH).contains(&length) {
+            Ok(())
+        } else {
+            Err(Error::InvalidContent(format!(
+                "comments must be between {} and {} characters, got {}",
+                COMMENT_MIN_LENGTH, COMMENT_MAX_LENGTH, length
+            )))
+        }
+    }
+}
+
+/// The question or answer a comment is posted on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommentTarget {
+    Question(i32),
+    Answer(i32),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comment(content: &str) -> NewComment {
+        NewComment {
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn comment_length_limits() {
+        assert!(comment("Which version?").validate().is_ok());
+        assert!(comment(&"é".repeat(COMMENT_MAX_LENGTH)).validate().is_ok());
+
+        let err = comment("  x ").validate().unwrap_err();
+        assert!(matches!(err, Error::InvalidContent(_)));
+        let err = comment(&"x".repeat(COMMENT_MAX_LENGTH + 1))
+            .validate()
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidContent(_)));
+    }
+}
@@ -0,0 +1,146 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::types::Question;
+
+/// Strong entity tag of a question, quoted the way it is sent in the
+/// `ETag` header. It starts with the question's version, which only
+/// edits move, and may go on with a hash of the representation it was
+/// sent with, which changes with anything in it, like votes and answers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ETag {
+    tag: String,
+    /// `q{id}-v{version}`, without the quotes
+    version: String,
+}
+
+impl ETag {
+    /// The tag of the question's version alone
+    pub fn of(question: &Question) -> Self {
+        let version = format!("q{}-v{}", question.id.0, question.version);
+        ETag {
+            tag: format!("\"{}\"", version),
+            version,
+        }
+    }
+
+    /// The tag of `representation`, a reply showing `question`
+    pub fn of_representation<T: Serialize>(question: &Question, representation: &T) -> Self {
+        let body = serde_json::to_vec(representation).expect("representation is serializable");
+        let digest = Sha256::digest(&body);
+        let hex: String = digest[..8]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+
+        let ETag { version, .. } = ETag::of(question);
+        ETag {
+            tag: format!("\"{}-{}\"", version, hex),
+            version,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.tag
+    }
+
+    /// Whether an `If-Match` header allows changing the resource. Only
+    /// the version a tag starts with counts, so votes and new answers
+    /// don't turn it stale. Uses the strong comparison, so weak tags
+    /// never match.
+    pub fn satisfies_if_match(&self, header: &str) -> bool {
+        entity_tags(header).any(|tag| {
+            tag == "*"
+                || tag
+                    .strip_prefix('"')
+                    .and_then(|tag| tag.strip_suffix('"'))
+                    .is_some_and(|tag| {
+                        tag.strip_prefix(self.version.as_str())
+                            .is_some_and(|rest| rest.is_empty() || rest.starts_with('-'))
+                    })
+        })
+    }
+
+    /// Whether an `If-None-Match` header already has this representation.
+    /// Uses the weak comparison, as RFC 9110 asks for.
+    pub fn satisfies_if_none_match(&self, header: &str) -> bool {
+        entity_tags(header).any(|tag| tag == "*" || tag.trim_start_matches("W/") == self.tag)
+    }
+}
+
+fn entity_tags(header: &str) -> impl Iterator<Item = &str> {
+    header
+        .split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{AccountId, QuestionId};
+
+    fn question(version: i32) -> Question {
+        Question {
+            id: QuestionId(3),
+            title: "title".to_string(),
+            content: "content".to_string(),
+            tags: None,
+            account_id: AccountId(1),
+            created_on: Default::default(),
+            score: 0,
+            accepted_answer_id: None,
+            version,
+            deleted_at: None,
+            locked: false,
+        }
+    }
+
+    #[test]
+    fn tags_follow_the_version() {
+        let tag = ETag::of(&question(2));
+        assert_eq!("\"q3-v2\"", tag.as_str());
+
+        let mut voted = question(2);
+        voted.score = 5;
+        assert_eq!(tag, ETag::of(&voted));
+        assert_ne!(tag, ETag::of(&question(3)));
+    }
+
+    #[test]
+    fn representation_tags_change_with_the_representation() {
+        let tag = ETag::of_representation(&question(2), &question(2));
+        assert!(tag.as_str().starts_with("\"q3-v2-"));
+
+        let mut voted = question(2);
+        voted.score = 5;
+        let voted_tag = ETag::of_representation(&voted, &voted);
+        assert_ne!(tag, voted_tag);
+        assert!(!voted_tag.satisfies_if_none_match(tag.as_str()));
+
+        // Either tag allows changing the version they start with
+        assert!(ETag::of(&voted).satisfies_if_match(tag.as_str()));
+        assert!(ETag::of(&voted).satisfies_if_match(voted_tag.as_str()));
+        assert!(!ETag::of(&question(3)).satisfies_if_match(tag.as_str()));
+        assert!(!ETag::of(&question(20)).satisfies_if_match(tag.as_str()));
+    }
+
+    #[test]
+    fn if_match_uses_strong_comparison() {
+        let tag = ETag::of(&question(1));
+        let weak = format!("W/{}", tag.as_str());
+
+        assert!(tag.satisfies_if_match(&format!("\"other\", {}", tag.as_str())));
+        assert!(tag.satisfies_if_match("*"));
+        assert!(!tag.satisfies_if_match(&weak));
+        assert!(!tag.satisfies_if_match("\"other\""));
+    }
+
+    #[test]
+    fn if_none_match_uses_weak_comparison() {
+        let tag = ETag::of(&question(1));
+
+        assert!(tag.satisfies_if_none_match(&format!("W/{}", tag.as_str())));
+        assert!(!tag.satisfies_if_none_match("\"other\""));
+    }
+}
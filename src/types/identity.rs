@@ -0,0 +1,24 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+/// A login through the OpenID Connect provider that is in flight,
+/// stored by the hash of the `state` it was sent off with. The nonce
+/// and PKCE code verifier are what the callback checks the provider's
+/// answer against.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OidcLogin {
+    pub state_hash: String,
+    pub nonce: String,
+    pub code_verifier: String,
+    pub expires_on: NaiveDateTime,
+}
+
+/// Query of `GET /oidc/callback`, carrying either a code or the error
+/// the provider turned the login down with
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct OidcCallback {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
@@ -0,0 +1,95 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+/// Lockouts start at this long and double with every further failure
+const BASE_LOCKOUT_SECONDS: i64 = 30;
+const MAX_LOCKOUT_SECONDS: i64 = 60 * 60;
+
+/// Failures older than this are forgotten rather than added to
+pub const FAILURE_WINDOW_HOURS: i64 = 24;
+
+/// How many logins may fail for one email address before it is locked out
+pub const ACCOUNT_FREE_FAILURES: i32 = 5;
+/// How many logins may fail from one IP, which may be shared by many
+/// people, before it is locked out
+pub const IP_FREE_FAILURES: i32 = 20;
+
+/// Body of `POST /login`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Login {
+    pub email: String,
+    pub password: String,
+    /// Space-separated scopes to limit the tokens to, for handing them
+    /// to something that should not do all the account may
+    #[serde(default)]
+    pub scope: Option<String>,
+}
+
+/// Failed logins for one email address or IP since its last success
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoginFailures {
+    pub failures: i32,
+    pub last_failure: NaiveDateTime,
+}
+
+impl LoginFailures {
+    /// When the next login may be tried. The first `free_failures` cost
+    /// nothing, after that each failure locks out for twice as long as
+    /// the one before, up to an hour.
+    pub fn locked_until(&self, free_failures: i32) -> Option<NaiveDateTime> {
+        let over = self.failures - free_failures;
+        if over <= 0 {
+            return None;
+        }
+
+        // Capping the shift first keeps it from overflowing
+        let seconds = (BASE_LOCKOUT_SECONDS << (over - 1).min(32)).min(MAX_LOCKOUT_SECONDS);
+        Some(self.last_failure + chrono::Duration::seconds(seconds))
+    }
+}
+
+/// Key failures for an email address are counted under, whether or not
+/// an account uses it
+pub fn account_key(email: &str) -> String {
+    format!("email:{}", email)
+}
+
+pub fn ip_key(ip: std::net::IpAddr) -> String {
+    format!("ip:{}", ip)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failures(failures: i32) -> LoginFailures {
+        LoginFailures {
+            failures,
+            last_failure: NaiveDateTime::default(),
+        }
+    }
+
+    fn lockout(failures_: i32) -> Option<i64> {
+        failures(failures_)
+            .locked_until(ACCOUNT_FREE_FAILURES)
+            .map(|until| (until - NaiveDateTime::default()).num_seconds())
+    }
+
+    #[test]
+    fn backs_off_exponentially() {
+        assert_eq!(None, lockout(0));
+        assert_eq!(None, lockout(ACCOUNT_FREE_FAILURES));
+        assert_eq!(Some(30), lockout(ACCOUNT_FREE_FAILURES + 1));
+        assert_eq!(Some(60), lockout(ACCOUNT_FREE_FAILURES + 2));
+        assert_eq!(Some(120), lockout(ACCOUNT_FREE_FAILURES + 3));
+    }
+
+    #[test]
+    fn caps_lockouts_at_an_hour() {
+        assert_eq!(
+            Some(MAX_LOCKOUT_SECONDS),
+            lockout(ACCOUNT_FREE_FAILURES + 8)
+        );
+        assert_eq!(Some(MAX_LOCKOUT_SECONDS), lockout(i32::MAX));
+    }
+}
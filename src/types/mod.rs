@@ -1,9 +1,47 @@
 mod account;
 mod answer;
+mod api_key;
+mod comment;
+mod etag;
+mod identity;
+mod login;
 mod pagination;
+mod profile;
+mod query;
 mod question;
+mod revision;
+mod search;
+mod tag;
+mod token;
+mod two_factor;
+mod vote;
 
-pub use account::{Account, AccountId, Session};
+pub use account::{
+    normalize_email, validate_password, Account, AccountId, AccountStatus, Actor,
+    EmailVerification, PasswordChange, PasswordResetConfirm, PasswordResetRequest, Role,
+    RoleUpdate, Scope, Session, StatusUpdate,
+};
 pub use answer::{Answer, AnswerId, NewAnswer};
-pub use pagination::{extract_pagination, Pagination};
-pub use question::{NewQuestion, Question, QuestionId};
+pub use api_key::{ApiKey, ApiKeyId, CreatedApiKey, NewApiKey};
+pub use comment::{Comment, CommentId, CommentTarget, NewComment};
+pub use etag::ETag;
+pub use identity::{OidcCallback, OidcLogin};
+pub use login::{
+    account_key, ip_key, Login, LoginFailures, ACCOUNT_FREE_FAILURES, FAILURE_WINDOW_HOURS,
+    IP_FREE_FAILURES,
+};
+pub use pagination::{extract_pagination, Cursor, CursorKey, Page, PageCursor, Pagination};
+pub use profile::{Profile, ProfileUpdate, UserPage, USER_PAGE_POST_LIMIT};
+pub use query::{extract_question_query, QuestionQuery, QuestionSort};
+pub use question::{NewQuestion, Question, QuestionId, QuestionWithAnswers};
+pub use revision::{extract_diff_range, QuestionRevision, RevisionDiff};
+pub use search::{AnswerSnippet, QuestionSearchResult};
+pub use tag::{
+    extract_tag_query, normalize_tag, normalize_tags, NewSynonym, Tag, TagQuery, TagUpdate,
+};
+pub use token::{AccountToken, RefreshRequest, RefreshToken, TokenPair};
+pub use two_factor::{
+    LoginChallenge, RecoveryCodes, TwoFactor, TwoFactorCode, TwoFactorEnrollment, TwoFactorLogin,
+    RECOVERY_CODE_COUNT,
+};
+pub use vote::{NewVote, Vote, VoteSummary};
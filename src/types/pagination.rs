@@ -1,4 +1,8 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::NaiveDateTime;
 use handle_errors::Error;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 /// Pagination struct that is getting extracted
@@ -16,17 +20,6 @@ pub struct Pagination {
 /// GET requests to this route can have a pagination attached so we just
 /// return the quetions we need
 /// `/questions?limit=1&offset=10`
-/// # Example usage
-/// ```rust
-/// use std::collections::HashMap;
-///
-/// let mut query = HashMap::new();
-/// query.insert("limit".to_string(), "1".to_string());
-/// query.insert("offset".to_string(), "10".to_string());
-/// let p = types::extract_pagination(query).unwrap();
-/// assert_eq(Some(1), p.limit);
-/// assert_eq(10, p.offset);
-/// ```
 pub fn extract_pagination(params: &HashMap<String, String>) -> Result<Pagination, Error> {
     match (params.get("limit"), params.get("offset")) {
         (Some(limit), Some(offset)) => Ok(Pagination {
@@ -43,6 +36,100 @@ pub fn extract_pagination(params: &HashMap<String, String>) -> Result<Pagination
     }
 }
 
+/// Position of a row in a sorted list, handed out to clients as an
+/// opaque token so they can continue right after (or before) it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cursor {
+    #[serde(rename = "k")]
+    pub key: CursorKey,
+    #[serde(rename = "i")]
+    pub id: i32,
+}
+
+/// Value of the sort key of the row a cursor points at
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CursorKey {
+    Created(NaiveDateTime),
+    Activity(NaiveDateTime),
+    Rank(f32),
+    Score(i32),
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).expect("cursor is always serializable"))
+    }
+
+    pub fn decode(token: &str) -> Option<Self> {
+        let bytes = URL_SAFE_NO_PAD.decode(token).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+}
+
+/// Which side of a cursor the requested page lies on
+#[derive(Debug, Clone, PartialEq)]
+pub enum PageCursor {
+    After(Cursor),
+    Before(Cursor),
+}
+
+/// One page of a list, with the cursors for the pages next to it
+#[derive(Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next: Option<Cursor>,
+    pub prev: Option<Cursor>,
+    /// Number of items across all pages, if it was asked for
+    pub total: Option<i64>,
+}
+
+impl<T> Page<T> {
+    /// Build a page from rows fetched with one extra row beyond the limit,
+    /// so we know whether there is more to come. When paging backwards the
+    /// rows come in reverse order, nearest to the cursor first.
+    pub fn from_rows(
+        mut rows: Vec<(T, Cursor)>,
+        pagination: &Pagination,
+        cursor: Option<&PageCursor>,
+        total: Option<i64>,
+    ) -> Self {
+        let has_more = pagination
+            .limit
+            .is_some_and(|limit| rows.len() > limit.max(0) as usize);
+        if has_more {
+            rows.truncate(pagination.limit.unwrap_or_default().max(0) as usize);
+        }
+
+        let backwards = matches!(cursor, Some(PageCursor::Before(_)));
+        if backwards {
+            rows.reverse();
+        }
+
+        let (has_next, has_prev) = if backwards {
+            (true, has_more)
+        } else {
+            (has_more, cursor.is_some() || pagination.offset > 0)
+        };
+
+        let next = rows.last().filter(|_| has_next).map(|(_, c)| c.clone());
+        let prev = rows.first().filter(|_| has_prev).map(|(_, c)| c.clone());
+
+        Self {
+            items: rows.into_iter().map(|(item, _)| item).collect(),
+            next,
+            prev,
+            total,
+        }
+    }
+}
+
+impl Pagination {
+    /// How many rows to fetch to fill a page and see if there is another one
+    pub fn fetch_limit(&self) -> Option<i32> {
+        self.limit.map(|limit| limit.saturating_add(1))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -61,6 +148,17 @@ mod tests {
         assert_eq!(expected, pagination);
     }
 
+    #[test]
+    fn example_query() {
+        let mut params = HashMap::new();
+        params.insert("limit".to_string(), "1".to_string());
+        params.insert("offset".to_string(), "10".to_string());
+
+        let pagination = extract_pagination(&params).unwrap();
+        assert_eq!(Some(1), pagination.limit);
+        assert_eq!(10, pagination.offset);
+    }
+
     #[test]
     fn missing_offset_parameter() {
         let mut params = HashMap::new();
@@ -91,6 +189,56 @@ mod tests {
         assert!(matches!(pagination, Error::ParseError(_)));
     }
 
+    #[test]
+    fn cursor_round_trip() {
+        let cursor = Cursor {
+            key: CursorKey::Rank(0.25),
+            id: 7,
+        };
+
+        assert_eq!(Some(cursor.clone()), Cursor::decode(&cursor.encode()));
+        assert_eq!(None, Cursor::decode("not a cursor"));
+    }
+
+    fn rows(ids: &[i32]) -> Vec<(i32, Cursor)> {
+        ids.iter()
+            .map(|id| {
+                let cursor = Cursor {
+                    key: CursorKey::Rank(0.0),
+                    id: *id,
+                };
+                (*id, cursor)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn first_page_links_to_next_only() {
+        let pagination = Pagination {
+            limit: Some(2),
+            offset: 0,
+        };
+        let page = Page::from_rows(rows(&[1, 2, 3]), &pagination, None, None);
+
+        assert_eq!(vec![1, 2], page.items);
+        assert_eq!(Some(2), page.next.map(|c| c.id));
+        assert_eq!(None, page.prev);
+    }
+
+    #[test]
+    fn backwards_page_is_put_back_in_order() {
+        let pagination = Pagination {
+            limit: Some(2),
+            offset: 0,
+        };
+        let before = PageCursor::Before(rows(&[4]).remove(0).1);
+        let page = Page::from_rows(rows(&[3, 2, 1]), &pagination, Some(&before), None);
+
+        assert_eq!(vec![2, 3], page.items);
+        assert_eq!(Some(3), page.next.map(|c| c.id));
+        assert_eq!(Some(2), page.prev.map(|c| c.id));
+    }
+
     #[test]
     fn wrong_limit_type() {
         let mut params = HashMap::new();
@@ -0,0 +1,138 @@
+use chrono::NaiveDateTime;
+use handle_errors::Error;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use crate::types::{AccountId, Answer, Question};
+
+pub const DISPLAY_NAME_MAX_LENGTH: usize = 50;
+pub const BIO_MAX_LENGTH: usize = 1000;
+pub const AVATAR_URL_MAX_LENGTH: usize = 2048;
+
+/// How many of their latest questions and answers a user's page lists
+pub const USER_PAGE_POST_LIMIT: i64 = 20;
+
+/// What everybody gets to see of an account. The email stays private.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Profile {
+    pub id: AccountId,
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub avatar_url: Option<String>,
+    pub created_on: NaiveDateTime,
+}
+
+/// Body of `PATCH /account`. Fields left out are kept as they are, blank
+/// ones are cleared.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+pub struct ProfileUpdate {
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub avatar_url: Option<String>,
+}
+
+impl ProfileUpdate {
+    /// Trims the fields, leaving blank ones empty. Fails with
+    /// `Error::InvalidContent` if one is too long, the display name has
+    /// line breaks or the avatar URL is not an http(s) URL.
+    pub fn normalize(self) -> Result<Self, Error> {
+        let trim = |field: Option<String>| field.map(|value| value.trim().to_string());
+        let update = ProfileUpdate {
+            display_name: trim(self.display_name),
+            bio: trim(self.bio),
+            avatar_url: trim(self.avatar_url),
+        };
+
+        check_length(
+            "display names",
+            &update.display_name,
+            DISPLAY_NAME_MAX_LENGTH,
+        )?;
+        check_length("bios", &update.bio, BIO_MAX_LENGTH)?;
+        check_length("avatar URLs", &update.avatar_url, AVATAR_URL_MAX_LENGTH)?;
+
+        if let Some(display_name) = &update.display_name {
+            if display_name.chars().any(char::is_control) {
+                return Err(Error::InvalidContent(
+                    "display names cannot contain line breaks or control characters".to_string(),
+                ));
+            }
+        }
+        if let Some(avatar_url) = update.avatar_url.as_deref().filter(|url| !url.is_empty()) {
+            // Anything else could run script or leak files where it is shown
+            let valid = Url::parse(avatar_url)
+                .is_ok_and(|url| matches!(url.scheme(), "http" | "https") && url.has_host());
+            if !valid {
+                return Err(Error::InvalidContent(
+                    "avatar URLs must be http or https URLs".to_string(),
+                ));
+            }
+        }
+
+        Ok(update)
+    }
+}
+
+fn check_length(field: &str, value: &Option<String>, max_length: usize) -> Result<(), Error> {
+    match value.as_ref().map(|value| value.chars().count()) {
+        Some(length) if length > max_length => Err(Error::InvalidContent(format!(
+            "{} must be at most {} characters, got {}",
+            field, max_length, length
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// A user's profile with their latest questions and answers, returned
+/// by `GET /users/{id}`
+#[derive(Debug, Serialize, Clone)]
+pub struct UserPage {
+    #[serde(flatten)]
+    pub profile: Profile,
+    pub questions: Vec<Question>,
+    pub answers: Vec<Answer>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trims_and_checks_updates() {
+        let update = ProfileUpdate {
+            display_name: Some("  Ada  ".to_string()),
+            bio: Some("   ".to_string()),
+            avatar_url: None,
+        };
+        assert_eq!(
+            ProfileUpdate {
+                display_name: Some("Ada".to_string()),
+                bio: Some(String::new()),
+                avatar_url: None,
+            },
+            update.normalize().unwrap()
+        );
+
+        for update in [
+            ProfileUpdate {
+                display_name: Some("x".repeat(DISPLAY_NAME_MAX_LENGTH + 1)),
+                ..Default::default()
+            },
+            ProfileUpdate {
+                display_name: Some("Ada\nLovelace".to_string()),
+                ..Default::default()
+            },
+            ProfileUpdate {
+                avatar_url: Some("javascript:alert(1)".to_string()),
+                ..Default::default()
+            },
+            ProfileUpdate {
+                avatar_url: Some("/avatars/ada.png".to_string()),
+                ..Default::default()
+            },
+        ] {
+            let err = update.clone().normalize().unwrap_err();
+            assert!(matches!(err, Error::InvalidContent(_)), "{:?}", update);
+        }
+    }
+}
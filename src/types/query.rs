@@ -0,0 +1,326 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use handle_errors::Error;
+use std::collections::HashMap;
+
+use crate::types::{
+    extract_pagination, normalize_tag, AccountId, Cursor, CursorKey, PageCursor, Pagination,
+};
+
+/// Orders in which the questions list can be returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuestionSort {
+    /// Most recently asked first
+    Newest,
+    /// Oldest first
+    Oldest,
+    /// Most recently asked or answered first
+    Activity,
+    /// Best full-text search match first, only valid with a search
+    Relevance,
+    /// Highest voted first
+    Score,
+}
+
+impl QuestionSort {
+    pub fn is_descending(&self) -> bool {
+        !matches!(self, QuestionSort::Oldest)
+    }
+
+    /// Whether a cursor was handed out for a list in this order
+    fn accepts(&self, key: &CursorKey) -> bool {
+        matches!(
+            (self, key),
+            (
+                QuestionSort::Newest | QuestionSort::Oldest,
+                CursorKey::Created(_)
+            ) | (QuestionSort::Activity, CursorKey::Activity(_))
+                | (QuestionSort::Relevance, CursorKey::Rank(_))
+                | (QuestionSort::Score, CursorKey::Score(_))
+        )
+    }
+}
+
+/// Filters, sort order and pagination for the `/questions` route,
+/// extracted from query params
+#[derive(Debug, Default, PartialEq)]
+pub struct QuestionQuery {
+    /// Full-text search terms
+    pub search: Option<String>,
+    pub tag: Option<String>,
+    pub author: Option<AccountId>,
+    pub created_after: Option<NaiveDateTime>,
+    pub created_before: Option<NaiveDateTime>,
+    /// Only questions without any answer
+    pub unanswered: bool,
+    /// Only questions with (`true`) or without (`false`) an accepted answer
+    pub answered: Option<bool>,
+    /// `None` sorts search results by relevance and everything else oldest first
+    pub sort: Option<QuestionSort>,
+    pub pagination: Pagination,
+    /// Keyset pagination, continuing from a row of a previous page
+    pub cursor: Option<PageCursor>,
+    /// Also count the matching questions across all pages
+    pub count: bool,
+}
+
+impl QuestionQuery {
+    pub fn effective_sort(&self) -> QuestionSort {
+        match (self.sort, &self.search) {
+            (Some(sort), _) => sort,
+            (None, Some(_)) => QuestionSort::Relevance,
+            (None, None) => QuestionSort::Oldest,
+        }
+    }
+}
+
+/// Extract query parameters from the `/questions` route
+/// # Example query
+/// `/questions?tag=rust&unanswered=true&sort=votes&limit=10&after=eyJrIjp7...`
+///
+/// Pages can be requested either with `limit` and `offset`, or with
+/// `limit` and one of the `after`/`before` cursors handed out in the
+/// `Link` header of a previous page.
+///
+/// Unknown parameters are rejected so that typos don't silently
+/// return unfiltered results.
+pub fn extract_question_query(params: &HashMap<String, String>) -> Result<QuestionQuery, Error> {
+    let mut query = QuestionQuery::default();
+
+    for (key, value) in params {
+        match key.as_str() {
+            "q" => query.search = Some(value.clone()),
+            "tag" => {
+                let tag = normalize_tag(value)
+                    .map_err(|_| Error::InvalidParameter(format!("invalid tag `{}`", value)))?;
+                query.tag = Some(tag);
+            }
+            "author" => {
+                query.author = Some(AccountId(value.parse().map_err(Error::ParseError)?));
+            }
+            "created_after" => query.created_after = Some(parse_datetime(key, value)?),
+            "created_before" => query.created_before = Some(parse_datetime(key, value)?),
+            "unanswered" => query.unanswered = parse_bool(key, value)?,
+            "answered" => query.answered = Some(parse_bool(key, value)?),
+            "sort" => query.sort = Some(parse_sort(value)?),
+            "after" => query.cursor = Some(PageCursor::After(parse_cursor(key, value)?)),
+            "before" => query.cursor = Some(PageCursor::Before(parse_cursor(key, value)?)),
+            "count" => query.count = parse_bool(key, value)?,
+            // Handled below
+            "limit" | "offset" => {}
+            _ => {
+                return Err(Error::InvalidParameter(format!(
+                    "unknown parameter `{}`",
+                    key
+                )))
+            }
+        }
+    }
+
+    if params.contains_key("after") && params.contains_key("before") {
+        return Err(Error::InvalidParameter(
+            "`after` and `before` cannot be combined".to_string(),
+        ));
+    }
+
+    if params.contains_key("offset") {
+        if query.cursor.is_some() {
+            return Err(Error::InvalidParameter(
+                "`offset` cannot be combined with a cursor".to_string(),
+            ));
+        }
+        query.pagination = extract_pagination(params)?;
+    } else if let Some(limit) = params.get("limit") {
+        query.pagination.limit = Some(limit.parse().map_err(Error::ParseError)?);
+    }
+
+    if query.sort == Some(QuestionSort::Relevance) && query.search.is_none() {
+        return Err(Error::InvalidParameter(
+            "`sort=relevance` needs a search `q`".to_string(),
+        ));
+    }
+
+    if let Some(PageCursor::After(cursor) | PageCursor::Before(cursor)) = &query.cursor {
+        if !query.effective_sort().accepts(&cursor.key) {
+            return Err(Error::InvalidParameter(
+                "cursor belongs to a different sort order".to_string(),
+            ));
+        }
+    }
+
+    Ok(query)
+}
+
+fn parse_cursor(key: &str, value: &str) -> Result<Cursor, Error> {
+    Cursor::decode(value)
+        .ok_or_else(|| Error::InvalidParameter(format!("`{}` is not a valid cursor", key)))
+}
+
+fn parse_sort(value: &str) -> Result<QuestionSort, Error> {
+    match value {
+        "newest" => Ok(QuestionSort::Newest),
+        "oldest" => Ok(QuestionSort::Oldest),
+        "activity" => Ok(QuestionSort::Activity),
+        "relevance" => Ok(QuestionSort::Relevance),
+        // `score` is accepted as an alias of `votes`
+        "votes" | "score" => Ok(QuestionSort::Score),
+        _ => Err(Error::InvalidParameter(format!(
+            "`sort` must be one of newest, oldest, votes, activity, relevance, got `{}`",
+            value
+        ))),
+    }
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool, Error> {
+    value.parse().map_err(|_| {
+        Error::InvalidParameter(format!("`{}` must be true or false, got `{}`", key, value))
+    })
+}
+
+/// Accepts either an RFC 3339 timestamp or a plain `YYYY-MM-DD` date
+fn parse_datetime(key: &str, value: &str) -> Result<NaiveDateTime, Error> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Ok(datetime.naive_utc());
+    }
+
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|date| date.and_time(Default::default()))
+        .map_err(|_| {
+            Error::InvalidParameter(format!(
+                "`{}` must be a date or RFC 3339 timestamp, got `{}`",
+                key, value
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn empty_query() {
+        let query = extract_question_query(&HashMap::new()).unwrap();
+        assert_eq!(QuestionQuery::default(), query);
+    }
+
+    #[test]
+    fn valid_filters() {
+        let query = extract_question_query(&params(&[
+            ("tag", "rust"),
+            ("author", "3"),
+            ("created_after", "2024-10-01"),
+            ("created_before", "2024-10-02T12:00:00+02:00"),
+            ("unanswered", "true"),
+            ("answered", "false"),
+            ("sort", "activity"),
+            ("limit", "5"),
+            ("offset", "10"),
+        ]))
+        .unwrap();
+
+        let expected = QuestionQuery {
+            search: None,
+            tag: Some("rust".to_string()),
+            author: Some(AccountId(3)),
+            created_after: NaiveDate::from_ymd_opt(2024, 10, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0),
+            created_before: NaiveDate::from_ymd_opt(2024, 10, 2)
+                .unwrap()
+                .and_hms_opt(10, 0, 0),
+            unanswered: true,
+            answered: Some(false),
+            sort: Some(QuestionSort::Activity),
+            pagination: Pagination {
+                limit: Some(5),
+                offset: 10,
+            },
+            cursor: None,
+            count: false,
+        };
+        assert_eq!(expected, query);
+    }
+
+    #[test]
+    fn unknown_parameter() {
+        let err = extract_question_query(&params(&[("tags", "rust")])).unwrap_err();
+        assert!(matches!(err, Error::InvalidParameter(_)));
+    }
+
+    #[test]
+    fn invalid_sort() {
+        let err = extract_question_query(&params(&[("sort", "random")])).unwrap_err();
+        assert!(matches!(err, Error::InvalidParameter(_)));
+    }
+
+    #[test]
+    fn sort_by_votes() {
+        for value in ["votes", "score"] {
+            let query = extract_question_query(&params(&[("sort", value)])).unwrap();
+            assert_eq!(Some(QuestionSort::Score), query.sort);
+        }
+    }
+
+    #[test]
+    fn invalid_date() {
+        let err = extract_question_query(&params(&[("created_after", "yesterday")])).unwrap_err();
+        assert!(matches!(err, Error::InvalidParameter(_)));
+    }
+
+    #[test]
+    fn offset_needs_a_limit() {
+        let err = extract_question_query(&params(&[("offset", "1")])).unwrap_err();
+        assert!(matches!(err, Error::MissingParameters));
+    }
+
+    #[test]
+    fn cursor_pagination() {
+        let cursor = Cursor {
+            key: CursorKey::Activity(NaiveDate::from_ymd_opt(2024, 10, 1).unwrap().into()),
+            id: 4,
+        };
+        let query = extract_question_query(&params(&[
+            ("sort", "activity"),
+            ("limit", "10"),
+            ("before", &cursor.encode()),
+            ("count", "true"),
+        ]))
+        .unwrap();
+
+        assert_eq!(Some(10), query.pagination.limit);
+        assert_eq!(Some(PageCursor::Before(cursor)), query.cursor);
+        assert!(query.count);
+    }
+
+    #[test]
+    fn cursor_must_match_sort() {
+        let cursor = Cursor {
+            key: CursorKey::Rank(0.5),
+            id: 4,
+        };
+        let err = extract_question_query(&params(&[("after", &cursor.encode())])).unwrap_err();
+        assert!(matches!(err, Error::InvalidParameter(_)));
+    }
+
+    #[test]
+    fn cursor_and_offset_are_exclusive() {
+        let cursor = Cursor {
+            key: CursorKey::Rank(0.5),
+            id: 4,
+        };
+        let err = extract_question_query(&params(&[
+            ("q", "rust"),
+            ("limit", "1"),
+            ("offset", "1"),
+            ("after", &cursor.encode()),
+        ]))
+        .unwrap_err();
+        assert!(matches!(err, Error::InvalidParameter(_)));
+    }
+}
@@ -1,3 +1,6 @@
+use crate::types::{AccountId, Answer, AnswerId};
+
+use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
@@ -9,6 +12,19 @@ pub struct Question {
     pub title: String,
     pub content: String,
     pub tags: Option<Vec<String>>,
+    pub account_id: AccountId,
+    pub created_on: NaiveDateTime,
+    /// Sum of the up and down votes
+    pub score: i32,
+    /// The answer the owner of the question accepted, if any
+    pub accepted_answer_id: Option<AnswerId>,
+    /// Starts at 1 and goes up with every edit, so an edit based on
+    /// an outdated version can be refused
+    pub version: i32,
+    /// Set while the question is deleted but not purged yet
+    pub deleted_at: Option<NaiveDateTime>,
+    /// Locked questions can only be changed by moderators
+    pub locked: bool,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -17,3 +33,12 @@ pub struct NewQuestion {
     pub content: String,
     pub tags: Option<Vec<String>>,
 }
+
+/// A question together with all the answers posted to it,
+/// returned by `GET /questions/{id}`
+#[derive(Debug, Serialize, Clone)]
+pub struct QuestionWithAnswers {
+    #[serde(flatten)]
+    pub question: Question,
+    pub answers: Vec<Answer>,
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+use crate::types::{AccountId, NewQuestion, QuestionId};
+
+use chrono::NaiveDateTime;
+use handle_errors::Error;
+use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, TextDiff};
+
+/// What a question looked like before an edit. Revisions are numbered
+/// from 1 per question, the latest one holds the values the current
+/// version replaced.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct QuestionRevision {
+    pub revision: i32,
+    pub question_id: QuestionId,
+    pub title: String,
+    pub content: String,
+    pub tags: Option<Vec<String>>,
+    /// The account whose edit replaced these values
+    pub editor_id: AccountId,
+    pub edited_on: NaiveDateTime,
+}
+
+impl QuestionRevision {
+    pub fn snapshot(&self) -> NewQuestion {
+        NewQuestion {
+            title: self.title.clone(),
+            content: self.content.clone(),
+            tags: self.tags.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffOp {
+    Equal,
+    Insert,
+    Delete,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct DiffChange {
+    pub op: DiffOp,
+    pub text: String,
+}
+
+/// Changes between two versions of a question, returned by
+/// `GET /questions/{id}/revisions/diff`
+#[derive(Debug, Serialize, Clone)]
+pub struct RevisionDiff {
+    pub from: i32,
+    /// `None` compares against the current version
+    pub to: Option<i32>,
+    pub title: Vec<DiffChange>,
+    pub content: Vec<DiffChange>,
+    pub tags_added: Vec<String>,
+    pub tags_removed: Vec<String>,
+}
+
+impl RevisionDiff {
+    /// Titles are compared word by word, contents line by line
+    pub fn new(from: (i32, &NewQuestion), to: (Option<i32>, &NewQuestion)) -> Self {
+        let (from, old) = from;
+        let (to, new) = to;
+
+        let old_tags = old.tags.as_deref().unwrap_or_default();
+        let new_tags = new.tags.as_deref().unwrap_or_default();
+
+        Self {
+            from,
+            to,
+            title: changes(&TextDiff::from_words(&old.title, &new.title)),
+            content: changes(&TextDiff::from_lines(&old.content, &new.content)),
+            tags_added: new_tags
+                .iter()
+                .filter(|tag| !old_tags.contains(tag))
+                .cloned()
+                .collect(),
+            tags_removed: old_tags
+                .iter()
+                .filter(|tag| !new_tags.contains(tag))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+fn changes<'a>(diff: &TextDiff<'a, 'a, '_, str>) -> Vec<DiffChange> {
+    diff.iter_all_changes()
+        .map(|change| DiffChange {
+            op: match change.tag() {
+                ChangeTag::Equal => DiffOp::Equal,
+                ChangeTag::Insert => DiffOp::Insert,
+                ChangeTag::Delete => DiffOp::Delete,
+            },
+            text: change.value().to_string(),
+        })
+        .collect()
+}
+
+/// Extract the revisions to compare from `?from=1&to=2`,
+/// without `to` the diff goes up to the current version
+pub fn extract_diff_range(params: &HashMap<String, String>) -> Result<(i32, Option<i32>), Error> {
+    let from = params
+        .get("from")
+        .ok_or(Error::MissingParameters)?
+        .parse()
+        .map_err(Error::ParseError)?;
+    let to = params
+        .get("to")
+        .map(|to| to.parse().map_err(Error::ParseError))
+        .transpose()?;
+
+    Ok((from, to))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn question(title: &str, content: &str, tags: &[&str]) -> NewQuestion {
+        NewQuestion {
+            title: title.to_string(),
+            content: content.to_string(),
+            tags: Some(tags.iter().map(|tag| tag.to_string()).collect()),
+        }
+    }
+
+    #[test]
+    fn diffs_title_content_and_tags() {
+        let old = question("Borrow checker", "first\nsecond\n", &["rust", "lifetimes"]);
+        let new = question(
+            "Borrow checker error",
+            "first\nthird\n",
+            &["rust", "borrowck"],
+        );
+
+        let diff = RevisionDiff::new((1, &old), (None, &new));
+
+        let inserted: Vec<&str> = diff
+            .title
+            .iter()
+            .filter(|change| change.op == DiffOp::Insert)
+            .map(|change| change.text.as_str())
+            .collect();
+        assert_eq!(vec![" ", "error"], inserted);
+        assert_eq!(
+            vec![
+                (DiffOp::Equal, "first\n"),
+                (DiffOp::Delete, "second\n"),
+                (DiffOp::Insert, "third\n"),
+            ],
+            diff.content
+                .iter()
+                .map(|change| (change.op, change.text.as_str()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(vec!["borrowck"], diff.tags_added);
+        assert_eq!(vec!["lifetimes"], diff.tags_removed);
+    }
+
+    #[test]
+    fn diff_range_needs_a_start() {
+        let params = HashMap::from([("to".to_string(), "2".to_string())]);
+        assert!(matches!(
+            extract_diff_range(&params),
+            Err(Error::MissingParameters)
+        ));
+
+        let params = HashMap::from([("from".to_string(), "1".to_string())]);
+        assert_eq!((1, None), extract_diff_range(&params).unwrap());
+    }
+}
@@ -0,0 +1,23 @@
+use crate::types::{AnswerId, Question};
+
+use serde::{Deserialize, Serialize};
+
+/// A question matching a full-text search, with the matching words
+/// wrapped in `<b>` tags. The text of a snippet is HTML-escaped, so
+/// the snippet can be shown as HTML.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct QuestionSearchResult {
+    #[serde(flatten)]
+    pub question: Question,
+    pub rank: f32,
+    pub title_snippet: String,
+    pub content_snippet: String,
+    /// Answers to this question that matched the search
+    pub answer_snippets: Vec<AnswerSnippet>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AnswerSnippet {
+    pub answer_id: AnswerId,
+    pub snippet: String,
+}
@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+
+use handle_errors::Error;
+use serde::{Deserialize, Serialize};
+
+pub const TAG_MAX_LENGTH: usize = 35;
+pub const TAG_DESCRIPTION_MAX_LENGTH: usize = 500;
+pub const MAX_TAGS_PER_QUESTION: usize = 5;
+
+/// How many tags `GET /tags` returns unless asked for fewer
+pub const DEFAULT_TAG_LIMIT: i64 = 20;
+pub const MAX_TAG_LIMIT: i64 = 100;
+
+/// A canonical tag with the synonyms that redirect to it and the
+/// number of questions tagged with it
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Tag {
+    pub name: String,
+    pub description: Option<String>,
+    pub synonyms: Vec<String>,
+    pub count: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TagUpdate {
+    pub description: Option<String>,
+}
+
+impl TagUpdate {
+    /// Trims the description, treating a blank one as none. Fails with
+    /// `Error::InvalidContent` if it is longer than `TAG_DESCRIPTION_MAX_LENGTH`.
+    pub fn normalize(self) -> Result<Self, Error> {
+        let description = self
+            .description
+            .map(|description| description.trim().to_string())
+            .filter(|description| !description.is_empty());
+
+        if let Some(length) = description.as_ref().map(|d| d.chars().count()) {
+            if length > TAG_DESCRIPTION_MAX_LENGTH {
+                return Err(Error::InvalidContent(format!(
+                    "tag descriptions must be at most {} characters, got {}",
+                    TAG_DESCRIPTION_MAX_LENGTH, length
+                )));
+            }
+        }
+
+        Ok(TagUpdate { description })
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct NewSynonym {
+    pub name: String,
+}
+
+/// Lowercase a tag and join its words with dashes, so `" Async  Await"`
+/// becomes `"async-await"`. Fails with `Error::InvalidContent` unless the
+/// result is made of letters, digits, `+`, `.` and `-` and is at most
+/// `TAG_MAX_LENGTH` characters.
+pub fn normalize_tag(raw: &str) -> Result<String, Error> {
+    let tag = raw
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join("-")
+        .to_lowercase();
+
+    let length = tag.chars().count();
+    if length == 0 || length > TAG_MAX_LENGTH {
+        return Err(Error::InvalidContent(format!(
+            "tags must be between 1 and {} characters, got `{}`",
+            TAG_MAX_LENGTH, raw
+        )));
+    }
+    if !tag
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, '+' | '.' | '-'))
+    {
+        return Err(Error::InvalidContent(format!(
+            "tags may only contain letters, digits, `+`, `.` and `-`, got `{}`",
+            raw
+        )));
+    }
+
+    Ok(tag)
+}
+
+/// Normalize the tags of a question, dropping duplicates but keeping
+/// the order they were given in
+pub fn normalize_tags(tags: Option<Vec<String>>) -> Result<Option<Vec<String>>, Error> {
+    let Some(tags) = tags else {
+        return Ok(None);
+    };
+
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = normalize_tag(&tag)?;
+        if !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+
+    if normalized.len() > MAX_TAGS_PER_QUESTION {
+        return Err(Error::InvalidContent(format!(
+            "questions can have at most {} tags, got {}",
+            MAX_TAGS_PER_QUESTION,
+            normalized.len()
+        )));
+    }
+
+    Ok(Some(normalized))
+}
+
+/// Parameters of `GET /tags`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagQuery {
+    /// Only tags, or tags with a synonym, starting with this
+    pub prefix: Option<String>,
+    pub limit: i64,
+}
+
+impl Default for TagQuery {
+    fn default() -> Self {
+        TagQuery {
+            prefix: None,
+            limit: DEFAULT_TAG_LIMIT,
+        }
+    }
+}
+
+/// Extract query parameters from the `/tags` route
+/// # Example query
+/// `/tags?prefix=ru&limit=10`
+pub fn extract_tag_query(params: &HashMap<String, String>) -> Result<TagQuery, Error> {
+    let mut query = TagQuery::default();
+
+    for (key, value) in params {
+        match key.as_str() {
+            "prefix" => {
+                let prefix = normalize_tag(value)
+                    .map_err(|_| Error::InvalidParameter(format!("invalid prefix `{}`", value)))?;
+                query.prefix = Some(prefix);
+            }
+            "limit" => {
+                let limit: i64 = value.parse().map_err(Error::ParseError)?;
+                if !(1..=MAX_TAG_LIMIT).contains(&limit) {
+                    return Err(Error::InvalidParameter(format!(
+                        "`limit` must be between 1 and {}",
+                        MAX_TAG_LIMIT
+                    )));
+                }
+                query.limit = limit;
+            }
+            _ => {
+                return Err(Error::InvalidParameter(format!(
+                    "unknown parameter `{}`",
+                    key
+                )))
+            }
+        }
+    }
+
+    Ok(query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn normalizes_case_and_whitespace() {
+        assert_eq!("async-await", normalize_tag(" Async  Await\t").unwrap());
+        assert_eq!("c++", normalize_tag("C++").unwrap());
+        assert_eq!(".net", normalize_tag(".NET").unwrap());
+    }
+
+    #[test]
+    fn rejects_invalid_tags() {
+        for raw in ["", "   ", "c#", "a/b", &"x".repeat(TAG_MAX_LENGTH + 1)] {
+            let err = normalize_tag(raw).unwrap_err();
+            assert!(matches!(err, Error::InvalidContent(_)), "{:?}", raw);
+        }
+    }
+
+    #[test]
+    fn drops_duplicate_tags_in_order() {
+        let tags = vec!["Rust".to_string(), "web".to_string(), "rust ".to_string()];
+        assert_eq!(
+            Some(vec!["rust".to_string(), "web".to_string()]),
+            normalize_tags(Some(tags)).unwrap()
+        );
+        assert_eq!(None, normalize_tags(None).unwrap());
+
+        let too_many = (0..=MAX_TAGS_PER_QUESTION).map(|i| i.to_string()).collect();
+        assert!(normalize_tags(Some(too_many)).is_err());
+    }
+
+    #[test]
+    fn blank_descriptions_are_cleared() {
+        let update = TagUpdate {
+            description: Some("  ".to_string()),
+        };
+        assert_eq!(None, update.normalize().unwrap().description);
+
+        let update = TagUpdate {
+            description: Some("x".repeat(TAG_DESCRIPTION_MAX_LENGTH + 1)),
+        };
+        assert!(update.normalize().is_err());
+    }
+
+    #[test]
+    fn extracts_tag_query() {
+        let query = extract_tag_query(&params(&[("prefix", "Ru"), ("limit", "5")])).unwrap();
+        assert_eq!(
+            TagQuery {
+                prefix: Some("ru".to_string()),
+                limit: 5,
+            },
+            query
+        );
+
+        assert!(extract_tag_query(&params(&[("limit", "0")])).is_err());
+        assert!(extract_tag_query(&params(&[("prefix", "a%")])).is_err());
+        assert!(extract_tag_query(&params(&[("name", "rust")])).is_err());
+    }
+}
@@ -0,0 +1,52 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::types::{AccountId, Scope};
+
+/// What `POST /login`, `POST /login/two-factor` and `POST /token/refresh`
+/// hand out
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TokenPair {
+    pub access_token: String,
+    /// Seconds until the access token expires
+    pub expires_in: i64,
+    pub refresh_token: String,
+    /// The space-separated scopes the access token is limited to, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+}
+
+/// Body of `POST /token/refresh` and `POST /logout`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+    /// Limits the new access token to some of the scopes of the refresh
+    /// token, which keeps all of its own. Only `POST /token/refresh`
+    /// looks at it.
+    #[serde(default)]
+    pub scope: Option<String>,
+}
+
+/// A refresh token as it is stored, by the hash of the token itself.
+/// Every token rotated out of the same login shares its `family`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RefreshToken {
+    pub token_hash: String,
+    pub family: String,
+    pub account_id: AccountId,
+    pub expires_on: NaiveDateTime,
+    /// Passed on to the access tokens it is traded for, see `Session`
+    pub two_factor: bool,
+    /// What the login was limited to, passed on like `two_factor`
+    pub scopes: Option<Vec<Scope>>,
+}
+
+/// A single-use token handed to an account, such as a password reset,
+/// an email verification or a login challenge, stored by the hash of
+/// the token
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountToken {
+    pub token_hash: String,
+    pub account_id: AccountId,
+    pub expires_on: NaiveDateTime,
+}
@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+
+use crate::types::AccountId;
+
+/// How many recovery codes an account gets when it turns on
+/// two-factor authentication
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// The TOTP secret of an account. It only guards logins once the
+/// account confirmed it with a code from its authenticator app.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TwoFactor {
+    pub account_id: AccountId,
+    /// Base32 encoded, the way authenticator apps take it
+    pub secret: String,
+    pub confirmed: bool,
+    /// The latest time step a code was accepted for. Codes for it or an
+    /// earlier step are not accepted again.
+    pub last_used_step: i64,
+}
+
+/// Answer to `POST /account/two-factor`, for setting up an
+/// authenticator app by hand or through a QR code of the URI
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TwoFactorEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+/// Body of `POST /account/two-factor/confirm` and
+/// `DELETE /account/two-factor`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TwoFactorCode {
+    pub code: String,
+}
+
+/// Answer to `POST /account/two-factor/confirm`, the only time the
+/// recovery codes are shown
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
+
+/// What `POST /login` hands out instead of tokens when the account has
+/// two-factor authentication on
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct LoginChallenge {
+    pub challenge_token: String,
+    /// Seconds until the challenge expires
+    pub expires_in: i64,
+}
+
+/// Body of `POST /login/two-factor`. The code is either a TOTP code or
+/// one of the account's recovery codes.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TwoFactorLogin {
+    pub challenge_token: String,
+    pub code: String,
+    /// Like `Login::scope`, given here as this is where tokens are issued
+    #[serde(default)]
+    pub scope: Option<String>,
+}
@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+
+/// An up or down vote on a question or answer, sent and stored as `1` or `-1`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "i16", into = "i16")]
+pub enum Vote {
+    Up,
+    Down,
+}
+
+impl TryFrom<i16> for Vote {
+    type Error = String;
+
+    fn try_from(value: i16) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Vote::Up),
+            -1 => Ok(Vote::Down),
+            _ => Err(format!("vote must be 1 or -1, got {}", value)),
+        }
+    }
+}
+
+impl From<Vote> for i16 {
+    fn from(vote: Vote) -> Self {
+        match vote {
+            Vote::Up => 1,
+            Vote::Down => -1,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewVote {
+    pub value: Vote,
+}
+
+/// Score of a question or answer right after the caller voted on it
+#[derive(Debug, Serialize)]
+pub struct VoteSummary {
+    pub score: i32,
+    pub vote: Option<Vote>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn votes_are_plus_or_minus_one() {
+        let vote: NewVote = serde_json::from_str(r#"{"value": -1}"#).unwrap();
+        assert_eq!(Vote::Down, vote.value);
+
+        assert!(serde_json::from_str::<NewVote>(r#"{"value": 2}"#).is_err());
+    }
+}